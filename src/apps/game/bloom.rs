@@ -0,0 +1,332 @@
+use super::memory::texture_bytes;
+use super::render_targets::{RenderTargets, COLOR_FORMAT};
+use super::wgpu::{self, util::DeviceExt, BindGroup, BindGroupLayout, Buffer, Device, Queue, TextureView};
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct BloomSettings {
+    /// brightness (largest color channel) above which pixels start to glow
+    pub threshold: f32,
+    /// how strongly the glow is added back, 0 (the default) adds nothing
+    pub intensity: f32,
+    /// standard deviation of the blur in half resolution pixels
+    pub radius: f32,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    _pad: f32,
+}
+
+impl BloomSettings {
+    pub fn new(threshold: f32, intensity: f32, radius: f32) -> Self {
+        Self {
+            threshold,
+            intensity,
+            radius,
+            _pad: 0.0,
+        }
+    }
+}
+
+impl Default for BloomSettings {
+    fn default() -> Self {
+        Self::new(1.0, 0.0, 4.0)
+    }
+}
+
+/// extracts what's brighter than the threshold into half resolution targets, blurs it
+/// horizontally and vertically and adds it back onto the color, in place
+///
+/// meant for emissive primitives, the color targets are hdr so they can exceed the threshold
+pub(crate) struct BloomPass {
+    extract_pipeline: wgpu::RenderPipeline,
+    blur_horizontal_pipeline: wgpu::RenderPipeline,
+    blur_vertical_pipeline: wgpu::RenderPipeline,
+    composite_pipeline: wgpu::RenderPipeline,
+    bind_group_layout: BindGroupLayout,
+    sampler: wgpu::Sampler,
+    settings_buffer: Buffer,
+    /// half resolution, the extracted and finally the blurred glow
+    bright: TextureView,
+    /// half resolution, between the two blur directions
+    scratch: TextureView,
+    /// of `bright` and `scratch`
+    half_size: (u32, u32),
+    /// extracting from `RenderTargets::color` and `RenderTargets::post`
+    from_color: BindGroup,
+    from_post: BindGroup,
+    from_bright: BindGroup,
+    from_scratch: BindGroup,
+}
+
+impl BloomPass {
+    pub fn new(device: &Device, targets: &RenderTargets) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("bloom_bind_group_layout"),
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Bloom Sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let settings_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Bloom Settings Buffer"),
+            contents: bytemuck::bytes_of(&BloomSettings::default()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Bloom Shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                concat!(
+                    include_str!("level/fullscreen.wgsl"),
+                    include_str!("level/bloom_shader.wgsl")
+                )
+                .into(),
+            ),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Bloom Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let mk_pipeline = |label, entry_point, blend| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader,
+                    entry_point: "vs_main",
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader,
+                    entry_point,
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: COLOR_FORMAT,
+                        blend,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+        };
+        // adds the glow to the color and keeps its alpha
+        let additive = wgpu::BlendState {
+            color: wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::One,
+                dst_factor: wgpu::BlendFactor::One,
+                operation: wgpu::BlendOperation::Add,
+            },
+            alpha: wgpu::BlendComponent {
+                src_factor: wgpu::BlendFactor::Zero,
+                dst_factor: wgpu::BlendFactor::One,
+                operation: wgpu::BlendOperation::Add,
+            },
+        };
+        let extract_pipeline = mk_pipeline("Bloom Extract Pipeline", "fs_extract", None);
+        let blur_horizontal_pipeline =
+            mk_pipeline("Bloom Horizontal Blur Pipeline", "fs_blur_horizontal", None);
+        let blur_vertical_pipeline =
+            mk_pipeline("Bloom Vertical Blur Pipeline", "fs_blur_vertical", None);
+        let composite_pipeline =
+            mk_pipeline("Bloom Composite Pipeline", "fs_composite", Some(additive));
+
+        let resources = Resources::new(device, &bind_group_layout, &sampler, &settings_buffer, targets);
+        Self {
+            extract_pipeline,
+            blur_horizontal_pipeline,
+            blur_vertical_pipeline,
+            composite_pipeline,
+            bind_group_layout,
+            sampler,
+            settings_buffer,
+            bright: resources.bright,
+            scratch: resources.scratch,
+            half_size: resources.half_size,
+            from_color: resources.from_color,
+            from_post: resources.from_post,
+            from_bright: resources.from_bright,
+            from_scratch: resources.from_scratch,
+        }
+    }
+
+    /// the settings and both half resolution targets, in bytes
+    pub fn memory_usage(&self) -> u64 {
+        self.settings_buffer.size() + 2 * texture_bytes(self.half_size, COLOR_FORMAT)
+    }
+
+    /// has to be called whenever the targets were recreated
+    pub fn rebind(&mut self, device: &Device, targets: &RenderTargets) {
+        let resources = Resources::new(
+            device,
+            &self.bind_group_layout,
+            &self.sampler,
+            &self.settings_buffer,
+            targets,
+        );
+        self.bright = resources.bright;
+        self.scratch = resources.scratch;
+        self.half_size = resources.half_size;
+        self.from_color = resources.from_color;
+        self.from_post = resources.from_post;
+        self.from_bright = resources.from_bright;
+        self.from_scratch = resources.from_scratch;
+    }
+
+    /// adds the glow onto `RenderTargets::post` if `after_post` (another pass ran before),
+    /// `RenderTargets::color` otherwise. nothing happens at an intensity of 0
+    pub fn run(
+        &self,
+        queue: &Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        targets: &RenderTargets,
+        settings: &BloomSettings,
+        after_post: bool,
+    ) {
+        if settings.intensity <= 0.0 {
+            return;
+        }
+        crate::trace_upload!("bloom settings", 0, std::mem::size_of::<BloomSettings>());
+        queue.write_buffer(&self.settings_buffer, 0, bytemuck::bytes_of(settings));
+        let (source, target) = if after_post {
+            (&self.from_post, &targets.post)
+        } else {
+            (&self.from_color, &targets.color)
+        };
+        let steps = [
+            ("Bloom Extract Pass", &self.extract_pipeline, source, &self.bright, true),
+            ("Bloom Horizontal Blur Pass", &self.blur_horizontal_pipeline, &self.from_bright, &self.scratch, true),
+            ("Bloom Vertical Blur Pass", &self.blur_vertical_pipeline, &self.from_scratch, &self.bright, true),
+            ("Bloom Composite Pass", &self.composite_pipeline, &self.from_bright, target, false),
+        ];
+        for (label, pipeline, input, output, clear) in steps {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some(label),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: output,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: if clear {
+                            wgpu::LoadOp::Clear(wgpu::Color::BLACK)
+                        } else {
+                            wgpu::LoadOp::Load
+                        },
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, input, &[]);
+            pass.draw(0..3, 0..1);
+        }
+    }
+}
+
+/// everything depending on the size of the targets
+struct Resources {
+    bright: TextureView,
+    scratch: TextureView,
+    half_size: (u32, u32),
+    from_color: BindGroup,
+    from_post: BindGroup,
+    from_bright: BindGroup,
+    from_scratch: BindGroup,
+}
+
+impl Resources {
+    fn new(
+        device: &Device,
+        layout: &BindGroupLayout,
+        sampler: &wgpu::Sampler,
+        settings_buffer: &Buffer,
+        targets: &RenderTargets,
+    ) -> Self {
+        let (width, height) = targets.size();
+        let half_size = ((width / 2).max(1), (height / 2).max(1));
+        let mk_target = |label| {
+            device
+                .create_texture(&wgpu::TextureDescriptor {
+                    label: Some(label),
+                    size: wgpu::Extent3d {
+                        width: half_size.0,
+                        height: half_size.1,
+                        depth_or_array_layers: 1,
+                    },
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: COLOR_FORMAT,
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                        | wgpu::TextureUsages::TEXTURE_BINDING,
+                    view_formats: &[],
+                })
+                .create_view(&wgpu::TextureViewDescriptor::default())
+        };
+        let mk_bind_group = |source: &TextureView| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(source),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: settings_buffer.as_entire_binding(),
+                    },
+                ],
+                label: Some("bloom_bind_group"),
+            })
+        };
+        let bright = mk_target("Bloom Bright Target");
+        let scratch = mk_target("Bloom Scratch Target");
+        Self {
+            from_color: mk_bind_group(&targets.color),
+            from_post: mk_bind_group(&targets.post),
+            from_bright: mk_bind_group(&bright),
+            from_scratch: mk_bind_group(&scratch),
+            bright,
+            scratch,
+            half_size,
+        }
+    }
+}
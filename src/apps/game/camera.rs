@@ -1,4 +1,7 @@
 use cgmath::*;
+use super::primitives::SDFPrimitive;
+use super::quality::DEFAULT_MAX_STEPS;
+use super::viewports::ViewRect;
 use super::wgpu::{self, Device, Queue};
 use super::wgpu::util::DeviceExt;
 use std::f32::consts::FRAC_PI_2;
@@ -6,6 +9,10 @@ use std::time::Duration;
 // use winit::dpi::{PhysicalSize};
 // use winit::event::*;
 
+/// cgmath's `perspective` and `ortho` follow opengl and map the depth to -1..1, wgpu's clip
+/// space has it in 0..1 (x and y are the same in both). `Projection::calc_matrix` always
+/// applies this, so `world_to_screen` and its inverse `screen_to_world` are in wgpu's convention,
+/// transforms coming from opengl tools need it applied (or removed) once, not again
 #[rustfmt::skip]
 pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
     1.0, 0.0, 0.0, 0.0,
@@ -16,6 +23,25 @@ pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
 
 const SAFE_FRAC_PI_2: f32 = FRAC_PI_2 - 0.0001;
 
+// I'm assuming a line is about 100 pixels
+const PIXELS_PER_LINE: f32 = 100.0;
+
+/// mirrors winit's `MouseScrollDelta`, mouse wheels usually report lines, trackpads pixels
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum ScrollDelta {
+    Lines(f32),
+    Pixels(f32),
+}
+
+/// the controllers dolly/zoom in for positive values
+fn scroll_lines(delta: &ScrollDelta) -> f32 {
+    match delta {
+        ScrollDelta::Lines(scroll) => -scroll,
+        ScrollDelta::Pixels(scroll) => -scroll / PIXELS_PER_LINE,
+    }
+}
+
 
 #[repr(u32)]
 #[derive(Debug, Copy, Clone, PartialEq)] //, bytemuck::Pod, bytemuck::Zeroable)]
@@ -46,11 +72,51 @@ impl Default for Effect {
     }
 }
 
+/// which world axis points up, Z-up matches tools like Blender
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum WorldUp {
+    #[default]
+    Y,
+    Z,
+}
+
+impl WorldUp {
+    pub fn vector(self) -> Vector3<f32> {
+        match self {
+            WorldUp::Y => Vector3::unit_y(),
+            WorldUp::Z => Vector3::unit_z(),
+        }
+    }
+
+    /// the unit vector for a yaw around the up axis (0 looks along +x)
+    /// and a pitch above the ground plane
+    pub fn direction(self, yaw: Rad<f32>, pitch: Rad<f32>) -> Vector3<f32> {
+        let (sin_pitch, cos_pitch) = pitch.0.sin_cos();
+        let (sin_yaw, cos_yaw) = yaw.0.sin_cos();
+        match self {
+            WorldUp::Y => Vector3::new(cos_pitch * cos_yaw, sin_pitch, cos_pitch * sin_yaw),
+            WorldUp::Z => Vector3::new(cos_pitch * cos_yaw, cos_pitch * sin_yaw, sin_pitch),
+        }
+    }
+
+    /// inverse of `direction`, `direction` has to be normalized
+    fn yaw_pitch(self, direction: Vector3<f32>) -> (Rad<f32>, Rad<f32>) {
+        let (side, up) = match self {
+            WorldUp::Y => (direction.z, direction.y),
+            WorldUp::Z => (direction.y, direction.z),
+        };
+        // rounding can push a vertical direction just past 1
+        (Rad(side.atan2(direction.x)), Rad(up.clamp(-1.0, 1.0).asin()))
+    }
+}
+
 #[derive(Debug)]
 pub struct Camera {
     pub position: Point3<f32>,
     yaw: Rad<f32>,
     pitch: Rad<f32>,
+    /// the controllers move along this too, so vertical movement stays consistent with the view
+    pub up: WorldUp,
 }
 
 impl Camera {
@@ -63,54 +129,303 @@ impl Camera {
             position: position.into(),
             yaw: yaw.into(),
             pitch: pitch.into(),
+            up: WorldUp::default(),
         }
     }
 
+    /// world to view space, right-handed: the camera looks down -z with +y up
     pub fn calc_matrix(&self) -> Matrix4<f32> {
-        let (sin_pitch, cos_pitch) = self.pitch.0.sin_cos();
-        let (sin_yaw, cos_yaw) = self.yaw.0.sin_cos();
+        Matrix4::look_to_rh(self.position, self.forward(), self.up.vector())
+    }
 
-        Matrix4::look_to_rh(
-            self.position,
-            Vector3::new(cos_pitch * cos_yaw, sin_pitch, cos_pitch * sin_yaw).normalize(),
-            Vector3::unit_y(),
-        )
+    /// view to world space, always derived from `calc_matrix` so the two can't disagree
+    /// about the orientation
+    pub fn calc_inverse_matrix(&self) -> Matrix4<f32> {
+        self.calc_matrix()
+            .invert()
+            .expect("a look-to matrix is a rotation and a translation")
+    }
+
+    /// the direction the camera looks in
+    pub fn forward(&self) -> Vector3<f32> {
+        self.up.direction(self.yaw, self.pitch).normalize()
+    }
+
+    /// at `position` facing `target`, with the default up axis
+    pub fn looking_at<V: Into<Point3<f32>>>(position: V, target: Point3<f32>) -> Self {
+        let mut camera = Self::new(position, Rad(0.0), Rad(0.0));
+        camera.look_at(target);
+        camera
+    }
+
+    /// turns the camera toward `target` without moving it, straight up or down stops at the
+    /// same pitch limit as the controllers. a `target` at the camera's position changes nothing
+    pub fn look_at(&mut self, target: Point3<f32>) {
+        let offset = target - self.position;
+        if offset.magnitude2() == 0.0 {
+            return;
+        }
+        let (yaw, pitch) = self.up.yaw_pitch(offset.normalize());
+        self.yaw = yaw;
+        self.pitch = Rad(pitch.0.clamp(-SAFE_FRAC_PI_2, SAFE_FRAC_PI_2));
     }
 }
 
+/// clip to world space, the inverse of the projection times `Camera::calc_matrix`. the
+/// uniform's `screen_to_world` and the cpu side rays (`RenderCamera::screen_ray`) share it
+pub(crate) fn screen_to_world(camera: &Camera, projection: &Projection) -> Matrix4<f32> {
+    camera.calc_inverse_matrix() * projection.calc_matrix().invert().unwrap()
+}
+
+/// how the view is projected onto the screen
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ProjectionKind {
+    Perspective,
+    /// parallel rays, `height` is the world space extent along the screen's y axis
+    /// that `fovy` would span in perspective mode
+    Orthographic { height: f32 },
+}
+
+impl ProjectionKind {
+    /// discriminant the shaders branch on, see `camera_ray` in common.wgsl
+    fn shader_index(&self) -> u32 {
+        match self {
+            ProjectionKind::Perspective => 0,
+            ProjectionKind::Orthographic { .. } => 1,
+        }
+    }
+}
+
+/// what the level shows instead of its shading, to debug the marcher
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum DebugMode {
+    #[default]
+    Off,
+    /// how many steps each pixel's ray took, from blue (few) to red (the step limit)
+    StepCount,
+    /// how far each pixel's ray went, from black (near) to white (the view distance)
+    Distance,
+    /// the surface normal of the hit as rgb (`normal * 0.5 + 0.5`), black where nothing is hit
+    Normals,
+}
+
+impl DebugMode {
+    /// discriminant the main shader branches on, see `fs_main` in main_shader.wgsl
+    fn shader_index(&self) -> u32 {
+        match self {
+            DebugMode::Off => 0,
+            DebugMode::StepCount => 1,
+            DebugMode::Distance => 2,
+            DebugMode::Normals => 3,
+        }
+    }
+}
+
+/// the largest `zfar / znear` the auto clip planes allow, the depth precision of a
+/// perspective projection is spent mostly near the camera and vanishes beyond this
+pub const MAX_CLIP_RATIO: f32 = 100_000.0;
+
+#[derive(Debug, Clone)]
 pub struct Projection {
     pixels : (u32, u32),
+    /// top left of the viewport in the render targets, see `set_viewport`
+    origin: (u32, u32),
     pub fovy: Rad<f32>,
+    pub kind: ProjectionKind,
     znear: f32,
     zfar: f32,
+    /// the planes it was created with, the least `fit_clip` extends them to
+    base_clip: (f32, f32),
+    /// see `set_auto_clip`
+    auto_clip: bool,
+    auto_clip_scales_near: bool,
 }
 
 impl Projection {
     pub fn new<F: Into<Rad<f32>>>(width: u32, height: u32, fovy: F, znear: f32, zfar: f32) -> Self {
         Self {
             pixels: (width, height),
+            origin: (0, 0),
             fovy: fovy.into(),
+            kind: ProjectionKind::Perspective,
             znear,
             zfar,
+            base_clip: (znear, zfar),
+            auto_clip: false,
+            auto_clip_scales_near: false,
+        }
+    }
+
+    /// the current near and far plane
+    pub fn clip_planes(&self) -> (f32, f32) {
+        (self.znear, self.zfar)
+    }
+
+    /// lets `RenderCamera::update` push the far plane out far enough to keep the scene's
+    /// bounds in view, never closer than the far plane it was created with. turning it off
+    /// goes back to the original planes
+    pub fn set_auto_clip(&mut self, enabled: bool) {
+        self.auto_clip = enabled;
+        if !enabled {
+            (self.znear, self.zfar) = self.base_clip;
         }
     }
 
+    pub fn auto_clip(&self) -> bool {
+        self.auto_clip
+    }
+
+    /// whether the auto clip moves the near plane out by the same factor as the far one,
+    /// which keeps the depth precision but clips close surfaces sooner
+    pub fn set_auto_clip_scales_near(&mut self, scales_near: bool) {
+        self.auto_clip_scales_near = scales_near;
+    }
+
+    /// with the auto clip on, moves the far plane to the far side of `bounds` (a bounding
+    /// sphere's center and radius) seen from `eye`. the near plane follows far enough to keep
+    /// the ratio within `MAX_CLIP_RATIO`
+    pub(crate) fn fit_clip(&mut self, eye: Point3<f32>, bounds: Option<(Point3<f32>, f32)>) {
+        if !self.auto_clip {
+            return;
+        }
+        let (base_near, base_far) = self.base_clip;
+        let reach = bounds.map_or(0.0, |(center, radius)| (center - eye).magnitude() + radius);
+        // nan or infinite bounds keep the original planes
+        let zfar = if reach.is_finite() { reach.max(base_far) } else { base_far };
+        let znear = if self.auto_clip_scales_near {
+            base_near * zfar / base_far
+        } else {
+            base_near
+        };
+        self.zfar = zfar;
+        self.znear = znear.max(zfar / MAX_CLIP_RATIO);
+    }
+
     pub fn resize(&mut self, width: u32, height: u32) {
         self.pixels = (width, height);
     }
 
+    /// renders into `rect` of the render targets only, for split views.
+    /// `resize` keeps the origin, so a single view never has to call this
+    pub fn set_viewport(&mut self, rect: ViewRect) {
+        self.origin = rect.origin;
+        self.pixels = rect.size;
+    }
+
+    pub fn viewport(&self) -> ViewRect {
+        ViewRect {
+            origin: self.origin,
+            size: self.pixels,
+        }
+    }
+
+    /// the render resolution in physical pixels
+    pub fn dimensions(&self) -> (u32, u32) {
+        self.pixels
+    }
+
+    pub fn aspect(&self) -> f32 {
+        self.pixels.0 as f32 / self.pixels.1 as f32
+    }
+
+    /// view to clip space in wgpu's convention, a point between `znear` and `zfar` lands
+    /// at a depth of 0..1 after the perspective divide (see `OPENGL_TO_WGPU_MATRIX`)
     pub fn calc_matrix(&self) -> Matrix4<f32> {
-        let aspect = self.pixels.0 as f32 / self.pixels.1 as f32;
-        OPENGL_TO_WGPU_MATRIX * 
-        perspective(self.fovy, aspect, self.znear, self.zfar)
+        let aspect = self.aspect();
+        OPENGL_TO_WGPU_MATRIX * match self.kind {
+            ProjectionKind::Perspective => perspective(self.fovy, aspect, self.znear, self.zfar),
+            ProjectionKind::Orthographic { height } => {
+                let (half_width, half_height) = (0.5 * height * aspect, 0.5 * height);
+                ortho(-half_width, half_width, -half_height, half_height, self.znear, self.zfar)
+            }
+        }
+    }
+
+    /// how big a pixel of the viewport is in world units, in perspective at a distance
+    /// of 1 (it grows linearly with the distance), in orthographic mode anywhere
+    pub fn pixel_size(&self) -> f32 {
+        // only the central half of the ndc range is visible (see `get_pixel_normalization_matrix`)
+        let visible_height = match self.kind {
+            ProjectionKind::Perspective => (0.5 * self.fovy.0).tan(),
+            ProjectionKind::Orthographic { height } => 0.5 * height,
+        };
+        visible_height / self.pixels.1.max(1) as f32
+    }
+
+    /// how big a pixel of the viewport is in the rasterizer's ndc (-1..1 across the viewport),
+    /// `world_to_screen` only spans the central half of that, where pixels are half as big
+    pub fn pixel_size_ndc(&self) -> Vector2<f32> {
+        Vector2::new(2.0 / self.pixels.0.max(1) as f32, 2.0 / self.pixels.1.max(1) as f32)
+    }
+
+    /// the world space footprint of a pixel `depth` in front of the camera, the same
+    /// everywhere in orthographic mode
+    pub fn pixel_size_world_at(&self, depth: f32) -> f32 {
+        match self.kind {
+            ProjectionKind::Perspective => self.pixel_size() * depth,
+            ProjectionKind::Orthographic { .. } => self.pixel_size(),
+        }
+    }
+
+    /// the default zoom range of an orbit, from twice the near plane (so a surface right at
+    /// the target isn't clipped) to half the far plane (so what's behind it stays visible)
+    pub fn orbit_radius_limits(&self) -> (f32, f32) {
+        (2.0 * self.znear, 0.5 * self.zfar)
     }
 
     pub fn get_pixel_normalization_matrix(&self) -> Matrix4<f32> {
-        Matrix4::from_translation(Vector3::new(-0.5, 0.5, 0.0)) * Matrix4::from_nonuniform_scale(1.0/(self.pixels.0 as f32), -1.0/(self.pixels.1 as f32), 1.0) //* Matrix4::from_translation(Vector3::new(-0.5*(self.pixels.0 as f32), 0.5, 0.0))
+        Matrix4::from_translation(Vector3::new(-0.5, 0.5, 0.0)) * Matrix4::from_nonuniform_scale(1.0/(self.pixels.0 as f32), -1.0/(self.pixels.1 as f32), 1.0)
+            * Matrix4::from_translation(Vector3::new(-(self.origin.0 as f32), -(self.origin.1 as f32), 0.0)) //* Matrix4::from_translation(Vector3::new(-0.5*(self.pixels.0 as f32), 0.5, 0.0))
     }
 
 }
 
+/// how the camera's velocity approaches the one the held keys ask for while
+/// `CameraController::set_smoothing` is on
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum AccelCurve {
+    /// constant acceleration, reaches full speed after the smoothing time
+    Linear,
+    /// slow to start and to stop, fastest halfway
+    EaseInOut,
+    /// closes the same fraction of the gap every moment, quick start and soft stop
+    #[default]
+    Exponential,
+}
+
+/// a key of the free flying camera, see `CameraController::process_keyboard`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum MoveDirection {
+    Forward,
+    Backward,
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+/// what the up and down keys move the free flying camera along
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum VerticalMode {
+    /// the world's up axis (see `Camera::up`), whichever way the camera looks
+    #[default]
+    WorldUp,
+    /// the camera's own up, tilted with its pitch
+    CameraUp,
+}
+
+/// the slowest auto speed (see `CameraController::set_auto_speed`), so the camera can still
+/// leave the scene's center
+pub const MIN_AUTO_SPEED: f32 = 1.0;
+
+/// free flying camera controls
+///
+/// input model: `amount_*` are levels, set on key press/release and held until the next
+/// event, so key repeats are harmless. mouse motion and scroll are deltas, every event between
+/// two frames is summed up and `update_camera` consumes the sum, ending the frame
+/// (see `end_frame`). so call the `process_*` methods for every event, then `update_camera` once.
 #[derive(Debug)]
 pub struct CameraController {
     amount_left: f32,
@@ -121,9 +436,29 @@ pub struct CameraController {
     amount_down: f32,
     rotate_horizontal: f32,
     rotate_vertical: f32,
-    // scroll: f32,
+    scroll: f32,
+    /// the scroll (in lines) still dollying the camera after the wheel stopped, see `set_scroll_decay`
+    scroll_velocity: f32,
+    scroll_decay: f32,
     speed: f32,
     sensitivity: f32,
+    scroll_sensitivity: f32,
+    /// what the keys moved the camera with last frame, in units per second
+    velocity: Vector3<f32>,
+    /// seconds the velocity takes to follow the keys, 0 follows immediately
+    smoothing: f32,
+    accel_curve: AccelCurve,
+    /// pending pan in pixels, see `process_pan`
+    pan_horizontal: f32,
+    pan_vertical: f32,
+    /// how far in front of the camera the point is that panning keeps under the cursor
+    pan_distance: f32,
+    vertical_mode: VerticalMode,
+    /// see `set_auto_speed`
+    auto_speed: bool,
+    auto_speed_factor: f32,
+    /// from the camera to the scene's center, `RenderCamera` keeps it up to date
+    scene_distance: Option<f32>,
 }
 
 impl CameraController {
@@ -137,12 +472,151 @@ impl CameraController {
             amount_down: 0.0,
             rotate_horizontal: 0.0,
             rotate_vertical: 0.0,
-            // scroll: 0.0,
+            scroll: 0.0,
+            scroll_velocity: 0.0,
+            scroll_decay: 0.0,
             speed,
             sensitivity,
+            // matches the old `-scroll * 0.5` line delta dollied with the look sensitivity
+            scroll_sensitivity: 0.5 * sensitivity,
+            velocity: Vector3::zero(),
+            smoothing: 0.0,
+            accel_curve: AccelCurve::default(),
+            pan_horizontal: 0.0,
+            pan_vertical: 0.0,
+            pan_distance: 100.0,
+            vertical_mode: VerticalMode::default(),
+            auto_speed: false,
+            auto_speed_factor: 0.5,
+            scene_distance: None,
+        }
+    }
+
+    pub fn vertical_mode(&self) -> VerticalMode {
+        self.vertical_mode
+    }
+
+    pub fn set_vertical_mode(&mut self, vertical_mode: VerticalMode) {
+        self.vertical_mode = vertical_mode;
+    }
+
+    pub fn pan_distance(&self) -> f32 {
+        self.pan_distance
+    }
+
+    /// there's no focus in free flight, so panning moves as if there was one this far ahead
+    pub fn set_pan_distance(&mut self, pan_distance: f32) {
+        self.pan_distance = pan_distance.max(0.0);
+    }
+
+    /// what `set_speed` clamps to, in units per second
+    pub const SPEED_RANGE: std::ops::RangeInclusive<f32> = 0.0..=10_000.0;
+    /// what `set_sensitivity` clamps to
+    pub const SENSITIVITY_RANGE: std::ops::RangeInclusive<f32> = 0.01..=10.0;
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed.clamp(*Self::SPEED_RANGE.start(), *Self::SPEED_RANGE.end());
+    }
+
+    /// moves faster far away from the scene and slower up close: the speed becomes
+    /// `auto_speed_factor` times the distance to the center of the scene's bounding sphere,
+    /// clamped to `MIN_AUTO_SPEED` and the end of `SPEED_RANGE`. the level's sphere only holds
+    /// the primitives in play (see `PrimitiveManager::bounding_sphere`). without scene bounds
+    /// (see `RenderCamera::set_scene_bounds`) it stays at `speed`
+    pub fn set_auto_speed(&mut self, auto_speed: bool) {
+        self.auto_speed = auto_speed;
+    }
+
+    pub fn auto_speed(&self) -> bool {
+        self.auto_speed
+    }
+
+    /// units per second per unit of distance to the scene, 0.5 by default
+    pub fn set_auto_speed_factor(&mut self, auto_speed_factor: f32) {
+        self.auto_speed_factor = auto_speed_factor.max(0.0);
+    }
+
+    pub fn auto_speed_factor(&self) -> f32 {
+        self.auto_speed_factor
+    }
+
+    pub(crate) fn set_scene_distance(&mut self, scene_distance: Option<f32>) {
+        self.scene_distance = scene_distance;
+    }
+
+    /// what the keys and scrolling move with, `speed` unless auto speed is on
+    pub fn effective_speed(&self) -> f32 {
+        match (self.auto_speed, self.scene_distance) {
+            (true, Some(distance)) => (distance * self.auto_speed_factor)
+                .clamp(MIN_AUTO_SPEED, *Self::SPEED_RANGE.end()),
+            _ => self.speed,
         }
     }
 
+    pub fn sensitivity(&self) -> f32 {
+        self.sensitivity
+    }
+
+    /// the mouse look sensitivity, scrolling has its own (see `set_scroll_sensitivity`)
+    pub fn set_sensitivity(&mut self, sensitivity: f32) {
+        self.sensitivity =
+            sensitivity.clamp(*Self::SENSITIVITY_RANGE.start(), *Self::SENSITIVITY_RANGE.end());
+    }
+
+    /// how far one line of scrolling dollies the camera, independent of the look sensitivity
+    pub fn set_scroll_sensitivity(&mut self, scroll_sensitivity: f32) {
+        self.scroll_sensitivity = scroll_sensitivity;
+    }
+
+    /// keeps dollying after a flick of the wheel: the share of the scroll that carries over
+    /// into each 60th of a second, so it fades out exponentially. 0 (the default) stops
+    /// with the scrolling, 1 never stops
+    pub fn set_scroll_decay(&mut self, scroll_decay: f32) {
+        self.scroll_decay = scroll_decay.clamp(0.0, 1.0);
+    }
+
+    pub fn scroll_decay(&self) -> f32 {
+        self.scroll_decay
+    }
+
+    /// lets the velocity ramp up and down over `smoothing` seconds instead of jumping to
+    /// the keys' speed, 0 (the default) turns it off
+    pub fn set_smoothing(&mut self, smoothing: f32) {
+        self.smoothing = smoothing.max(0.0);
+    }
+
+    pub fn accel_curve(&self) -> AccelCurve {
+        self.accel_curve
+    }
+
+    /// only matters while smoothing is on
+    pub fn set_accel_curve(&mut self, accel_curve: AccelCurve) {
+        self.accel_curve = accel_curve;
+    }
+
+    /// forgets all pending input, call this when the window loses focus
+    /// (e.g. on `WindowEvent::Focused(false)`), otherwise keys held while alt-tabbing away
+    /// never get their release event and the camera keeps drifting
+    pub fn reset_input(&mut self) {
+        self.amount_left = 0.0;
+        self.amount_right = 0.0;
+        self.amount_forward = 0.0;
+        self.amount_backward = 0.0;
+        self.amount_up = 0.0;
+        self.amount_down = 0.0;
+        self.rotate_horizontal = 0.0;
+        self.rotate_vertical = 0.0;
+        self.scroll = 0.0;
+        self.scroll_velocity = 0.0;
+        self.pan_horizontal = 0.0;
+        self.pan_vertical = 0.0;
+        self.velocity = Vector3::zero();
+    }
+
     pub fn move_by(&mut self, delta: Vector3<f32>, camera: &mut Camera) {
         camera.position += delta;
     }
@@ -182,40 +656,89 @@ impl CameraController {
     //     }
     // }
 
-    // pub fn process_mouse(&mut self, _mouse_dx: f64,_mouse_dyy: f64) {
-    //     // self.rotate_horizontal = mouse_dx as f32;
-    //     // self.rotate_vertical = mouse_dy as f32;
-    // }
+    /// a movement key went down or up, the camera moves while it's held
+    pub fn process_keyboard(&mut self, direction: MoveDirection, pressed: bool) {
+        let amount = if pressed { 1.0 } else { 0.0 };
+        match direction {
+            MoveDirection::Forward => self.amount_forward = amount,
+            MoveDirection::Backward => self.amount_backward = amount,
+            MoveDirection::Left => self.amount_left = amount,
+            MoveDirection::Right => self.amount_right = amount,
+            MoveDirection::Up => self.amount_up = amount,
+            MoveDirection::Down => self.amount_down = amount,
+        }
+    }
 
-    // pub fn process_scroll(&mut self, delta: &MouseScrollDelta) {
-    //     self.scroll = match delta {
-    //         // I'm assuming a line is about 100 pixels
-    //         MouseScrollDelta::LineDelta(_, scroll) => -scroll * 0.5,
-    //         MouseScrollDelta::PixelDelta(PhysicalPosition { y: scroll, .. }) => -*scroll as f32,
-    //     };
-    // }
+    /// accumulates until the end of the frame, like scrolling
+    pub fn process_mouse(&mut self, mouse_dx: f64, mouse_dy: f64) {
+        self.rotate_horizontal += mouse_dx as f32;
+        self.rotate_vertical += mouse_dy as f32;
+    }
+
+    /// pixel deltas are converted to lines so wheels and trackpads feel the same,
+    /// several notches within one frame add up
+    pub fn process_scroll(&mut self, delta: &ScrollDelta) {
+        self.scroll += scroll_lines(delta);
+    }
+
+    /// strafes in the view plane by pixels of cursor movement (e.g. while the middle
+    /// button is held), the scene follows the cursor. accumulates like `process_mouse`
+    pub fn process_pan(&mut self, dx: f64, dy: f64) {
+        self.pan_horizontal += dx as f32;
+        self.pan_vertical += dy as f32;
+    }
+
+    /// drops the per-frame deltas (mouse motion, scroll, pan) once they were applied,
+    /// `update_camera` calls this, held keys are unaffected
+    pub fn end_frame(&mut self) {
+        self.rotate_horizontal = 0.0;
+        self.rotate_vertical = 0.0;
+        self.scroll = 0.0;
+        self.pan_horizontal = 0.0;
+        self.pan_vertical = 0.0;
+    }
 
     pub fn update_camera(&mut self, camera: &mut Camera, dt: Duration) {
         let dt = dt.as_secs_f32();
 
         // Move forward/backward and left/right
-        let (yaw_sin, yaw_cos) = camera.yaw.0.sin_cos();
-        let forward = Vector3::new(yaw_cos, 0.0, yaw_sin).normalize();
-        let right = Vector3::new(-yaw_sin, 0.0, yaw_cos).normalize();
-        camera.position = (camera.position + forward * (self.amount_forward - self.amount_backward) * self.speed * dt)%super::level::VIEW_DST; //XXX: did it work?
-        camera.position += right * (self.amount_right - self.amount_left) * self.speed * dt;
+        let up = camera.up.vector();
+        let forward = camera.up.direction(camera.yaw, Rad(0.0)).normalize();
+        let right = forward.cross(up).normalize();
+        let vertical = match self.vertical_mode {
+            VerticalMode::WorldUp => up,
+            VerticalMode::CameraUp => right.cross(camera.forward()),
+        };
+        let target = (forward * (self.amount_forward - self.amount_backward)
+            + right * (self.amount_right - self.amount_left)
+            + vertical * (self.amount_up - self.amount_down))
+            * self.effective_speed();
+        self.velocity = self.smoothed_velocity(target, dt);
+        camera.position = (camera.position + self.velocity * dt)%super::level::VIEW_DST; //XXX: did it work?
 
-        camera.position.y += (self.amount_up - self.amount_down) * self.speed * dt;
+        // Move in/out (aka. "zoom")
+        // Note: this isn't an actual zoom. The camera's position
+        // changes when zooming. I've added this to make it easier
+        // to get closer to an object you want to focus on.
+        let scrollward = camera.forward();
+        self.scroll_velocity += self.scroll;
+        camera.position +=
+            scrollward * self.scroll_velocity * self.effective_speed() * self.scroll_sensitivity * dt;
+        self.scroll_velocity *= self.scroll_decay.powf(dt * 60.0);
+        if self.scroll_velocity.abs() < 1e-3 {
+            self.scroll_velocity = 0.0;
+        }
+
+        // like `OrbitController`'s pan, the whole delta is applied at once
+        let pan_right = camera.forward().cross(up).normalize();
+        let pan_up = pan_right.cross(camera.forward());
+        let pan = pan_right * -self.pan_horizontal + pan_up * self.pan_vertical;
+        camera.position += pan * PAN_PER_PIXEL * self.pan_distance;
 
         // Rotate
         camera.yaw += Rad(self.rotate_horizontal) * self.sensitivity * dt;
         camera.pitch += Rad(-self.rotate_vertical) * self.sensitivity * dt;
-
-        // If process_mouse isn't called every frame, these values
-        // will not get set to zero, and the camera will rotate
-        // when moving in a non cardinal direction.
-        self.rotate_horizontal = 0.0;
-        self.rotate_vertical = 0.0;
+        self.end_frame();
 
         // Keep the camera's angle from going too high/low.
         if camera.pitch < -Rad(SAFE_FRAC_PI_2) {
@@ -224,6 +747,240 @@ impl CameraController {
             camera.pitch = Rad(SAFE_FRAC_PI_2);
         }
     }
+
+    /// moves the velocity toward `target` by the curve's lerp factor
+    fn smoothed_velocity(&self, target: Vector3<f32>, dt: f32) -> Vector3<f32> {
+        let gap = (target - self.velocity).magnitude();
+        if self.smoothing <= 0.0 || gap < f32::EPSILON {
+            return target;
+        }
+        let speed = self.effective_speed();
+        // how much of a full speed change is done, 0 right after a key changed
+        let progress = 1.0 - (gap / speed.max(f32::EPSILON)).min(1.0);
+        // going from standstill to full speed in `smoothing` seconds
+        let acceleration = speed / self.smoothing;
+        let factor = match self.accel_curve {
+            AccelCurve::Linear => acceleration * dt / gap,
+            // the rate of a smoothstep (averaging 1), kept from vanishing at the ends
+            AccelCurve::EaseInOut => {
+                acceleration * dt / gap * (6.0 * progress * (1.0 - progress)).max(0.1)
+            }
+            AccelCurve::Exponential => 1.0 - (-dt / self.smoothing).exp(),
+        };
+        self.velocity + (target - self.velocity) * factor.min(1.0)
+    }
+}
+
+/// orbits `target` at `radius`, the camera keeps looking at it with its own yaw and pitch
+#[derive(Debug)]
+pub struct OrbitController {
+    pub target: Point3<f32>,
+    radius: f32,
+    rotate_horizontal: f32,
+    rotate_vertical: f32,
+    scroll: f32,
+    sensitivity: f32,
+    /// how much one line of scrolling shrinks the radius, as a fraction of it
+    zoom_per_line: f32,
+    /// zoom toward the point under the cursor instead of the target, see `process_scroll`
+    pub zoom_to_cursor: bool,
+    zoom_anchor: Option<Point3<f32>>,
+    /// pending pan in pixels, see `process_pan`
+    pan_horizontal: f32,
+    pan_vertical: f32,
+    /// the radius zooming stays within, see `set_radius_limits`
+    min_radius: f32,
+    max_radius: f32,
+}
+
+/// how far one pixel of panning moves, as a fraction of the distance to the focus
+/// (the orbit's radius, `CameraController::pan_distance` in free flight)
+const PAN_PER_PIXEL: f32 = 0.002;
+
+impl OrbitController {
+    pub fn new(target: Point3<f32>, radius: f32, sensitivity: f32) -> Self {
+        Self {
+            target,
+            // zooming scales the radius, from zero it could never grow again
+            radius: radius.max(f32::EPSILON),
+            rotate_horizontal: 0.0,
+            rotate_vertical: 0.0,
+            scroll: 0.0,
+            sensitivity,
+            zoom_per_line: 0.1,
+            zoom_to_cursor: false,
+            zoom_anchor: None,
+            pan_horizontal: 0.0,
+            pan_vertical: 0.0,
+            min_radius: f32::EPSILON,
+            max_radius: f32::INFINITY,
+        }
+    }
+
+    pub fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    pub fn radius_limits(&self) -> (f32, f32) {
+        (self.min_radius, self.max_radius)
+    }
+
+    /// how close and how far scrolling can zoom, scrolling past a limit stops at it.
+    /// unlimited (but above zero) for a controller made with `new`, the ones `RenderCamera`
+    /// creates use `Projection::orbit_radius_limits`
+    pub fn set_radius_limits(&mut self, min: f32, max: f32) {
+        self.min_radius = min.max(f32::EPSILON);
+        self.max_radius = max.max(self.min_radius);
+        self.radius = self.radius.clamp(self.min_radius, self.max_radius);
+    }
+
+    /// the point the camera orbits around, the same as `target`
+    pub fn pivot(&self) -> Point3<f32> {
+        self.target
+    }
+
+    /// orbits around `pivot` (e.g. the world origin) from the next update on, keeping the
+    /// radius and the orientation, so the camera moves with it
+    pub fn set_pivot(&mut self, pivot: Point3<f32>) {
+        self.target = pivot;
+    }
+
+    pub fn reset_input(&mut self) {
+        self.rotate_horizontal = 0.0;
+        self.rotate_vertical = 0.0;
+        self.scroll = 0.0;
+        self.zoom_anchor = None;
+        self.pan_horizontal = 0.0;
+        self.pan_vertical = 0.0;
+    }
+
+    /// drags the pivot (and the camera with it) in the view plane, by pixels of cursor
+    /// movement. scaled with the radius so the scene moves about as fast as the cursor
+    pub fn process_pan(&mut self, dx: f64, dy: f64) {
+        self.pan_horizontal += dx as f32;
+        self.pan_vertical += dy as f32;
+    }
+
+    /// `cursor_hit` is the point under the cursor (see `SingleLevelManager::scroll_at`), with
+    /// `zoom_to_cursor` the zoom is centered on it so it stays under the cursor, otherwise
+    /// (or when the cursor is over nothing) it's centered on the target
+    /// accumulates like `CameraController::process_scroll`, the latest hit wins
+    pub fn process_scroll(&mut self, delta: &ScrollDelta, cursor_hit: Option<Point3<f32>>) {
+        self.scroll += scroll_lines(delta);
+        self.zoom_anchor = cursor_hit.filter(|_| self.zoom_to_cursor);
+    }
+
+    pub fn process_mouse(&mut self, mouse_dx: f64, mouse_dy: f64) {
+        self.rotate_horizontal += mouse_dx as f32;
+        self.rotate_vertical += mouse_dy as f32;
+    }
+
+    pub fn update_camera(&mut self, camera: &mut Camera, dt: Duration) {
+        let dt = dt.as_secs_f32();
+        camera.yaw += Rad(self.rotate_horizontal) * self.sensitivity * dt;
+        camera.pitch += Rad(-self.rotate_vertical) * self.sensitivity * dt;
+        camera.pitch = Rad(camera.pitch.0.clamp(-SAFE_FRAC_PI_2, SAFE_FRAC_PI_2));
+        self.rotate_horizontal = 0.0;
+        self.rotate_vertical = 0.0;
+
+        // scaling target and radius around the anchor keeps the orientation,
+        // so the anchor stays on the same pixel. limiting the factor (not just the radius)
+        // keeps the target from drifting once the radius hits a limit
+        let zoomed = self.radius * (1.0 - self.zoom_per_line).powf(self.scroll);
+        let factor = zoomed.clamp(self.min_radius, self.max_radius) / self.radius;
+        let anchor = self.zoom_anchor.take().unwrap_or(self.target);
+        self.target = anchor + (self.target - anchor) * factor;
+        self.radius *= factor;
+        self.scroll = 0.0;
+
+        // the scene follows the cursor, so the pivot moves against it
+        let right = camera.forward().cross(camera.up.vector()).normalize();
+        let up = right.cross(camera.forward());
+        let pan = right * -self.pan_horizontal + up * self.pan_vertical;
+        self.target += pan * PAN_PER_PIXEL * self.radius;
+        self.pan_horizontal = 0.0;
+        self.pan_vertical = 0.0;
+
+        camera.position = self.target - camera.forward() * self.radius;
+    }
+}
+
+/// debug shading that bands every pixel by the closest distance its ray passed the
+/// primitives at, like the contour lines of a map, showing the structure of the distance field.
+/// the rays hitting a primitive keep their usual shading
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct ContourSettings {
+    /// bands per world unit
+    pub frequency: f32,
+    /// 0 (the default) is off
+    pub enabled: u32,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    _pad: [u32; 2],
+}
+
+impl ContourSettings {
+    /// enabled with `frequency` bands per world unit
+    pub fn new(frequency: f32) -> Self {
+        Self {
+            frequency,
+            enabled: 1,
+            _pad: [0; 2],
+        }
+    }
+}
+
+impl Default for ContourSettings {
+    fn default() -> Self {
+        Self {
+            enabled: 0,
+            ..Self::new(0.1)
+        }
+    }
+}
+
+/// softens the silhouettes without multisampling: rays passing a surface closer than
+/// `width` pixels (at the distance they pass it) blend its color in by how close they got
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct EdgeAA {
+    /// of the soft edge in pixels
+    pub width: f32,
+    /// on (1) by default
+    pub enabled: u32,
+    /// `Projection::pixel_size`, kept up to date by `CameraUniform::update_view_proj`
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pixel_size: f32,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    _pad: u32,
+}
+
+impl EdgeAA {
+    pub fn new(width: f32) -> Self {
+        Self {
+            width,
+            enabled: 1,
+            pixel_size: 0.0,
+            _pad: 0,
+        }
+    }
+
+    pub fn disabled() -> Self {
+        Self {
+            enabled: 0,
+            ..Self::default()
+        }
+    }
+}
+
+impl Default for EdgeAA {
+    fn default() -> Self {
+        Self::new(1.0)
+    }
 }
 
 impl Default for CameraUniform {
@@ -233,12 +990,35 @@ impl Default for CameraUniform {
             world_to_screen: cgmath::Matrix4::identity().into(),
             screen_to_world: cgmath::Matrix4::identity().into(),
             pixel_normalization_matrix: cgmath::Matrix4::identity().into(),
+            prev_world_to_screen: cgmath::Matrix4::identity().into(),
             effect : Effect::default(),
-            _pad: [0.0; 3],
+            projection_kind: 0,
+            max_steps: DEFAULT_MAX_STEPS,
+            debug_mode: 0,
+            contour: ContourSettings::default(),
+            edge_aa: EdgeAA::default(),
         }
     }
 }
 
+/// what the shaders know about a camera, `CameraUniform` in common.wgsl
+///
+/// the layout (byte offsets, 320 bytes in total), for putting it into other bind groups
+/// with `as_bytes`:
+///
+/// | offset | field | wgsl type |
+/// |---|---|---|
+/// | 0 | `view_position` | `vec4<f32>` |
+/// | 16 | `world_to_screen` | `mat4x4<f32>` |
+/// | 80 | `screen_to_world` | `mat4x4<f32>` |
+/// | 144 | `pixel_normalization_matrix` | `mat4x4<f32>` |
+/// | 208 | `prev_world_to_screen` | `mat4x4<f32>` |
+/// | 272 | `effect` | `u32` |
+/// | 276 | `projection_kind` | `u32` |
+/// | 280 | `max_steps` | `u32` |
+/// | 284 | `debug_mode` | `u32` |
+/// | 288 | `contour` | `ContourSettings`, 16 bytes |
+/// | 304 | `edge_aa` | `EdgeAA`, 16 bytes, `@align(16)` in wgsl |
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct CameraUniform {
@@ -248,10 +1028,22 @@ pub struct CameraUniform {
     world_to_screen: [[f32; 4]; 4],
     screen_to_world: [[f32; 4]; 4],
     pixel_normalization_matrix : [[f32; 4]; 4],
+    /// last frame's `world_to_screen`, for motion blur and other temporal effects
+    prev_world_to_screen: [[f32; 4]; 4],
     pub effect : Effect,
-    _pad: [f32; 3],
+    projection_kind: u32,
+    /// how many steps the marcher takes per ray at most, see `AdaptiveSteps`
+    pub max_steps: u32,
+    /// `DebugMode::shader_index`
+    pub(crate) debug_mode: u32,
+    pub contour: ContourSettings,
+    pub edge_aa: EdgeAA,
 }
 
+// has to match the `CameraUniform` struct in common.wgsl, uniforms are laid out in 16 byte steps
+const _: () = assert!(std::mem::size_of::<CameraUniform>() == 320);
+const _: () = assert!(std::mem::size_of::<CameraUniform>() % 16 == 0);
+
 impl CameraUniform {
     pub fn new() -> Self {
         Self {
@@ -267,43 +1059,293 @@ impl CameraUniform {
         }
     }
 
+    pub fn set_debug_mode(&mut self, debug_mode: DebugMode) {
+        self.debug_mode = debug_mode.shader_index();
+    }
+
+    /// the raw uniform as the shaders read it, see the layout above
+    pub fn as_bytes(&self) -> &[u8] {
+        bytemuck::bytes_of(self)
+    }
+
+    /// the camera's position, `w` is 1
+    pub fn view_position(&self) -> [f32; 4] {
+        self.view_position
+    }
+
+    /// 0 is perspective, 1 orthographic
+    pub fn projection_kind(&self) -> u32 {
+        self.projection_kind
+    }
+
+    /// the `DebugMode` as the shader sees it, 0 is off
+    pub fn debug_mode(&self) -> u32 {
+        self.debug_mode
+    }
+
+    /// world to clip space, the projection times `Camera::calc_matrix`
+    pub fn world_to_screen(&self) -> Matrix4<f32> {
+        self.world_to_screen.into()
+    }
+
+    /// clip to world space, the inverse of `world_to_screen`
+    pub fn screen_to_world(&self) -> Matrix4<f32> {
+        self.screen_to_world.into()
+    }
+
+    /// `world_to_screen` of the previous `update_view_proj`
+    pub fn prev_world_to_screen(&self) -> Matrix4<f32> {
+        self.prev_world_to_screen.into()
+    }
+
+    /// framebuffer pixels to -0.5..0.5 across the viewport with y up, see
+    /// `Projection::get_pixel_normalization_matrix`
+    pub fn pixel_normalization_matrix(&self) -> Matrix4<f32> {
+        self.pixel_normalization_matrix.into()
+    }
+
+    /// last frame's `world_to_screen` is kept in `prev_world_to_screen` before it is recomputed
     pub fn update_view_proj(&mut self, camera: &Camera, projection: &Projection) {
+        self.prev_world_to_screen = self.world_to_screen;
         self.view_position = camera.position.to_homogeneous().into();
         let proj = projection.calc_matrix();
         let world_to_cam = camera.calc_matrix();
         self.world_to_screen = (proj * world_to_cam).into();
-        self.screen_to_world = //(camera.calc_inverse_matrix() * proj.invert().unwrap()).into();
-            (
-                // camera.calc_inverse_matrix() * 
-                world_to_cam.invert().unwrap() 
-                * 
-                proj.invert().unwrap() 
-            // * projection.get_uv_to_screen_matrix()
-        ).into();
+        self.screen_to_world = screen_to_world(camera, projection).into();
         self.pixel_normalization_matrix = projection.get_pixel_normalization_matrix().into();
+        self.projection_kind = projection.kind.shader_index();
+        self.edge_aa.pixel_size = projection.pixel_size();
+    }
+}
+
+/// how `RenderCamera::focus_on_with` moves the camera
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FocusOptions {
+    /// pick the radius so the primitive's bounding sphere fills the view,
+    /// otherwise the current distance is kept
+    pub frame: bool,
+    /// turn to a standard view from above and to the side instead of keeping the orientation
+    pub three_quarter_view: bool,
+}
+
+impl Default for FocusOptions {
+    fn default() -> Self {
+        Self {
+            frame: true,
+            three_quarter_view: false,
+        }
     }
 }
 
+/// a ray through a pixel, `direction` is normalized
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Ray {
+    pub origin: Point3<f32>,
+    pub direction: Vector3<f32>,
+}
+
+/// a snapshot of the camera for drawing a debug readout, see `RenderCamera::debug_state`
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct CameraDebugState {
+    pub position: [f32; 3],
+    /// in -180..180
+    pub yaw_degrees: f32,
+    pub pitch_degrees: f32,
+    /// the vertical fov including the fov kicks
+    pub fovy_degrees: f32,
+    /// from the frame time of the last `RenderCamera::update`, 0 before the first one
+    pub fps: f32,
+}
+
 pub struct RenderCamera {
     pub camera: Camera,
     pub projection: Projection,
     pub controller: CameraController,
+    /// replaces `controller` while set
+    pub orbit: Option<OrbitController>,
     pub uniform: CameraUniform,
     pub buffer: wgpu::Buffer,
     // pub bind_group: wgpu::BindGroup,
     pub bind_group_layout: wgpu::BindGroupLayout,
+    /// transient offsets on top of `projection.fovy`, see `fov_kick`
+    fov_kicks: Vec<FovKick>,
+    shake: CameraShake,
+    /// replaces both controllers' movement while set, see `follow`
+    follow: Option<Follow>,
+    /// center and radius of a sphere around the scene, see `set_scene_bounds`
+    scene_bounds: Option<(Point3<f32>, f32)>,
+    /// the `dt` of the last `update`, for `debug_state`
+    frame_time: Duration,
+}
+
+/// what `RenderCamera::follow` trails, and how fast the camera is moving after it
+#[derive(Debug, Clone, Copy)]
+struct Follow {
+    target: Point3<f32>,
+    offset: Vector3<f32>,
+    stiffness: f32,
+    velocity: Vector3<f32>,
+}
+
+impl Follow {
+    /// moves `camera` along a critically damped spring toward `target + offset`, which
+    /// approaches it as fast as possible without overshooting, and turns it toward `target`
+    fn update_camera(&mut self, camera: &mut Camera, dt: Duration) {
+        let dt = dt.as_secs_f32();
+        let goal = self.target + self.offset;
+        // exact solution of x'' = -stiffness² x - 2 stiffness x', stable for any dt
+        let displacement = camera.position - goal;
+        let decay = (-self.stiffness * dt).exp();
+        let temp = (self.velocity + displacement * self.stiffness) * dt;
+        self.velocity = (self.velocity - temp * self.stiffness) * decay;
+        camera.position = goal + (displacement + temp) * decay;
+        if camera.position != self.target {
+            camera.look_at(self.target);
+        }
+    }
+}
+
+/// how much trauma `add_shake` leaves per second
+pub const SHAKE_DECAY: f32 = 1.0;
+/// the offsets at full trauma
+pub const MAX_SHAKE_OFFSET: f32 = 0.1;
+pub const MAX_SHAKE_ANGLE: Rad<f32> = Rad(0.05);
+/// how many noise periods per second the shake runs through
+const SHAKE_FREQUENCY: f32 = 15.0;
+
+/// trauma in 0..1 that wobbles the view and fades out, see `RenderCamera::add_shake`
+#[derive(Debug, Clone, Copy, Default)]
+struct CameraShake {
+    trauma: f32,
+    /// where along the noise the shake is, in noise periods
+    time: f32,
+    seed: u32,
+}
+
+impl CameraShake {
+    fn update(&mut self, dt: Duration) {
+        let dt = dt.as_secs_f32();
+        self.trauma = (self.trauma - SHAKE_DECAY * dt).max(0.0);
+        self.time = if self.trauma > 0.0 { self.time + SHAKE_FREQUENCY * dt } else { 0.0 };
+    }
+
+    /// position offset and yaw and pitch offsets, all zero without trauma
+    fn offsets(&self) -> (Vector3<f32>, Rad<f32>, Rad<f32>) {
+        // squared for a punchy feel, small trauma barely moves the view
+        let magnitude = self.trauma * self.trauma;
+        let channel = |channel| self.noise(channel) * magnitude;
+        (
+            Vector3::new(channel(0), channel(1), channel(2)) * MAX_SHAKE_OFFSET,
+            MAX_SHAKE_ANGLE * channel(3),
+            MAX_SHAKE_ANGLE * channel(4),
+        )
+    }
+
+    /// smoothly interpolated value noise in [-1, 1], a separate one per `channel`
+    fn noise(&self, channel: u32) -> f32 {
+        let i = self.time.floor();
+        let f = self.time - i;
+        let u = f * f * (3.0 - 2.0 * f);
+        let a = shake_hash(self.seed, channel, i as u32);
+        let b = shake_hash(self.seed, channel, i as u32 + 1);
+        a + (b - a) * u
+    }
+}
+
+/// integer hash of the arguments to [-1, 1]
+fn shake_hash(seed: u32, channel: u32, i: u32) -> f32 {
+    let mut h = seed ^ channel.wrapping_mul(0x9e37_79b9) ^ i.wrapping_mul(0x85eb_ca6b);
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x7feb_352d);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x846c_a68b);
+    h ^= h >> 16;
+    h as f32 / u32::MAX as f32 * 2.0 - 1.0
+}
+
+/// a fov offset fading out over `duration`
+#[derive(Debug, Clone, Copy)]
+struct FovKick {
+    amount: Rad<f32>,
+    duration: Duration,
+    elapsed: Duration,
+}
+
+impl FovKick {
+    /// eases from the full amount at the start to 0 at the end
+    fn offset(&self) -> Rad<f32> {
+        let t = (self.elapsed.as_secs_f32() / self.duration.as_secs_f32()).clamp(0.0, 1.0);
+        let remaining = 1.0 - t;
+        self.amount * (remaining * remaining * (3.0 - 2.0 * remaining))
+    }
+}
+
+/// the stages the camera uniform is visible to unless requested otherwise
+pub const DEFAULT_CAMERA_VISIBILITY: wgpu::ShaderStages = wgpu::ShaderStages::VERTEX_FRAGMENT;
+
+/// the initial pose, lens and controls of a `RenderCamera`, see `RenderCamera::with_config`
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderCameraConfig {
+    pub position: Point3<f32>,
+    pub yaw: Rad<f32>,
+    pub pitch: Rad<f32>,
+    pub fovy: Rad<f32>,
+    pub znear: f32,
+    pub zfar: f32,
+    pub speed: f32,
+    pub sensitivity: f32,
+    pub visibility: wgpu::ShaderStages,
+}
+
+impl RenderCameraConfig {
+    /// what `RenderCamera::new` uses: at the origin looking down +z with a 120° fov,
+    /// flying as fast as the view reaches per second
+    pub fn new(far: f32) -> Self {
+        Self {
+            position: Point3::new(0.0, 0.0, 0.0),
+            yaw: Deg(90.0).into(),
+            pitch: Rad(0.0),
+            fovy: Deg(120.0).into(),
+            znear: 1.0,
+            zfar: far,
+            speed: far,
+            sensitivity: 0.5,
+            visibility: DEFAULT_CAMERA_VISIBILITY,
+        }
+    }
 }
 
 impl RenderCamera {
     pub fn new(device : &Device, size: (u32,u32), far:f32)->(Self, wgpu::BindGroup){
+        Self::with_visibility(device, size, far, DEFAULT_CAMERA_VISIBILITY)
+    }
 
-        let camera = Camera::new((0.0, 0.0, 0.0), cgmath::Deg(90.0), cgmath::Deg(0.0));
+    /// like `new`, but lets you pick which shader stages can read the camera uniform
+    pub fn with_visibility(
+        device: &Device,
+        size: (u32, u32),
+        far: f32,
+        visibility: wgpu::ShaderStages,
+    ) -> (Self, wgpu::BindGroup) {
+        Self::with_config(device, size, RenderCameraConfig { visibility, ..RenderCameraConfig::new(far) })
+    }
+
+    /// like `new`, but starting from `config` instead of the default pose
+    pub fn with_config(
+        device: &Device,
+        size: (u32, u32),
+        config: RenderCameraConfig,
+    ) -> (Self, wgpu::BindGroup) {
+        let visibility = config.visibility;
+        let camera = Camera::new(config.position, config.yaw, config.pitch);
         let projection =
-            Projection::new(size.0, size.1, cgmath::Deg(120.0), 1.0, far);
-        let controller = CameraController::new(far, 0.5);
+            Projection::new(size.0, size.1, config.fovy, config.znear, config.zfar);
+        let controller = CameraController::new(config.speed, config.sensitivity);
 
         let mut uniform = CameraUniform::new();
         uniform.update_view_proj(&camera, &projection);
+        // no motion on the first frame
+        uniform.prev_world_to_screen = uniform.world_to_screen;
 
         let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Camera Buffer"),
@@ -314,7 +1356,7 @@ impl RenderCamera {
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 entries: &[wgpu::BindGroupLayoutEntry {
                     binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    visibility,
                     ty: wgpu::BindingType::Buffer {
                         ty: wgpu::BufferBindingType::Uniform,
                         has_dynamic_offset: false,
@@ -336,24 +1378,493 @@ impl RenderCamera {
             camera,
             projection,
             controller,
+            orbit: None,
             uniform,
             buffer,
             // bind_group,
             bind_group_layout,
+            fov_kicks: Vec::new(),
+            shake: CameraShake::default(),
+            follow: None,
+            scene_bounds: None,
+            frame_time: Duration::ZERO,
         }, bind_group)
     }
+    /// another bind group for this camera's uniform, e.g. one matching the level's pipelines
+    /// for an extra view
+    pub fn bind_group(&self, device: &Device, layout: &wgpu::BindGroupLayout) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: self.buffer.as_entire_binding(),
+            }],
+            label: Some("camera_bind_group"),
+        })
+    }
+
     fn update_controller(&mut self, dt: Duration) {
-        self.controller.update_camera(&mut self.camera, dt);
+        if let Some(follow) = &mut self.follow {
+            follow.update_camera(&mut self.camera, dt);
+            return;
+        }
+        match &mut self.orbit {
+            Some(orbit) => orbit.update_camera(&mut self.camera, dt),
+            None => self.controller.update_camera(&mut self.camera, dt),
+        }
+    }
+
+    /// trails `target` from `desired_offset`, looking at it. call this every frame with the
+    /// target's current position, the camera eases toward it on a critically damped spring in
+    /// `update`, faster with a higher `stiffness` (settled after about `5 / stiffness` seconds),
+    /// without overshooting. the controllers don't move the camera while following
+    pub fn follow(&mut self, target: Point3<f32>, desired_offset: Vector3<f32>, stiffness: f32) {
+        let velocity = self.follow.map_or(Vector3::zero(), |follow| follow.velocity);
+        self.follow = Some(Follow {
+            target,
+            offset: desired_offset,
+            stiffness: stiffness.max(0.0),
+            velocity,
+        });
+    }
+
+    /// back to the controllers, from where following left the camera
+    pub fn stop_following(&mut self) {
+        self.follow = None;
+    }
+
+    pub fn is_following(&self) -> bool {
+        self.follow.is_some()
+    }
+
+    /// switches to orbit mode around `target`, keeping the current distance to it
+    pub fn orbit_around(&mut self, target: Point3<f32>) {
+        let radius = self.camera.position.distance(target);
+        self.camera.look_at(target);
+        let mut orbit = OrbitController::new(target, radius, self.controller.sensitivity);
+        let (min, max) = self.projection.orbit_radius_limits();
+        orbit.set_radius_limits(min, max);
+        self.camera.position = target - self.camera.forward() * orbit.radius;
+        self.orbit = Some(orbit);
+    }
+
+    /// orbits around the primitive's center and frames it, keeping the orientation
+    pub fn focus_on(&mut self, primitive: &SDFPrimitive) {
+        self.focus_on_with(primitive, FocusOptions::default());
+    }
+
+    pub fn focus_on_with(&mut self, primitive: &SDFPrimitive, options: FocusOptions) {
+        let target = Point3::from(primitive.position);
+        if options.three_quarter_view {
+            self.camera.yaw = Deg(45.0).into();
+            self.camera.pitch = Deg(-30.0).into();
+        }
+        let radius = if options.frame {
+            // only the central half of the ndc range is visible (see `get_pixel_normalization_matrix`)
+            let half_fovy = (0.5 * (0.5 * self.projection.fovy.0).tan()).atan();
+            (primitive.bounding_radius() / half_fovy.sin()).max(self.projection.znear)
+        } else {
+            self.camera.position.distance(target)
+        };
+        let sensitivity = self.controller.sensitivity;
+        let limits = self.projection.orbit_radius_limits();
+        let orbit = self.orbit.get_or_insert_with(|| {
+            let mut orbit = OrbitController::new(target, radius, sensitivity);
+            orbit.set_radius_limits(limits.0, limits.1);
+            orbit
+        });
+        orbit.target = target;
+        orbit.radius = radius.clamp(orbit.min_radius, orbit.max_radius);
+        self.camera.position = target - self.camera.forward() * orbit.radius;
+    }
+
+    /// back to free flying from wherever the orbit left the camera
+    pub fn fly(&mut self) {
+        self.orbit = None;
+    }
+
+    /// routes the scroll to the active controller, `cursor_hit` is only used in orbit mode
+    pub fn process_scroll(&mut self, delta: &ScrollDelta, cursor_hit: Option<Point3<f32>>) {
+        match &mut self.orbit {
+            Some(orbit) => orbit.process_scroll(delta, cursor_hit),
+            None => self.controller.process_scroll(delta),
+        }
+    }
+
+    /// only the free flying controller moves with the keys
+    pub fn process_keyboard(&mut self, direction: MoveDirection, pressed: bool) {
+        self.controller.process_keyboard(direction, pressed);
+    }
+
+    /// turns the camera with the active controller
+    pub fn process_mouse(&mut self, mouse_dx: f64, mouse_dy: f64) {
+        match &mut self.orbit {
+            Some(orbit) => orbit.process_mouse(mouse_dx, mouse_dy),
+            None => self.controller.process_mouse(mouse_dx, mouse_dy),
+        }
+    }
+
+    /// pans the active controller, in orbit mode this moves the pivot
+    pub fn process_pan(&mut self, dx: f64, dy: f64) {
+        match &mut self.orbit {
+            Some(orbit) => orbit.process_pan(dx, dy),
+            None => self.controller.process_pan(dx, dy),
+        }
+    }
+
+    /// forgets all pending input of both controllers
+    pub fn reset_input(&mut self) {
+        self.controller.reset_input();
+        if let Some(orbit) = &mut self.orbit {
+            orbit.reset_input();
+        }
     }
     fn update_uniform(&mut self, queue: &Queue) {
-        self.uniform.update_view_proj(&self.camera, &self.projection);
-        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.uniform]));
+        // the kicks only go into the uniform, `projection.fovy` stays the base fov
+        // neither do the shake's offsets, they'd otherwise pile up in `camera`
+        let base_fovy = self.projection.fovy;
+        self.projection.fovy = self.kicked_fovy();
+        let (offset, yaw, pitch) = self.shake.offsets();
+        let shaken = Camera {
+            position: self.camera.position + offset,
+            yaw: self.camera.yaw + yaw,
+            pitch: self.camera.pitch + pitch,
+            up: self.camera.up,
+        };
+        self.uniform.update_view_proj(&shaken, &self.projection);
+        self.projection.fovy = base_fovy;
+        self.write_uniform(queue, &self.uniform);
+    }
+
+    /// shakes the view, `trauma` (clamped to 0..1 in total) adds up and decays by `SHAKE_DECAY`
+    /// per second, the offsets scale with its square. only the uniform is shaken, `camera` stays put
+    pub fn add_shake(&mut self, trauma: f32) {
+        self.shake.trauma = (self.shake.trauma + trauma).clamp(0.0, 1.0);
+    }
+
+    pub fn trauma(&self) -> f32 {
+        self.shake.trauma
+    }
+
+    /// the same seed and updates give the same shake
+    pub fn set_shake_seed(&mut self, seed: u32) {
+        self.shake.seed = seed;
+    }
+
+    /// widens (or narrows, for a negative `amount`) the fov by `amount`, easing back to the
+    /// base fov over `duration`. overlapping kicks add up, the base `projection.fovy` is untouched
+    pub fn fov_kick(&mut self, amount: Rad<f32>, duration: Duration) {
+        if duration.is_zero() {
+            return;
+        }
+        self.fov_kicks.push(FovKick {
+            amount,
+            duration,
+            elapsed: Duration::ZERO,
+        });
+    }
+
+    /// the fov the uniform is built with, the base plus what's left of the kicks
+    pub fn kicked_fovy(&self) -> Rad<f32> {
+        let offset: f32 = self.fov_kicks.iter().map(|kick| kick.offset().0).sum();
+        // stays a valid perspective however many kicks pile up
+        Rad((self.projection.fovy.0 + offset).clamp(0.01, std::f32::consts::PI - 0.01))
+    }
+
+    fn update_fov_kicks(&mut self, dt: Duration) {
+        for kick in &mut self.fov_kicks {
+            kick.elapsed += dt;
+        }
+        self.fov_kicks.retain(|kick| kick.elapsed < kick.duration);
+    }
+
+    /// uploads `uniform` instead of this camera's own, until the next `update`
+    pub fn write_uniform(&self, queue: &Queue, uniform: &CameraUniform) {
+        crate::trace_upload!("camera", 0, std::mem::size_of::<CameraUniform>());
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[*uniform]));
+    }
+
+    /// left and right eye uniforms for side by side stereo, the eyes sit `ipd / 2` to either
+    /// side of the camera along its right vector and each one renders into its half of the viewport
+    pub fn stereo_pair(&self, ipd: f32) -> (CameraUniform, CameraUniform) {
+        let ViewRect { origin, size } = self.projection.viewport();
+        let half = size.0 / 2;
+        let left = ViewRect {
+            origin,
+            size: (half.max(1), size.1),
+        };
+        let right = ViewRect {
+            origin: (origin.0 + half, origin.1),
+            size: ((size.0 - half).max(1), size.1),
+        };
+        (self.eye_uniform(-0.5 * ipd, left), self.eye_uniform(0.5 * ipd, right))
+    }
+
+    /// the uniform of an eye `offset` along the right vector, rendering into `rect`
+    fn eye_uniform(&self, offset: f32, rect: ViewRect) -> CameraUniform {
+        let right = self.camera.forward().cross(self.camera.up.vector()).normalize();
+        let eye = Camera {
+            position: self.camera.position + right * offset,
+            yaw: self.camera.yaw,
+            pitch: self.camera.pitch,
+            up: self.camera.up,
+        };
+        let mut projection = self.projection.clone();
+        projection.set_viewport(rect);
+        let mut uniform = self.uniform;
+        uniform.update_view_proj(&eye, &projection);
+        // moving the eye is a translation in view space: eye view = T(-offset) * view, so last
+        // frame's eye is last frame's camera pushed through the eye projection with the same shift
+        let eye_proj = projection.calc_matrix();
+        let shift = eye_proj
+            * Matrix4::from_translation(Vector3::new(-offset, 0.0, 0.0))
+            * self.projection.calc_matrix().invert().unwrap();
+        uniform.prev_world_to_screen = (shift * Matrix4::from(self.uniform.prev_world_to_screen)).into();
+        uniform
+    }
+    /// what the far plane has to reach with `Projection::set_auto_clip`, the level keeps
+    /// its own camera's bounds up to date
+    pub fn set_scene_bounds(&mut self, bounds: Option<(Point3<f32>, f32)>) {
+        self.scene_bounds = bounds;
     }
+
+    /// the numbers a debug readout shows, formatting and drawing them is up to the app
+    pub fn debug_state(&self) -> CameraDebugState {
+        let fps = if self.frame_time.is_zero() { 0.0 } else { 1.0 / self.frame_time.as_secs_f32() };
+        CameraDebugState {
+            position: self.camera.position.into(),
+            yaw_degrees: Deg::from(self.camera.yaw).normalize_signed().0,
+            pitch_degrees: Deg::from(self.camera.pitch).0,
+            fovy_degrees: Deg::from(self.kicked_fovy()).0,
+            fps,
+        }
+    }
+
     pub fn update(&mut self, dt: Duration, queue: &Queue) {
+        self.frame_time = dt;
+        let eye = self.camera.position;
+        self.controller
+            .set_scene_distance(self.scene_bounds.map(|(center, _)| eye.distance(center)));
         self.update_controller(dt);
+        self.update_fov_kicks(dt);
+        self.shake.update(dt);
+        self.projection.fit_clip(self.camera.position, self.scene_bounds);
         self.update_uniform(queue);
     }
     pub fn resize(&mut self, width: u32, height: u32) {
         self.projection.resize(width, height);
     }
+
+    /// the corners of the view frustum in world space, bit 0 of the index picks the right
+    /// side, bit 1 the top and bit 2 the far plane. unprojected from the uniform's
+    /// `screen_to_world`, so they include the fov kicks and the shake of the last update
+    pub fn frustum_corners(&self) -> [Point3<f32>; 8] {
+        let screen_to_world = self.uniform.screen_to_world();
+        [0, 1, 2, 3, 4, 5, 6, 7].map(|i: u32| {
+            // the view spans the central half of the ndc range, see `get_pixel_normalization_matrix`
+            let side = |bit: u32| if i & bit == 0 { -0.5 } else { 0.5 };
+            let depth = (i >> 2) as f32;
+            Point3::from_homogeneous(screen_to_world * Vector4::new(side(1), side(2), depth, 1.0))
+        })
+    }
+
+    /// the ray the marcher shoots through `pixel` (physical pixels from the top left),
+    /// for picking and cpu side sdf queries
+    ///
+    /// perspective rays all start at the eye and diverge, orthographic rays start on the
+    /// near plane and all point along the view direction (mirrors `camera_ray` in common.wgsl)
+    pub fn screen_ray(&self, pixel: Vector2<f32>) -> Ray {
+        screen_ray(&self.camera, &self.projection, pixel)
+    }
+}
+
+/// see `RenderCamera::screen_ray`
+fn screen_ray(camera: &Camera, projection: &Projection, pixel: Vector2<f32>) -> Ray {
+    let screen =
+        projection.get_pixel_normalization_matrix() * Vector4::new(pixel.x, pixel.y, 0.0, 1.0);
+    let screen_to_world = screen_to_world(camera, projection);
+    let unproject = |depth: f32| {
+        Point3::from_homogeneous(screen_to_world * Vector4::new(screen.x, screen.y, depth, 1.0))
+    };
+    let near = unproject(0.0);
+    match projection.kind {
+        ProjectionKind::Perspective => Ray {
+            origin: camera.position,
+            direction: (near - camera.position).normalize(),
+        },
+        ProjectionKind::Orthographic { .. } => Ray {
+            origin: near,
+            direction: (unproject(1.0) - near).normalize(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-4;
+
+    /// 200 by 100 pixels, so a mixed up aspect shows, with a 90° fov
+    fn projection(kind: ProjectionKind) -> Projection {
+        let mut projection = Projection::new(200, 100, Deg(90.0), 1.0, 100.0);
+        projection.kind = kind;
+        projection
+    }
+
+    /// a few poses that don't line up with the axes
+    fn cameras() -> Vec<Camera> {
+        vec![
+            Camera::looking_at((0.0, 0.0, 0.0), Point3::new(0.0, 0.0, -1.0)),
+            Camera::new((1.0, -2.0, 3.0), Deg(30.0), Deg(-20.0)),
+            Camera::new((-5.0, 4.0, 0.5), Deg(200.0), Deg(60.0)),
+        ]
+    }
+
+    fn kinds() -> [ProjectionKind; 2] {
+        [
+            ProjectionKind::Perspective,
+            ProjectionKind::Orthographic { height: 10.0 },
+        ]
+    }
+
+    fn ndc(world_to_screen: Matrix4<f32>, point: Point3<f32>) -> Point3<f32> {
+        Point3::from_homogeneous(world_to_screen * point.to_homogeneous())
+    }
+
+    #[test]
+    fn world_to_screen_inverts_screen_to_world() {
+        for camera in cameras() {
+            for kind in kinds() {
+                let mut uniform = CameraUniform::new();
+                uniform.update_view_proj(&camera, &projection(kind));
+                assert_abs_diff_eq!(
+                    uniform.world_to_screen() * uniform.screen_to_world(),
+                    Matrix4::identity(),
+                    epsilon = EPSILON
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn known_point_projects_to_golden_ndc() {
+        let camera = Camera::looking_at((0.0, 0.0, 0.0), Point3::new(0.0, 0.0, -1.0));
+        let mut uniform = CameraUniform::new();
+        uniform.update_view_proj(&camera, &projection(ProjectionKind::Perspective));
+        // x is squeezed by the aspect of 2, the depth is `zfar * (z - znear) / (z * (zfar - znear))`
+        assert_abs_diff_eq!(
+            ndc(uniform.world_to_screen(), Point3::new(5.0, 2.5, -10.0)),
+            Point3::new(0.25, 0.25, 100.0 * 9.0 / (10.0 * 99.0)),
+            epsilon = EPSILON
+        );
+    }
+
+    #[test]
+    fn inverse_matrix_inverts_calc_matrix() {
+        for camera in cameras() {
+            assert_abs_diff_eq!(
+                camera.calc_inverse_matrix() * camera.calc_matrix(),
+                Matrix4::identity(),
+                epsilon = EPSILON
+            );
+        }
+    }
+
+    #[test]
+    fn screen_to_world_round_trips() {
+        for camera in cameras() {
+            // in front of the camera, between the clip planes
+            let point = camera.position + 10.0 * camera.forward() + Vector3::new(0.5, -0.3, 0.2);
+            for kind in kinds() {
+                let projection = projection(kind);
+                let world_to_screen = projection.calc_matrix() * camera.calc_matrix();
+                let ndc = ndc(world_to_screen, point);
+                assert_abs_diff_eq!(
+                    Point3::from_homogeneous(
+                        screen_to_world(&camera, &projection) * ndc.to_homogeneous()
+                    ),
+                    point,
+                    epsilon = EPSILON
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn depth_is_in_wgpu_range() {
+        for camera in cameras() {
+            let world_to_screen =
+                projection(ProjectionKind::Perspective).calc_matrix() * camera.calc_matrix();
+            let in_front = ndc(world_to_screen, camera.position + 50.0 * camera.forward());
+            assert!((0.0..=1.0).contains(&in_front.z), "{in_front:?}");
+            let behind = ndc(world_to_screen, camera.position - 50.0 * camera.forward());
+            assert!(!(0.0..=1.0).contains(&behind.z), "{behind:?}");
+        }
+    }
+
+    #[test]
+    fn pixel_size_ndc_is_two_over_pixels() {
+        let projection = Projection::new(640, 360, Deg(60.0), 1.0, 100.0);
+        assert_abs_diff_eq!(
+            projection.pixel_size_ndc(),
+            Vector2::new(2.0 / 640.0, 2.0 / 360.0),
+            epsilon = f32::EPSILON
+        );
+    }
+
+    #[test]
+    fn aspect_follows_resize() {
+        let mut projection = projection(ProjectionKind::Perspective);
+        projection.resize(1920, 1080);
+        assert_eq!(projection.dimensions(), (1920, 1080));
+        assert_abs_diff_eq!(projection.aspect(), 1920.0 / 1080.0, epsilon = f32::EPSILON);
+    }
+
+    #[test]
+    fn adjacent_rays_diverge_in_perspective_only() {
+        let camera = Camera::new((1.0, -2.0, 3.0), Deg(30.0), Deg(-20.0));
+        let pixels = [Vector2::new(100.0, 50.0), Vector2::new(101.0, 50.0)];
+
+        let ortho = projection(ProjectionKind::Orthographic { height: 10.0 });
+        let [a, b] = pixels.map(|pixel| screen_ray(&camera, &ortho, pixel));
+        assert_abs_diff_eq!(a.direction, b.direction, epsilon = EPSILON);
+        assert!((a.origin - b.origin).magnitude() > EPSILON);
+
+        let perspective = projection(ProjectionKind::Perspective);
+        let [a, b] = pixels.map(|pixel| screen_ray(&camera, &perspective, pixel));
+        assert_abs_diff_eq!(a.origin, b.origin, epsilon = EPSILON);
+        assert!((a.direction - b.direction).magnitude() > EPSILON);
+    }
+
+    #[test]
+    fn auto_speed_scales_with_the_scene_distance() {
+        let mut controller = CameraController::new(4.0, 0.5);
+        controller.set_auto_speed(true);
+        controller.set_auto_speed_factor(0.5);
+        assert_eq!(controller.effective_speed(), 4.0);
+        controller.set_scene_distance(Some(20.0));
+        assert_abs_diff_eq!(controller.effective_speed(), 10.0, epsilon = EPSILON);
+        controller.set_scene_distance(Some(50_000.0));
+        assert_eq!(controller.effective_speed(), *CameraController::SPEED_RANGE.end());
+        controller.set_auto_speed(false);
+        assert_eq!(controller.effective_speed(), 4.0);
+    }
+
+    #[test]
+    fn pixel_normalization_spans_the_viewport() {
+        let mut projection = projection(ProjectionKind::Perspective);
+        projection.set_viewport(ViewRect {
+            origin: (50, 20),
+            size: (200, 100),
+        });
+        let normalize = |x: f32, y: f32| {
+            let screen = projection.get_pixel_normalization_matrix() * Vector4::new(x, y, 0.0, 1.0);
+            Vector2::new(screen.x, screen.y)
+        };
+        // the marcher only uses the central half of the ndc range, with y up
+        assert_abs_diff_eq!(normalize(50.0, 20.0), Vector2::new(-0.5, 0.5), epsilon = EPSILON);
+        assert_abs_diff_eq!(normalize(250.0, 120.0), Vector2::new(0.5, -0.5), epsilon = EPSILON);
+        assert_abs_diff_eq!(normalize(150.0, 70.0), Vector2::new(0.0, 0.0), epsilon = EPSILON);
+    }
 }
@@ -0,0 +1,95 @@
+use std::fmt;
+
+use super::primitives::SDFPrimitive;
+use super::wgpu::{self, Adapter, Device, PresentMode, TextureFormat};
+
+/// the device limits the renderer cares about, query them once at startup
+/// to know how big a scene the gpu can take
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+    /// largest storage buffer a single binding may see
+    pub max_storage_buffer_binding_size: u32,
+    /// largest buffer that can be created at all
+    pub max_buffer_size: u64,
+    pub max_bind_groups: u32,
+}
+
+impl Capabilities {
+    pub fn from_limits(limits: &wgpu::Limits) -> Self {
+        Self {
+            max_storage_buffer_binding_size: limits.max_storage_buffer_binding_size,
+            max_buffer_size: limits.max_buffer_size,
+            max_bind_groups: limits.max_bind_groups,
+        }
+    }
+
+    /// the limits the device was actually created with
+    pub fn from_device(device: &Device) -> Self {
+        Self::from_limits(&device.limits())
+    }
+
+    /// the best the adapter could do, a device requested with default limits may get less
+    pub fn from_adapter(adapter: &Adapter) -> Self {
+        Self::from_limits(&adapter.limits())
+    }
+
+    /// how many primitives fit into the primitives storage buffer
+    pub fn max_primitive_count(&self) -> usize {
+        let max_bytes = (self.max_storage_buffer_binding_size as u64).min(self.max_buffer_size);
+        (max_bytes / std::mem::size_of::<SDFPrimitive>() as u64) as usize
+    }
+
+    pub fn check_primitive_count(&self, requested: usize) -> Result<(), CapacityError> {
+        let max = self.max_primitive_count();
+        if requested > max {
+            Err(CapacityError { requested, max })
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// `requested` if the surface supports it, `Fifo` (vsync, which every surface has) otherwise
+///
+/// `supported` is `Surface::get_capabilities(adapter).present_modes`. the surface belongs to
+/// eframe, which only takes the present mode at startup (`NativeOptions::wgpu_options`),
+/// so switching vsync at runtime is only possible with a `Renderer` (`set_present_mode`)
+pub fn supported_present_mode(requested: PresentMode, supported: &[PresentMode]) -> PresentMode {
+    match requested {
+        // picked by wgpu itself, always valid
+        PresentMode::AutoVsync | PresentMode::AutoNoVsync => requested,
+        _ if supported.contains(&requested) => requested,
+        _ => PresentMode::Fifo,
+    }
+}
+
+/// the first srgb format in `supported` (`Surface::get_capabilities(adapter).formats`, best first),
+/// the first format at all if there is none. `None` if the adapter can't present to the surface
+///
+/// eframe picks its own format, `State::target_format` tells which one
+pub fn preferred_surface_format(supported: &[TextureFormat]) -> Option<TextureFormat> {
+    supported
+        .iter()
+        .find(|format| format.describe().srgb)
+        .or_else(|| supported.first())
+        .copied()
+}
+
+/// more primitives were requested than the device's storage buffers can hold
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CapacityError {
+    pub requested: usize,
+    pub max: usize,
+}
+
+impl fmt::Display for CapacityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} primitives were requested but the device's storage buffers only fit {}",
+            self.requested, self.max
+        )
+    }
+}
+
+impl std::error::Error for CapacityError {}
@@ -0,0 +1,173 @@
+use super::wgpu::{self, util::DeviceExt, BindGroup, BindGroupLayout, Buffer, Device};
+use super::{BindGroups, CAMERA_GROUP, PRIMITIVES_GROUP, VISIBLE_GROUP};
+
+const WORKGROUP_SIZE: u32 = 64;
+
+/// compute pre-pass that writes the indices of all primitives inside the camera frustum
+/// into a compacted list, which the marcher iterates instead of the whole primitives buffer
+///
+/// the primitives and camera bind groups must be visible to `ShaderStages::COMPUTE`
+pub(crate) struct CullingPass {
+    pipeline: wgpu::ComputePipeline,
+    buffer: Buffer,
+    compute_bind_group: BindGroup,
+    compute_bind_group_layout: BindGroupLayout,
+    pub render_bind_group_layout: BindGroupLayout,
+}
+
+impl CullingPass {
+    pub fn new(
+        device: &Device,
+        primitives_bind_group_layout: &BindGroupLayout,
+        camera_bind_group_layout: &BindGroupLayout,
+        primitive_count: usize,
+    ) -> (Self, BindGroup) {
+        let mk_layout = |label, visibility, read_only| {
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+                label: Some(label),
+            })
+        };
+        let compute_bind_group_layout = mk_layout(
+            "visible_primitives_compute_bind_group_layout",
+            wgpu::ShaderStages::COMPUTE,
+            false,
+        );
+        let render_bind_group_layout = mk_layout(
+            "visible_primitives_bind_group_layout",
+            wgpu::ShaderStages::FRAGMENT,
+            true,
+        );
+        let (buffer, compute_bind_group, render_bind_group) = mk_visible_list(
+            device,
+            &compute_bind_group_layout,
+            &render_bind_group_layout,
+            primitive_count,
+        );
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Culling Shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                concat!(
+                    include_str!("level/common.wgsl"),
+                    include_str!("level/culling_shader.wgsl")
+                )
+                .into(),
+            ),
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Culling Pipeline Layout"),
+            bind_group_layouts: &[
+                primitives_bind_group_layout,
+                camera_bind_group_layout,
+                &compute_bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Culling Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "cs_main",
+        });
+
+        (
+            Self {
+                pipeline,
+                buffer,
+                compute_bind_group,
+                compute_bind_group_layout,
+                render_bind_group_layout,
+            },
+            render_bind_group,
+        )
+    }
+
+    /// recreates the visible list for `primitive_count` primitives, needed whenever the
+    /// primitives buffer is recreated with a different size (see `PrimitiveManager::resize`).
+    /// the returned bind group has to replace the old visible one
+    pub fn resize(&mut self, device: &Device, primitive_count: usize) -> BindGroup {
+        let (buffer, compute_bind_group, render_bind_group) = mk_visible_list(
+            device,
+            &self.compute_bind_group_layout,
+            &self.render_bind_group_layout,
+            primitive_count,
+        );
+        self.buffer = buffer;
+        self.compute_bind_group = compute_bind_group;
+        render_bind_group
+    }
+
+    /// the visible list, in bytes
+    pub fn memory_usage(&self) -> u64 {
+        self.buffer.size()
+    }
+
+    /// resets the visible count and tells the marcher whether to use the compacted list,
+    /// only dispatches the pass if `enabled`
+    pub fn run(
+        &self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        bind_groups: &BindGroups,
+        enabled: bool,
+        primitive_count: usize,
+    ) {
+        crate::trace_upload!("culling header", 0, 8);
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[0u32, enabled as u32]));
+        if !enabled || primitive_count == 0 {
+            return;
+        }
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("Culling Pass"),
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(PRIMITIVES_GROUP, &bind_groups.primitives_bind_group, &[]);
+        pass.set_bind_group(CAMERA_GROUP, &bind_groups.camera_bind_group, &[]);
+        pass.set_bind_group(VISIBLE_GROUP, &self.compute_bind_group, &[]);
+        pass.dispatch_workgroups(
+            (primitive_count as u32 + WORKGROUP_SIZE - 1) / WORKGROUP_SIZE,
+            1,
+            1,
+        );
+    }
+}
+
+/// the visible list's buffer, with the compute pass' and the marcher's bind group on it
+fn mk_visible_list(
+    device: &Device,
+    compute_bind_group_layout: &BindGroupLayout,
+    render_bind_group_layout: &BindGroupLayout,
+    primitive_count: usize,
+) -> (Buffer, BindGroup, BindGroup) {
+    // header (count + enabled flag) followed by one index per primitive
+    let contents = vec![0u32; 2 + primitive_count];
+    let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Visible Primitives Buffer"),
+        contents: bytemuck::cast_slice(&contents),
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+    });
+    let mk_bind_group = |label, layout| {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+            label: Some(label),
+        })
+    };
+    let compute_bind_group =
+        mk_bind_group("visible_primitives_compute_bind_group", compute_bind_group_layout);
+    let render_bind_group = mk_bind_group("visible_primitives_bind_group", render_bind_group_layout);
+    (buffer, compute_bind_group, render_bind_group)
+}
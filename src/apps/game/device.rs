@@ -0,0 +1,168 @@
+use std::fmt;
+
+use super::capabilities::preferred_surface_format;
+use super::wgpu::{self, Adapter, Device, Queue, Surface, SurfaceConfiguration};
+
+/// what the renderer can't do without: the primitives are read from storage buffers in the
+/// fragment shader and the culling pre-pass is a compute shader (both missing on webgl)
+pub const REQUIRED_DOWNLEVEL_FLAGS: wgpu::DownlevelFlags = wgpu::DownlevelFlags::FRAGMENT_STORAGE
+    .union(wgpu::DownlevelFlags::COMPUTE_SHADERS);
+
+/// how `init_wgpu` and `init_headless` pick the adapter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InitOptions {
+    pub power_preference: wgpu::PowerPreference,
+    /// try a low power adapter if there's no suitable one with `power_preference`
+    pub low_power_fallback: bool,
+    /// what `init_wgpu` configures the surface with, `RENDER_ATTACHMENT` by default. add
+    /// `COPY_SRC` to read the presented image back (screenshots of the window), which not
+    /// every platform supports for surfaces. the render targets' usage is separate, see
+    /// `REQUIRED_TARGET_USAGES`
+    pub surface_usage: wgpu::TextureUsages,
+}
+
+impl Default for InitOptions {
+    fn default() -> Self {
+        Self {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            low_power_fallback: true,
+            surface_usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        }
+    }
+}
+
+/// sets up a device for rendering into `surface`, which has to be created by the caller
+/// (`Instance::create_surface` is unsafe as the window has to outlive it).
+/// the surface is configured for `size` with an srgb format if it has one (see
+/// `preferred_surface_format`), pipelines drawing into it have to use `config.format`
+///
+/// the app doesn't need this, eframe does its own setup, see `Capabilities` for what it got
+pub async fn init_wgpu(
+    instance: &wgpu::Instance,
+    surface: Surface,
+    size: (u32, u32),
+    options: InitOptions,
+) -> Result<(Device, Queue, Surface, SurfaceConfiguration), InitError> {
+    let (device, queue, surface, config, _) =
+        init_wgpu_with_present_modes(instance, surface, size, options).await?;
+    Ok((device, queue, surface, config))
+}
+
+/// `init_wgpu`, also returning the present modes the surface supports, see
+/// `supported_present_mode`
+pub(crate) async fn init_wgpu_with_present_modes(
+    instance: &wgpu::Instance,
+    surface: Surface,
+    size: (u32, u32),
+    options: InitOptions,
+) -> Result<(Device, Queue, Surface, SurfaceConfiguration, Vec<wgpu::PresentMode>), InitError> {
+    let adapter = request_adapter(instance, Some(&surface), options).await?;
+    let (device, queue) = request_device(&adapter).await?;
+    let capabilities = surface.get_capabilities(&adapter);
+    let format =
+        preferred_surface_format(&capabilities.formats).ok_or(InitError::IncompatibleSurface)?;
+    let config = SurfaceConfiguration {
+        usage: options.surface_usage | wgpu::TextureUsages::RENDER_ATTACHMENT,
+        format,
+        width: size.0.max(1),
+        height: size.1.max(1),
+        present_mode: wgpu::PresentMode::Fifo,
+        alpha_mode: capabilities
+            .alpha_modes
+            .first()
+            .copied()
+            .unwrap_or(wgpu::CompositeAlphaMode::Auto),
+        view_formats: Vec::new(),
+    };
+    surface.configure(&device, &config);
+    Ok((device, queue, surface, config, capabilities.present_modes))
+}
+
+/// sets up a device without a window, e.g. for rendering into textures
+pub async fn init_headless(
+    instance: &wgpu::Instance,
+    options: InitOptions,
+) -> Result<(Device, Queue), InitError> {
+    let adapter = request_adapter(instance, None, options).await?;
+    request_device(&adapter).await
+}
+
+async fn request_adapter(
+    instance: &wgpu::Instance,
+    compatible_surface: Option<&Surface>,
+    options: InitOptions,
+) -> Result<Adapter, InitError> {
+    let mut preferences = vec![options.power_preference];
+    if options.low_power_fallback && options.power_preference != wgpu::PowerPreference::LowPower {
+        preferences.push(wgpu::PowerPreference::LowPower);
+    }
+    let mut missing = None;
+    for power_preference in preferences {
+        let Some(adapter) = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference,
+                compatible_surface,
+                force_fallback_adapter: false,
+            })
+            .await
+        else {
+            continue;
+        };
+        let flags = adapter.get_downlevel_capabilities().flags;
+        if flags.contains(REQUIRED_DOWNLEVEL_FLAGS) {
+            return Ok(adapter);
+        }
+        missing = Some(REQUIRED_DOWNLEVEL_FLAGS - flags);
+    }
+    Err(match missing {
+        Some(missing) => InitError::Unsupported { missing },
+        None => InitError::NoAdapter,
+    })
+}
+
+async fn request_device(adapter: &Adapter) -> Result<(Device, Queue), InitError> {
+    let supported = adapter.limits();
+    // the primitive count is bound by the storage buffer size, so take as much as there is
+    let limits = wgpu::Limits {
+        max_storage_buffer_binding_size: supported.max_storage_buffer_binding_size,
+        max_buffer_size: supported.max_buffer_size,
+        ..wgpu::Limits::downlevel_defaults().using_resolution(supported)
+    };
+    adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                label: Some("ShapeStormer Device"),
+                features: wgpu::Features::empty(),
+                limits,
+            },
+            None,
+        )
+        .await
+        .map_err(InitError::RequestDevice)
+}
+
+/// no device the renderer can run on
+#[derive(Debug)]
+pub enum InitError {
+    NoAdapter,
+    /// the adapter lacks some of `REQUIRED_DOWNLEVEL_FLAGS`
+    Unsupported { missing: wgpu::DownlevelFlags },
+    RequestDevice(wgpu::RequestDeviceError),
+    /// the adapter can't present to the surface
+    IncompatibleSurface,
+}
+
+impl fmt::Display for InitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoAdapter => write!(f, "no graphics adapter found"),
+            Self::Unsupported { missing } => {
+                write!(f, "the graphics adapter doesn't support {missing:?}")
+            }
+            Self::RequestDevice(err) => write!(f, "can't create the device: {err}"),
+            Self::IncompatibleSurface => write!(f, "the graphics adapter can't present to the surface"),
+        }
+    }
+}
+
+impl std::error::Error for InitError {}
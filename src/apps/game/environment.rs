@@ -0,0 +1,157 @@
+use std::path::Path;
+
+use super::memory::texture_bytes;
+use super::wgpu::{self, util::DeviceExt, BindGroup, BindGroupLayout, Buffer, Device, Queue};
+
+const TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba32Float;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct EnvironmentUniform {
+    enabled: u32,
+    intensity: f32,
+    _pad: [f32; 2],
+}
+
+/// optional equirectangular background, sampled by the direction of rays that hit nothing
+///
+/// without an environment a 1x1 placeholder is bound and the marcher keeps its
+/// plain background
+pub struct Environment {
+    pub bind_group_layout: BindGroupLayout,
+    sampler: wgpu::Sampler,
+    /// of the bound image, 1x1 for the placeholder
+    texture_size: (u32, u32),
+}
+
+impl Environment {
+    pub fn new(device: &Device, queue: &Queue) -> (Self, BindGroup) {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        // 32 bit floats can't be filtered without an extra device feature
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::NonFiltering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("environment_bind_group_layout"),
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Environment Sampler"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            ..Default::default()
+        });
+        let mut environment = Self {
+            bind_group_layout,
+            sampler,
+            texture_size: (1, 1),
+        };
+        let bind_group = environment.mk_bind_group(device, queue, 1, 1, &[0.0; 4], false);
+        (environment, bind_group)
+    }
+
+    /// loads an equirectangular image (`.hdr`, `.png`, `.jpg`, ...) as the background,
+    /// the returned bind group replaces the current one
+    pub fn load<P: AsRef<Path>>(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        path: P,
+    ) -> Result<BindGroup, image::ImageError> {
+        let image = image::open(path)?.into_rgba32f();
+        let (width, height) = image.dimensions();
+        Ok(self.mk_bind_group(device, queue, width, height, image.as_raw(), true))
+    }
+
+    /// the bind group for the plain background
+    pub fn clear(&mut self, device: &Device, queue: &Queue) -> BindGroup {
+        self.mk_bind_group(device, queue, 1, 1, &[0.0; 4], false)
+    }
+
+    /// the image and its uniform, in bytes
+    pub fn memory_usage(&self) -> u64 {
+        texture_bytes(self.texture_size, TEXTURE_FORMAT)
+            + std::mem::size_of::<EnvironmentUniform>() as u64
+    }
+
+    fn mk_bind_group(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        width: u32,
+        height: u32,
+        rgba: &[f32],
+        enabled: bool,
+    ) -> BindGroup {
+        let texture = device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label: Some("Environment Texture"),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: TEXTURE_FORMAT,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+            bytemuck::cast_slice(rgba),
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        self.texture_size = (width, height);
+        let uniform: Buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Environment Buffer"),
+            contents: bytemuck::bytes_of(&EnvironmentUniform {
+                enabled: enabled as u32,
+                intensity: 1.0,
+                ..Default::default()
+            }),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: uniform.as_entire_binding(),
+                },
+            ],
+            label: Some("environment_bind_group"),
+        })
+    }
+}
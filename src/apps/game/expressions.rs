@@ -0,0 +1,283 @@
+use std::fmt;
+use std::time::Duration;
+
+use evalexpr::{
+    ContextWithMutableFunctions, ContextWithMutableVariables, EvalexprError, Function,
+    HashMapContext, Node, Value,
+};
+
+use super::primitives::SDFPrimitive;
+
+/// a field of `SDFPrimitive` an expression can drive, named like the field with the component
+/// after a dot, e.g. `position.y`, `rgba.a` or `twist`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ExpressionChannel {
+    /// 0 is x, 1 y and 2 z
+    Position(usize),
+    /// 0 is r, 1 g, 2 b and 3 a
+    Rgba(usize),
+    /// 0 is r, 1 g and 2 b
+    Emissive(usize),
+    Speed,
+    Twist,
+    Bend,
+    Rounding,
+    Displacement,
+    NoiseScale,
+    Metalness,
+    ShellThickness,
+}
+
+impl ExpressionChannel {
+    fn write(self, primitive: &mut SDFPrimitive, value: f32) {
+        match self {
+            Self::Position(component) => primitive.position[component] = value,
+            Self::Rgba(component) => primitive.rgba[component] = value,
+            Self::Emissive(component) => primitive.emissive[component] = value,
+            Self::Speed => primitive.speed = value,
+            Self::Twist => primitive.twist = value,
+            Self::Bend => primitive.bend = value,
+            Self::Rounding => primitive.rounding = value,
+            Self::Displacement => primitive.displacement = value,
+            Self::NoiseScale => primitive.noise_scale = value,
+            Self::Metalness => primitive.metalness = value,
+            Self::ShellThickness => primitive.shell_thickness = value,
+        }
+    }
+}
+
+impl std::str::FromStr for ExpressionChannel {
+    type Err = ExpressionError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let component = |component: &str, names: &[&str]| {
+            names.iter().position(|&name| name == component)
+        };
+        let channel = match name.split_once('.') {
+            Some(("position", xyz)) => component(xyz, &["x", "y", "z"]).map(Self::Position),
+            Some(("rgba", rgba)) => component(rgba, &["r", "g", "b", "a"]).map(Self::Rgba),
+            Some(("emissive", rgb)) => component(rgb, &["r", "g", "b"]).map(Self::Emissive),
+            Some(_) => None,
+            None => match name {
+                "speed" => Some(Self::Speed),
+                "twist" => Some(Self::Twist),
+                "bend" => Some(Self::Bend),
+                "rounding" => Some(Self::Rounding),
+                "displacement" => Some(Self::Displacement),
+                "noise_scale" => Some(Self::NoiseScale),
+                "metalness" => Some(Self::Metalness),
+                "shell_thickness" => Some(Self::ShellThickness),
+                _ => None,
+            },
+        };
+        channel.ok_or_else(|| ExpressionError::UnknownChannel(name.to_owned()))
+    }
+}
+
+/// `channel = expression` for one primitive, e.g. `position.y = sin(t + phase)`.
+///
+/// the expression is evaluated by `evalexpr` on every update with `t` (the seconds the
+/// primitives have been updated for), `phase` (the primitive's, see `SDFPrimitive::set_phase`)
+/// and `index` (the primitive's) in scope. besides evalexpr's own functions (`math::sin`,
+/// `min`, ..) `sin`, `cos`, `tan`, `sqrt`, `abs`, `exp`, `ln` and `pi` can be used as is
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct PrimitiveExpression {
+    pub primitive: usize,
+    pub expression: String,
+}
+
+impl PrimitiveExpression {
+    pub fn new(primitive: usize, expression: impl Into<String>) -> Self {
+        Self {
+            primitive,
+            expression: expression.into(),
+        }
+    }
+}
+
+/// a `PrimitiveExpression` parsed once, so updates only evaluate it
+pub(crate) struct CompiledExpression {
+    source: PrimitiveExpression,
+    channel: ExpressionChannel,
+    node: Node,
+}
+
+impl CompiledExpression {
+    pub fn new(source: &PrimitiveExpression) -> Result<Self, ExpressionError> {
+        let not_an_assignment = || ExpressionError::NotAnAssignment(source.expression.clone());
+        let (channel, expression) = source.expression.split_once('=').ok_or_else(not_an_assignment)?;
+        // `a == b` isn't an assignment either
+        if expression.starts_with('=') {
+            return Err(not_an_assignment());
+        }
+        Ok(Self {
+            source: source.clone(),
+            channel: channel.trim().parse()?,
+            node: evalexpr::build_operator_tree(expression)?,
+        })
+    }
+}
+
+/// the expressions animating the primitives, evaluated by `PrimitiveManager::update` after
+/// moving them, so an expression wins over the `speed` and `rotation_delta` of its channel
+pub struct PrimitiveExpressions {
+    expressions: Vec<CompiledExpression>,
+    context: HashMapContext,
+    time: Duration,
+}
+
+impl Default for PrimitiveExpressions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PrimitiveExpressions {
+    pub fn new() -> Self {
+        let mut context = HashMapContext::new();
+        let functions = [
+            ("sin", f64::sin as fn(f64) -> f64),
+            ("cos", f64::cos),
+            ("tan", f64::tan),
+            ("sqrt", f64::sqrt),
+            ("abs", f64::abs),
+            ("exp", f64::exp),
+            ("ln", f64::ln),
+        ];
+        for (name, function) in functions {
+            let function =
+                Function::new(move |argument| Ok(Value::Float(function(argument.as_number()?))));
+            context
+                .set_function(name.to_owned(), function)
+                .expect("a HashMapContext takes any function");
+        }
+        context
+            .set_value("pi".to_owned(), Value::Float(std::f64::consts::PI))
+            .expect("`pi` is only set once");
+        Self {
+            expressions: Vec::new(),
+            context,
+            time: Duration::ZERO,
+        }
+    }
+
+    /// adds the expression, replacing the one driving the same channel of the same primitive
+    pub fn set(&mut self, expression: &PrimitiveExpression) -> Result<(), ExpressionError> {
+        let compiled = CompiledExpression::new(expression)?;
+        self.expressions.retain(|other| {
+            other.source.primitive != compiled.source.primitive || other.channel != compiled.channel
+        });
+        self.expressions.push(compiled);
+        Ok(())
+    }
+
+    /// whether there was an expression driving `channel` of `primitive`
+    pub fn remove(&mut self, primitive: usize, channel: ExpressionChannel) -> bool {
+        let len = self.expressions.len();
+        self.expressions
+            .retain(|expression| expression.source.primitive != primitive || expression.channel != channel);
+        self.expressions.len() != len
+    }
+
+    pub fn clear(&mut self) {
+        self.expressions.clear();
+    }
+
+    /// swaps in expressions compiled beforehand (so a failure keeps the current ones), the
+    /// clock keeps running
+    pub(crate) fn replace_all(&mut self, expressions: Vec<CompiledExpression>) {
+        self.expressions = expressions;
+    }
+
+    /// points the expressions at their primitives' new indices after reordering them
+    pub(crate) fn remap_primitives(&mut self, new_index: impl Fn(usize) -> usize) {
+        for expression in &mut self.expressions {
+            expression.source.primitive = new_index(expression.source.primitive);
+        }
+    }
+
+    /// drops the expressions of the primitives at `count` and beyond, after removing those
+    pub(crate) fn truncate_primitives(&mut self, count: usize) {
+        self.expressions.retain(|expression| expression.source.primitive < count);
+    }
+
+    pub fn sources(&self) -> impl Iterator<Item = &PrimitiveExpression> + '_ {
+        self.expressions.iter().map(|expression| &expression.source)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.expressions.is_empty()
+    }
+
+    /// advances `t` by `dt` and writes the expressions' values into `primitives`, returns
+    /// whether anything was written. expressions of primitives beyond `primitives` are
+    /// skipped, one that fails to evaluate (e.g. adds a string) is logged and dropped
+    pub(crate) fn evaluate(&mut self, dt: Duration, primitives: &mut [SDFPrimitive]) -> bool {
+        self.time += dt;
+        let time = self.time.as_secs_f64();
+        let context = &mut self.context;
+        let mut written = false;
+        self.expressions.retain(|expression| {
+            let index = expression.source.primitive;
+            let Some(primitive) = primitives.get_mut(index) else {
+                return true;
+            };
+            // the expressions can't assign, so the variables always keep their types
+            let variables = [
+                ("t", Value::Float(time)),
+                ("phase", Value::Float(primitive.phase as f64)),
+                ("index", Value::Int(index as i64)),
+            ];
+            for (name, value) in variables {
+                context
+                    .set_value(name.to_owned(), value)
+                    .expect("the variables keep their types");
+            }
+            match expression.node.eval_number_with_context(context) {
+                Ok(value) => {
+                    expression.channel.write(primitive, value as f32);
+                    written = true;
+                    true
+                }
+                Err(err) => {
+                    tracing::warn!(
+                        "dropping `{}` of primitive {index}: {err}",
+                        expression.source.expression
+                    );
+                    false
+                }
+            }
+        });
+        written
+    }
+}
+
+#[derive(Debug)]
+pub enum ExpressionError {
+    /// not of the form `channel = expression`
+    NotAnAssignment(String),
+    /// see `ExpressionChannel`
+    UnknownChannel(String),
+    Parse(EvalexprError),
+}
+
+impl fmt::Display for ExpressionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotAnAssignment(expression) => {
+                write!(f, "`{expression}` isn't of the form `channel = expression`")
+            }
+            Self::UnknownChannel(channel) => write!(f, "`{channel}` isn't an animatable field"),
+            Self::Parse(err) => write!(f, "invalid expression: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for ExpressionError {}
+
+impl From<EvalexprError> for ExpressionError {
+    fn from(err: EvalexprError) -> Self {
+        Self::Parse(err)
+    }
+}
@@ -0,0 +1,160 @@
+use cgmath::Point3;
+
+use super::render_targets::{overlay_depth_stencil, OverlayTarget, COLOR_FORMAT};
+use super::wgpu::{self, BindGroup, BindGroupLayout, Buffer, Device, Queue};
+use super::{BindGroups, CAMERA_GROUP, PRIMITIVES_GROUP, VISIBLE_GROUP};
+
+/// how many frustums are drawn at most, the rest are left out
+pub const MAX_DEBUG_FRUSTUMS: usize = 8;
+
+/// the colors the extra views' frustums cycle through, see `State::show_frustums`
+pub const FRUSTUM_COLORS: [[f32; 4]; 4] = [
+    [1.0, 0.8, 0.0, 1.0],
+    [0.0, 0.9, 1.0, 1.0],
+    [1.0, 0.2, 0.8, 1.0],
+    [0.4, 1.0, 0.3, 1.0],
+];
+
+/// a camera's frustum drawn as a wireframe
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DebugFrustum {
+    /// see `RenderCamera::frustum_corners`
+    pub corners: [Point3<f32>; 8],
+    pub color: [f32; 4],
+}
+
+impl DebugFrustum {
+    fn uniform(&self) -> FrustumUniform {
+        FrustumUniform {
+            corners: self.corners.map(|corner| corner.to_homogeneous().into()),
+            color: self.color,
+        }
+    }
+}
+
+/// `Frustum` in frustum_shader.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct FrustumUniform {
+    corners: [[f32; 4]; 8],
+    color: [f32; 4],
+}
+
+/// draws `DebugFrustum`s as lines over the finished image, tested against the marched depth
+/// so primitives hide them while `State::set_overlay_depth_test` is on (the default)
+pub(crate) struct FrustumPass {
+    pipeline: wgpu::RenderPipeline,
+    /// `MAX_DEBUG_FRUSTUMS` frustums, the unused ones aren't drawn
+    buffer: Buffer,
+    bind_group: BindGroup,
+}
+
+impl FrustumPass {
+    pub fn new(
+        device: &Device,
+        primitives_bind_group_layout: &BindGroupLayout,
+        camera_bind_group_layout: &BindGroupLayout,
+    ) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("frustum_bind_group_layout"),
+        });
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Frustum Buffer"),
+            size: (MAX_DEBUG_FRUSTUMS * std::mem::size_of::<FrustumUniform>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+            label: Some("frustum_bind_group"),
+        });
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Frustum Shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                concat!(
+                    include_str!("level/common.wgsl"),
+                    include_str!("level/frustum_shader.wgsl")
+                )
+                .into(),
+            ),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Frustum Pipeline Layout"),
+            bind_group_layouts: &[
+                primitives_bind_group_layout,
+                camera_bind_group_layout,
+                &bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Frustum Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(COLOR_FORMAT.into())],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineList,
+                ..Default::default()
+            },
+            depth_stencil: Some(overlay_depth_stencil()),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+        Self {
+            pipeline,
+            buffer,
+            bind_group,
+        }
+    }
+
+    /// the frustums' uniform, in bytes
+    pub fn memory_usage(&self) -> u64 {
+        self.buffer.size()
+    }
+
+    /// draws over the finished image, see `OverlayTarget`
+    pub fn run(
+        &self,
+        queue: &Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        bind_groups: &BindGroups,
+        target: &OverlayTarget<'_>,
+        frustums: &[Option<DebugFrustum>; MAX_DEBUG_FRUSTUMS],
+    ) {
+        let uniforms: Vec<FrustumUniform> = frustums.iter().flatten().map(DebugFrustum::uniform).collect();
+        if uniforms.is_empty() {
+            return;
+        }
+        crate::trace_upload!("frustums", 0, uniforms.len() * std::mem::size_of::<FrustumUniform>());
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&uniforms));
+        let mut pass = target.begin_pass(encoder, "Frustum Pass");
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(PRIMITIVES_GROUP, &bind_groups.primitives_bind_group, &[]);
+        pass.set_bind_group(CAMERA_GROUP, &bind_groups.camera_bind_group, &[]);
+        pass.set_bind_group(VISIBLE_GROUP, &self.bind_group, &[]);
+        // 12 edges of 2 vertices each
+        pass.draw(0..24, 0..uniforms.len() as u32);
+    }
+}
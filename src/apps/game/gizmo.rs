@@ -0,0 +1,266 @@
+use cgmath::{InnerSpace, MetricSpace, Point3, Quaternion, Vector3};
+
+use super::camera::{ProjectionKind, Ray, RenderCamera};
+use super::primitives::{Axis, PrimitiveManager};
+use super::render_targets::{overlay_depth_stencil, OverlayTarget, COLOR_FORMAT};
+use super::wgpu::{self, util::DeviceExt, BindGroup, BindGroupLayout, Buffer, Device, Queue};
+use super::{BindGroups, CAMERA_GROUP, PRIMITIVES_GROUP, VISIBLE_GROUP};
+
+/// the fraction of the distance to the camera (or of the orthographic view height)
+/// the axes are long, which keeps them the same size on screen
+const GIZMO_SCALE: f32 = 0.1;
+/// how close to an axis (relative to its length) the cursor ray has to pass to grab it
+const GRAB_RADIUS: f32 = 0.08;
+
+/// which axes the gizmo shows and drags along
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum GizmoSpace {
+    /// the selected primitive's rotated axes, world axes for several selected primitives
+    #[default]
+    Local,
+    World,
+}
+
+/// the selection's local axes, at the selected primitive's position and oriented by its
+/// rotation, several selected primitives share world axes at their center
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Gizmo {
+    pub origin: Point3<f32>,
+    pub rotation: Quaternion<f32>,
+    /// of each axis in world units
+    pub length: f32,
+}
+
+impl Gizmo {
+    /// `None` if nothing is selected
+    pub fn for_selection(
+        primitive_manager: &PrimitiveManager,
+        camera: &RenderCamera,
+        space: GizmoSpace,
+    ) -> Option<Self> {
+        let origin = primitive_manager.selection_center()?;
+        let rotation = match primitive_manager.selection().iter().next() {
+            Some(&index) if space == GizmoSpace::Local && primitive_manager.selection().len() == 1 => {
+                Quaternion::from(primitive_manager.primitives[index].rotation).normalize()
+            }
+            _ => Quaternion::new(1.0, 0.0, 0.0, 0.0),
+        };
+        let extent = match camera.projection.kind {
+            ProjectionKind::Perspective => camera.camera.position.distance(origin),
+            ProjectionKind::Orthographic { height } => height,
+        };
+        Some(Self {
+            origin,
+            rotation,
+            length: extent * GIZMO_SCALE,
+        })
+    }
+
+    /// the world direction of the gizmo's `axis`
+    pub fn axis(&self, axis: Axis) -> Vector3<f32> {
+        self.rotation * axis.unit()
+    }
+
+    /// the axis whose line the ray passes closest to, if it passes close enough
+    pub fn hit_test(&self, ray: &Ray) -> Option<Axis> {
+        [Axis::X, Axis::Y, Axis::Z]
+            .into_iter()
+            .filter_map(|axis| {
+                let (t, s) = closest_params(self.origin, self.axis(axis), ray)?;
+                if s < 0.0 || !(0.0..=self.length).contains(&t) {
+                    return None;
+                }
+                let on_axis = self.origin + self.axis(axis) * t;
+                let on_ray = ray.origin + ray.direction * s;
+                Some((axis, on_axis.distance(on_ray)))
+            })
+            .filter(|&(_, distance)| distance < GRAB_RADIUS * self.length)
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(axis, _)| axis)
+    }
+
+    fn uniform(&self) -> GizmoUniform {
+        GizmoUniform {
+            origin: self.origin.into(),
+            length: self.length,
+            rotation: self.rotation.into(),
+        }
+    }
+}
+
+/// moving the selection by dragging one of the gizmo's axes, the axis is fixed at the grab
+/// so the selection follows the cursor's projection onto it
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GizmoDrag {
+    pub axis: Axis,
+    origin: Point3<f32>,
+    direction: Vector3<f32>,
+    /// where along the axis the cursor was last, the offset between the grabbed point and
+    /// the gizmo's origin is kept as only the difference to this is applied
+    last: f32,
+}
+
+impl GizmoDrag {
+    /// `None` if the ray misses the gizmo
+    pub fn grab(gizmo: &Gizmo, ray: &Ray) -> Option<Self> {
+        let axis = gizmo.hit_test(ray)?;
+        let direction = gizmo.axis(axis);
+        let (last, _) = closest_params(gizmo.origin, direction, ray)?;
+        Some(Self {
+            axis,
+            origin: gizmo.origin,
+            direction,
+            last,
+        })
+    }
+
+    /// how far the selection has to move since the last call for the cursor ray to stay
+    /// on the grabbed point, zero while the ray runs parallel to the axis
+    pub fn drag(&mut self, ray: &Ray) -> Vector3<f32> {
+        let Some((t, _)) = closest_params(self.origin, self.direction, ray) else {
+            return Vector3::new(0.0, 0.0, 0.0);
+        };
+        let delta = self.direction * (t - self.last);
+        self.last = t;
+        delta
+    }
+}
+
+/// the parameters `(t, s)` of the closest points `origin + direction * t` on the line and
+/// `ray.origin + ray.direction * s` on the ray, `None` if they are (close to) parallel
+fn closest_params(origin: Point3<f32>, direction: Vector3<f32>, ray: &Ray) -> Option<(f32, f32)> {
+    let w = origin - ray.origin;
+    let b = direction.dot(ray.direction);
+    // both directions are unit length
+    let denominator = 1.0 - b * b;
+    if denominator < 1e-6 {
+        return None;
+    }
+    let (d, e) = (direction.dot(w), ray.direction.dot(w));
+    Some(((b * e - d) / denominator, (e - b * d) / denominator))
+}
+
+/// `Gizmo` in gizmo_shader.wgsl
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct GizmoUniform {
+    origin: [f32; 3],
+    length: f32,
+    rotation: [f32; 4],
+}
+
+/// draws the `Gizmo` as three lines over the finished image, tested against the marched depth
+/// so primitives hide it while `State::set_overlay_depth_test` is on (the default)
+pub(crate) struct GizmoPass {
+    pipeline: wgpu::RenderPipeline,
+    buffer: Buffer,
+    bind_group: BindGroup,
+}
+
+impl GizmoPass {
+    pub fn new(
+        device: &Device,
+        primitives_bind_group_layout: &BindGroupLayout,
+        camera_bind_group_layout: &BindGroupLayout,
+    ) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+            label: Some("gizmo_bind_group_layout"),
+        });
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Gizmo Buffer"),
+            contents: bytemuck::bytes_of(&GizmoUniform {
+                origin: [0.0; 3],
+                length: 0.0,
+                rotation: [0.0, 0.0, 0.0, 1.0],
+            }),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+            label: Some("gizmo_bind_group"),
+        });
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Gizmo Shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                concat!(
+                    include_str!("level/common.wgsl"),
+                    include_str!("level/gizmo_shader.wgsl")
+                )
+                .into(),
+            ),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Gizmo Pipeline Layout"),
+            bind_group_layouts: &[
+                primitives_bind_group_layout,
+                camera_bind_group_layout,
+                &bind_group_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Gizmo Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(COLOR_FORMAT.into())],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::LineList,
+                ..Default::default()
+            },
+            depth_stencil: Some(overlay_depth_stencil()),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+        Self {
+            pipeline,
+            buffer,
+            bind_group,
+        }
+    }
+
+    /// the gizmo's uniform, in bytes
+    pub fn memory_usage(&self) -> u64 {
+        self.buffer.size()
+    }
+
+    /// draws over the finished image, see `OverlayTarget`
+    pub fn run(
+        &self,
+        queue: &Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        bind_groups: &BindGroups,
+        target: &OverlayTarget<'_>,
+        gizmo: &Gizmo,
+    ) {
+        crate::trace_upload!("gizmo", 0, std::mem::size_of::<GizmoUniform>());
+        queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(&gizmo.uniform()));
+        let mut pass = target.begin_pass(encoder, "Gizmo Pass");
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(PRIMITIVES_GROUP, &bind_groups.primitives_bind_group, &[]);
+        pass.set_bind_group(CAMERA_GROUP, &bind_groups.camera_bind_group, &[]);
+        pass.set_bind_group(VISIBLE_GROUP, &self.bind_group, &[]);
+        pass.draw(0..6, 0..1);
+    }
+}
@@ -0,0 +1,183 @@
+use super::primitives::SDFPrimitive;
+use super::wgpu::{self, util::DeviceExt, Buffer, Device, Queue};
+
+/// primitives repeat infinitely along x and y with this period (see `distance_to_primitive`),
+/// so the grid only has to cover a single period there
+pub const INFINITE_REPETITION_PERIOD: f32 = 1000.0;
+
+pub const MAX_GRID_RESOLUTION: u32 = 32;
+const MAX_GRID_CELLS: usize = (MAX_GRID_RESOLUTION * MAX_GRID_RESOLUTION * MAX_GRID_RESOLUTION) as usize;
+const MAX_GRID_INDICES: usize = 1 << 16;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct GridHeader {
+    origin: [f32; 3],
+    enabled: u32,
+    cell_size: [f32; 3],
+    _pad1: f32,
+    resolution: [u32; 3],
+    _pad2: u32,
+}
+
+/// uniform grid bucketing primitive indices by cell, so the marcher only has to look at
+/// the primitives close to the current sample point
+///
+/// the grid is stored like a compressed sparse row matrix, the shader lookup is:
+/// 1. wrap `p.xy` into `[0, INFINITE_REPETITION_PERIOD)` and take `p.z - origin.z`
+/// 2. `cell = floor(local / cell_size)`, points outside the z range hit no primitives
+/// 3. `i = cell.x + res.x * (cell.y + res.y * cell.z)`
+/// 4. the primitives in that cell are `indices[cell_offsets[i]..cell_offsets[i + 1]]`
+///
+/// as the neighbouring cells aren't looked at, each marching step is clamped to the distance
+/// to the current cell's border
+pub struct SpatialGrid {
+    resolution: Option<[u32; 3]>,
+    header_buffer: Buffer,
+    cell_offsets_buffer: Buffer,
+    indices_buffer: Buffer,
+}
+
+impl SpatialGrid {
+    pub fn new(device: &Device) -> Self {
+        let mk_buffer = |label, contents: &[u8]| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                contents,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            })
+        };
+        Self {
+            resolution: None,
+            header_buffer: mk_buffer("Grid Header Buffer", bytemuck::bytes_of(&GridHeader::default())),
+            cell_offsets_buffer: mk_buffer(
+                "Grid Cell Offsets Buffer",
+                bytemuck::cast_slice(&vec![0u32; MAX_GRID_CELLS + 1]),
+            ),
+            indices_buffer: mk_buffer(
+                "Grid Indices Buffer",
+                bytemuck::cast_slice(&vec![0u32; MAX_GRID_INDICES]),
+            ),
+        }
+    }
+
+    /// all three buffers, in bytes, they are allocated at their maximum size up front
+    pub fn memory_usage(&self) -> u64 {
+        self.header_buffer.size() + self.cell_offsets_buffer.size() + self.indices_buffer.size()
+    }
+
+    pub fn resolution(&self) -> Option<[u32; 3]> {
+        self.resolution
+    }
+
+    /// `None` disables the grid, each axis is clamped to `1..=MAX_GRID_RESOLUTION`
+    pub fn set_resolution(&mut self, resolution: Option<[u32; 3]>, queue: &Queue) {
+        self.resolution = resolution.map(|r| r.map(|n| n.clamp(1, MAX_GRID_RESOLUTION)));
+        if self.resolution.is_none() {
+            queue.write_buffer(&self.header_buffer, 0, bytemuck::bytes_of(&GridHeader::default()));
+        }
+    }
+
+    pub(crate) fn bind_group_entries(&self) -> [wgpu::BindGroupEntry<'_>; 3] {
+        [
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: self.header_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: self.cell_offsets_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: self.indices_buffer.as_entire_binding(),
+            },
+        ]
+    }
+
+    /// buckets the primitives and uploads the result, a no-op while the grid is disabled
+    pub fn rebuild(&self, primitives: &[SDFPrimitive], queue: &Queue) {
+        let Some(resolution) = self.resolution else {
+            return;
+        };
+        let (header, cell_offsets, indices) = build(primitives, resolution);
+        if indices.len() > MAX_GRID_INDICES {
+            tracing::warn!(
+                "spatial grid needs {} indices but only has room for {}, falling back to testing every primitive",
+                indices.len(),
+                MAX_GRID_INDICES
+            );
+            queue.write_buffer(&self.header_buffer, 0, bytemuck::bytes_of(&GridHeader::default()));
+            return;
+        }
+        crate::trace_upload!("grid cell offsets", 0, cell_offsets.len() * 4);
+        queue.write_buffer(&self.cell_offsets_buffer, 0, bytemuck::cast_slice(&cell_offsets));
+        if !indices.is_empty() {
+            crate::trace_upload!("grid indices", 0, indices.len() * 4);
+            queue.write_buffer(&self.indices_buffer, 0, bytemuck::cast_slice(&indices));
+        }
+        crate::trace_upload!("grid header", 0, std::mem::size_of::<GridHeader>());
+        queue.write_buffer(&self.header_buffer, 0, bytemuck::bytes_of(&header));
+    }
+}
+
+fn build(primitives: &[SDFPrimitive], resolution: [u32; 3]) -> (GridHeader, Vec<u32>, Vec<u32>) {
+    let (z_min, z_max) = primitives.iter().fold((f32::MAX, f32::MIN), |(lo, hi), p| {
+        let r = p.bounding_radius();
+        (lo.min(p.position[2] - r), hi.max(p.position[2] + r))
+    });
+    let cell_size = [
+        INFINITE_REPETITION_PERIOD / resolution[0] as f32,
+        INFINITE_REPETITION_PERIOD / resolution[1] as f32,
+        ((z_max - z_min) / resolution[2] as f32).max(f32::EPSILON),
+    ];
+    let origin = [0.0, 0.0, z_min];
+
+    // the cells an interval covers along one axis, wrapping around for the repeating axes
+    let cells_on_axis = |axis: usize, center: f32, r: f32| -> Vec<u32> {
+        let res = resolution[axis] as i64;
+        let first = ((center - r - origin[axis]) / cell_size[axis]).floor() as i64;
+        let last = ((center + r - origin[axis]) / cell_size[axis]).floor() as i64;
+        if axis < 2 {
+            if last - first + 1 >= res {
+                (0..res as u32).collect()
+            } else {
+                (first..=last).map(|c| c.rem_euclid(res) as u32).collect()
+            }
+        } else {
+            (first.max(0)..=last.min(res - 1)).map(|c| c as u32).collect()
+        }
+    };
+
+    let cell_count = resolution.iter().product::<u32>() as usize;
+    let mut buckets = vec![Vec::new(); cell_count];
+    for (i, primitive) in primitives.iter().enumerate() {
+        let r = primitive.bounding_radius();
+        let [xs, ys, zs] = [0, 1, 2].map(|axis| cells_on_axis(axis, primitive.position[axis], r));
+        for &z in &zs {
+            for &y in &ys {
+                for &x in &xs {
+                    let cell = x + resolution[0] * (y + resolution[1] * z);
+                    buckets[cell as usize].push(i as u32);
+                }
+            }
+        }
+    }
+
+    let mut cell_offsets = Vec::with_capacity(cell_count + 1);
+    let mut indices = Vec::new();
+    for bucket in buckets {
+        cell_offsets.push(indices.len() as u32);
+        indices.extend(bucket);
+    }
+    cell_offsets.push(indices.len() as u32);
+
+    let header = GridHeader {
+        origin,
+        enabled: 1,
+        cell_size,
+        resolution,
+        ..Default::default()
+    };
+    (header, cell_offsets, indices)
+}
@@ -1,12 +1,18 @@
 use bytemuck::Contiguous;
-use cgmath::{Quaternion, Vector3};
+use cgmath::{Point3, Quaternion, Vector2, Vector3};
 use fastrand;
 use super::BindGroups;
-use super::wgpu::{self, Device, PipelineLayout, ShaderModule};
+use super::wgpu::{self, Device, PipelineLayout, Queue, ShaderModule};
 
 use super::{
-    camera,
+    camera::{self, ContourSettings, DebugMode, EdgeAA},
+    capabilities::CapacityError,
+    culling::CullingPass,
+    environment::Environment,
+    gizmo::{Gizmo, GizmoDrag, GizmoSpace},
     primitives::{self, SDFPrimitive, Typus},
+    replay::{InputEvent, InputPlayer, InputRecorder},
+    viewports::{ViewLayout, ViewRect},
     // Input,
 };
 
@@ -15,10 +21,11 @@ use crate::{x4,x3};
 use self::distance::get_min_dst_to_primitives;
 
 mod distance;
+mod mesh;
 
 pub const VIEW_DST: f32 = 1000.0;
 
-const PRIMITIVE_COUNT: u8 = 10;
+const PRIMITIVE_COUNT: usize = 10;
 
 struct SpawnData {
     last_spawn: std::time::Duration,
@@ -50,6 +57,38 @@ impl SpawnData {
     }
 }
 
+/// accumulates the frame times and hands them out in constant steps,
+/// see `SingleLevelManager::set_fixed_timestep`
+struct FixedTimestep {
+    step: std::time::Duration,
+    accumulator: std::time::Duration,
+}
+
+impl FixedTimestep {
+    /// after a long stall (e.g. the window being dragged) the rest is dropped instead of
+    /// catching up with a burst of steps
+    const MAX_STEPS_PER_FRAME: u32 = 8;
+
+    fn new(step: std::time::Duration) -> Self {
+        Self {
+            step,
+            accumulator: std::time::Duration::ZERO,
+        }
+    }
+
+    /// how many whole steps fit into the accumulated time, the remainder is carried over
+    fn steps(&mut self, dt: std::time::Duration) -> u32 {
+        self.accumulator += dt;
+        let steps = (self.accumulator.as_nanos() / self.step.as_nanos().max(1)) as u32;
+        if steps > Self::MAX_STEPS_PER_FRAME {
+            self.accumulator = std::time::Duration::ZERO;
+            return Self::MAX_STEPS_PER_FRAME;
+        }
+        self.accumulator -= self.step * steps;
+        steps
+    }
+}
+
 // struct Range {
 //     min: f32,
 //     len: f32,
@@ -59,16 +98,56 @@ impl SpawnData {
 //     len: 20.0,
 // };
 
+/// everything `State` needs to build the level's render pipeline and paint it
+pub(crate) struct LevelRendering {
+    pub shader: ShaderModule,
+    pub pipeline_layout: PipelineLayout,
+    pub bind_groups: BindGroups,
+    pub culling: CullingPass,
+    pub environment: Environment,
+}
+
+/// what `SingleLevelManager::pick_at` stops at
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum PickMode {
+    #[default]
+    FirstHit,
+    /// clicks through transparent primitives (alpha below 1, see `SDFPrimitive::is_transparent`),
+    /// e.g. to pick what's behind glass
+    FirstOpaque,
+}
+
 pub(crate) struct SingleLevelManager {
     /// 0.0 - 1.0
     hardness: f32,
     rng: fastrand::Rng,
     pub primitive_manager: primitives::PrimitiveManager,
     pub camera: camera::RenderCamera,
+    /// cameras of the extra split views, `camera` is always the first view, see `State::add_view`
+    pub views: Vec<camera::RenderCamera>,
+    /// the interpupillary distance while rendering side by side stereo, the right eye
+    /// is uploaded into the first of `views`, see `State::set_stereo`
+    pub stereo: Option<f32>,
+    pub gizmo_space: GizmoSpace,
+    /// what hovering, focusing and zooming to the cursor pick
+    pub pick_mode: PickMode,
+    /// the gizmo axis being dragged, see `grab_gizmo_at`
+    gizmo_drag: Option<GizmoDrag>,
     mouse_pressed: bool,
     total_time: std::time::Duration,
     spawn_data: SpawnData,
     pub game_over: bool,
+    fixed_timestep: Option<FixedTimestep>,
+    /// whether the camera is stepped with the primitives or once per frame
+    pub fixed_timestep_camera: bool,
+    /// see `set_time_scale`
+    time_scale: f32,
+    /// whether `time_scale` slows the camera down too
+    pub time_scale_camera: bool,
+    /// see `process_input`
+    pub input_recorder: Option<InputRecorder>,
+    /// replaces the live input while set, see `process_input`
+    pub input_player: Option<InputPlayer>,
 }
 
 impl SingleLevelManager {
@@ -76,13 +155,35 @@ impl SingleLevelManager {
         hardness: f32,
         seed: u64,
         device: &Device,
+        queue: &Queue,
         size: (u32, u32),
-    ) -> (Self, ShaderModule, PipelineLayout, BindGroups) {
+    ) -> Result<(Self, LevelRendering), CapacityError> {
         assert!(hardness >= 0.0 && hardness <= 1.0);
         let rng = fastrand::Rng::with_seed(seed);
-        let (primitive_manager, primitives_bind_group) = primitives::PrimitiveManager::new(&device, PRIMITIVE_COUNT);
-        let (camera, camera_bind_group) = camera::RenderCamera::new(device, size, VIEW_DST);
-        let shader = device.create_shader_module(wgpu::include_wgsl!("level/main_shader.wgsl"));
+        // the culling pre-pass reads both from a compute shader
+        let visibility = wgpu::ShaderStages::VERTEX_FRAGMENT | wgpu::ShaderStages::COMPUTE;
+        let (primitive_manager, primitives_bind_group) =
+            primitives::PrimitiveManager::with_visibility(&device, PRIMITIVE_COUNT, visibility)?;
+        let (camera, camera_bind_group) =
+            camera::RenderCamera::with_visibility(device, size, VIEW_DST, visibility);
+        let (culling, visible_bind_group) = CullingPass::new(
+            device,
+            &primitive_manager.bind_group_layout,
+            &camera.bind_group_layout,
+            primitive_manager.len(),
+        );
+        let (environment, environment_bind_group) = Environment::new(device, queue);
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Main Shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                concat!(
+                    include_str!("level/common.wgsl"),
+                    include_str!("level/environment.wgsl"),
+                    include_str!("level/main_shader.wgsl")
+                )
+                .into(),
+            ),
+        });
         let render_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("Render Pipeline Layout"),
@@ -90,27 +191,46 @@ impl SingleLevelManager {
                     &primitive_manager.bind_group_layout,
                     // &texture_bind_group_layout,
                     &camera.bind_group_layout,
+                    &culling.render_bind_group_layout,
+                    &environment.bind_group_layout,
                 ],
                 push_constant_ranges: &[],
             });
-        (
+        Ok((
             Self {
                 hardness,
                 primitive_manager,
                 camera,
+                views: Vec::new(),
+                stereo: None,
+                gizmo_space: GizmoSpace::default(),
+                pick_mode: PickMode::default(),
+                gizmo_drag: None,
                 mouse_pressed: false,
                 total_time: std::time::Duration::from_secs(0),
                 spawn_data: SpawnData::new(rng.clone()),
                 rng,
                 game_over: false,
+                fixed_timestep: None,
+                fixed_timestep_camera: false,
+                time_scale: 1.0,
+                time_scale_camera: false,
+                input_recorder: None,
+                input_player: None,
             },
-            shader,
-            render_pipeline_layout,
-            BindGroups {
-                primitives_bind_group,
-                camera_bind_group,
+            LevelRendering {
+                shader,
+                pipeline_layout: render_pipeline_layout,
+                bind_groups: BindGroups {
+                    primitives_bind_group,
+                    camera_bind_group,
+                    visible_bind_group,
+                    environment_bind_group,
+                },
+                culling,
+                environment,
             },
-        )
+        ))
     }
 
     pub fn start(&mut self, queue: &wgpu::Queue) {
@@ -132,16 +252,229 @@ impl SingleLevelManager {
     }
 
     pub fn move_by(&mut self, delta: Vector3<f32>) {
-        self.camera.controller.move_by(delta, &mut self.camera.camera); //weird controller but heyy
+        self.process_input(InputEvent::Move { delta: delta.into() }); //weird controller but heyy
+    }
+
+    /// hands live input to the camera, recording it while `input_recorder` is set.
+    /// ignored while an `input_player` plays a recording back
+    pub fn process_input(&mut self, event: InputEvent) {
+        if self.input_player.is_some() {
+            return;
+        }
+        if let Some(recorder) = &mut self.input_recorder {
+            recorder.record(event);
+        }
+        event.apply(&mut self.camera);
+    }
+
+    /// splits the targets of `size` between `camera` and the extra `views`
+    pub fn resize(&mut self, size: (u32, u32), layout: ViewLayout) {
+        if self.stereo.is_some() {
+            // `stereo_pair` splits the main camera's viewport itself
+            self.camera.projection.set_viewport(ViewRect::full(size));
+            return;
+        }
+        let rects = layout.rects(1 + self.views.len(), size);
+        let cameras = std::iter::once(&mut self.camera).chain(self.views.iter_mut());
+        for (camera, rect) in cameras.zip(rects) {
+            camera.projection.set_viewport(rect);
+        }
+    }
+
+    /// the primitive under `pixel` (physical pixels from the top left of the view)
+    /// and the point where the ray hits it
+    pub fn pick_at(&self, pixel: Vector2<f32>, mode: PickMode) -> Option<(usize, Point3<f32>)> {
+        let ray = self.camera.screen_ray(pixel);
+        let hit = match mode {
+            PickMode::FirstHit => distance::march(&ray, &self.primitive_manager.primitives),
+            PickMode::FirstOpaque => distance::march_through(
+                &ray,
+                &self.primitive_manager.primitives,
+                SDFPrimitive::is_transparent,
+            ),
+        };
+        hit.map(|(dst, index)| (index, ray.origin + ray.direction * dst))
+    }
+
+    /// orbits around and frames the primitive under `pixel`, returns whether there was one
+    pub fn focus_at(&mut self, pixel: Vector2<f32>) -> bool {
+        let Some((index, _)) = self.pick_at(pixel, self.pick_mode) else {
+            return false;
+        };
+        self.camera.focus_on(&self.primitive_manager.primitives[index]);
+        true
+    }
+
+    /// highlights the primitive under `pixel` (nothing if there is none), for hover feedback
+    pub fn highlight_at(&mut self, pixel: Vector2<f32>, queue: &wgpu::Queue) {
+        let hovered = self.pick_at(pixel, self.pick_mode).map(|(index, _)| index);
+        self.primitive_manager.set_highlighted(hovered, queue);
     }
 
-    pub fn resize(&mut self, size: (u32, u32)) {
-        self.camera.resize(size.0, size.1);
+    /// the selection's axes as seen from the main camera
+    pub fn gizmo(&self) -> Option<Gizmo> {
+        Gizmo::for_selection(&self.primitive_manager, &self.camera, self.gizmo_space)
     }
+
+    /// starts dragging the gizmo axis under `pixel`, returns whether there was one
+    pub fn grab_gizmo_at(&mut self, pixel: Vector2<f32>) -> bool {
+        let ray = self.camera.screen_ray(pixel);
+        self.gizmo_drag = self.gizmo().and_then(|gizmo| GizmoDrag::grab(&gizmo, &ray));
+        self.gizmo_drag.is_some()
+    }
+
+    /// moves the selection along the grabbed axis to follow the cursor
+    pub fn drag_gizmo_to(&mut self, pixel: Vector2<f32>, queue: &wgpu::Queue) {
+        let Some(drag) = &mut self.gizmo_drag else {
+            return;
+        };
+        let delta = drag.drag(&self.camera.screen_ray(pixel));
+        self.primitive_manager.translate_selection(delta, queue);
+    }
+
+    pub fn release_gizmo(&mut self) {
+        self.gizmo_drag = None;
+    }
+
+    pub fn is_dragging_gizmo(&self) -> bool {
+        self.gizmo_drag.is_some()
+    }
+
+    /// scrolling with the cursor at `pixel`, the orbit camera's zoom-to-cursor
+    /// needs to know what's under it
+    pub fn scroll_at(&mut self, delta: camera::ScrollDelta, pixel: Vector2<f32>) {
+        let cursor_hit = match &self.camera.orbit {
+            Some(orbit) if orbit.zoom_to_cursor => {
+                self.pick_at(pixel, self.pick_mode).map(|(_, point)| point)
+            }
+            _ => None,
+        };
+        self.process_input(InputEvent::Scroll {
+            delta,
+            cursor_hit: cursor_hit.map(Into::into),
+        });
+    }
+    /// advances the simulation in constant steps of `step` instead of the frame time,
+    /// which makes the animation reproducible (e.g. for recordings), `None` goes back to
+    /// one variable step per frame
+    ///
+    /// the camera keeps its variable step unless `fixed_timestep_camera` is set
+    pub fn set_fixed_timestep(&mut self, step: Option<std::time::Duration>) {
+        self.fixed_timestep = step.map(FixedTimestep::new);
+    }
+
+    pub fn fixed_timestep(&self) -> Option<std::time::Duration> {
+        self.fixed_timestep.as_ref().map(|fixed_timestep| fixed_timestep.step)
+    }
+
+    /// the marcher's step limit for all views, uploaded with the next `update`
+    pub fn set_max_steps(&mut self, max_steps: u32) {
+        for camera in std::iter::once(&mut self.camera).chain(self.views.iter_mut()) {
+            camera.uniform.max_steps = max_steps;
+        }
+    }
+
+    /// the contour shading of all views, uploaded with the next `update`
+    pub fn set_contour(&mut self, contour: ContourSettings) {
+        for camera in std::iter::once(&mut self.camera).chain(self.views.iter_mut()) {
+            camera.uniform.contour = contour;
+        }
+    }
+
+    /// the silhouette antialiasing of all views, uploaded with the next `update`
+    pub fn set_edge_aa(&mut self, edge_aa: EdgeAA) {
+        for camera in std::iter::once(&mut self.camera).chain(self.views.iter_mut()) {
+            camera.uniform.edge_aa = edge_aa;
+        }
+    }
+
+    /// what all views show instead of the shading, uploaded with the next `update`
+    pub fn set_debug_mode(&mut self, debug_mode: DebugMode) {
+        for camera in std::iter::once(&mut self.camera).chain(self.views.iter_mut()) {
+            camera.uniform.set_debug_mode(debug_mode);
+        }
+    }
+
+    /// slow motion below 1, fast forward above, 0 freezes the animation. every simulation
+    /// clock (movement, spawning, fixed steps) runs on the scaled time, the camera keeps
+    /// the real time unless `time_scale_camera` is set, so it can fly around a frozen scene
+    pub fn set_time_scale(&mut self, time_scale: f32) {
+        self.time_scale = time_scale.max(0.0);
+    }
+
+    pub fn time_scale(&self) -> f32 {
+        self.time_scale
+    }
+
+    /// the level's clock: the sum of the (scaled) steps since `start`, stands still while
+    /// paused. use this rather than summing `dt` yourself
+    pub fn elapsed(&self) -> std::time::Duration {
+        self.total_time
+    }
+
+    /// the only place the frame time is scaled
     pub fn update(&mut self, dt: std::time::Duration, queue: &wgpu::Queue) {
+        self.replay_input(dt);
+        self.camera.set_scene_bounds(self.primitive_manager.bounding_sphere());
+        let scaled_dt = dt.mul_f32(self.time_scale);
+        let camera_dt = if self.time_scale_camera { scaled_dt } else { dt };
+        let Some(fixed_timestep) = &mut self.fixed_timestep else {
+            self.step(scaled_dt, queue, Some(camera_dt));
+            self.update_views(camera_dt, queue);
+            return;
+        };
+        let (step, steps) = (fixed_timestep.step, fixed_timestep.steps(scaled_dt));
+        if !self.fixed_timestep_camera {
+            self.camera.update(camera_dt, queue);
+        }
+        self.update_views(camera_dt, queue);
+        for _ in 0..steps {
+            self.step(step, queue, self.fixed_timestep_camera.then_some(step));
+        }
+    }
+
+    /// plays the input recorded before this update back, and moves the recorder's clock
+    /// past it. both use the unscaled frame time
+    fn replay_input(&mut self, dt: std::time::Duration) {
+        if let Some(recorder) = &mut self.input_recorder {
+            recorder.advance(dt);
+        }
+        let Some(player) = &mut self.input_player else {
+            return;
+        };
+        for input in player.advance(dt) {
+            input.event.apply(&mut self.camera);
+        }
+        if player.is_finished() {
+            self.input_player = None;
+        }
+    }
+
+    fn update_views(&mut self, dt: std::time::Duration, queue: &wgpu::Queue) {
+        if let (Some(ipd), Some(right_eye)) = (self.stereo, self.views.first()) {
+            let (left, right) = self.camera.stereo_pair(ipd);
+            self.camera.write_uniform(queue, &left);
+            right_eye.write_uniform(queue, &right);
+            return;
+        }
+        for view in self.views.iter_mut() {
+            view.update(dt, queue);
+        }
+    }
+
+    /// moves the primitives (and the camera by `camera_dt` if given), spawns new ones and checks for collisions
+    fn step(
+        &mut self,
+        dt: std::time::Duration,
+        queue: &wgpu::Queue,
+        camera_dt: Option<std::time::Duration>,
+    ) {
         self.total_time += dt;
+        self.primitive_manager.set_view_position(self.camera.camera.position);
         self.primitive_manager.update(dt, queue);
-        self.camera.update(dt, queue);
+        if let Some(camera_dt) = camera_dt {
+            self.camera.update(camera_dt, queue);
+        }
         if self.spawn_data.should_spawn(dt, self.hardness) {
             if let Some(ref mut primitive) = self.primitive_manager.get_spawnable_primitive() {
                 let params = &RespawnParams {
@@ -245,6 +578,7 @@ fn respawn_primitive(params: &RespawnParams, primitive: &mut SDFPrimitive) {
             .into();
         primitive.place_in_spawn_area(rng);
         primitive.rgba = x4!(rng.f32());
+        primitive.rgba[3] = 1.0; // opaque, see `SDFPrimitive::is_transparent`
         let max_len = primitive.data.iter().fold(f32::MIN, |a, &b| a.max(b));
         const DISTANCE_FACTOR: f32 = 3.5;
         if max_len*3.0+DISTANCE_FACTOR*2.0 < VIEW_DST / 3.0 { //safety distance to prevent artifacts
@@ -256,7 +590,7 @@ fn respawn_primitive(params: &RespawnParams, primitive: &mut SDFPrimitive) {
         } else {
             primitive.instances = x3!(0);
         }
-        // primitive.twist = rng.f32()*5.0; //FIXME: doesnt work yet, disabled in shader rn
+        // primitive.set_twist(rng.f32()*0.05);
         
         //these integers are not in line with the ones used for enum representation, but that doesn't matter here
         match rng.u32(..=Typus::MAX_VALUE) {
@@ -0,0 +1,29 @@
+// copies the (post-processed) offscreen color target into egui's render pass
+
+@group(0) @binding(0)
+var source_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var source_sampler: sampler;
+
+// see `Blit::set_dither`
+struct BlitSettings {
+    dither: u32,
+}
+@group(0) @binding(2)
+var<uniform> settings: BlitSettings;
+
+// interleaved gradient noise, cheap and close enough to blue noise to hide banding
+fn dither_noise(pixel: vec2<f32>) -> f32 {
+    return fract(52.9829189 * fract(dot(pixel, vec2<f32>(0.06711056, 0.00583715))));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(source_texture, source_sampler, in.uv);
+    if (settings.dither == 0u) {
+        return color;
+    }
+    // up to half an 8 bit step in either direction
+    let noise = (dither_noise(in.clip_position.xy) - 0.5) / 255.0;
+    return vec4<f32>(color.rgb + noise, color.a);
+}
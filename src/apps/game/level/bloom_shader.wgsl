@@ -0,0 +1,58 @@
+// bloom, bright pixels are extracted into a half resolution target, blurred with a
+// separable gaussian and added back onto the color (see `BloomPass` in bloom.rs)
+
+struct BloomSettings {
+    threshold: f32,
+    intensity: f32,
+    // standard deviation of the blur in half resolution pixels
+    radius: f32,
+}
+@group(0) @binding(0)
+var source_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var source_sampler: sampler;
+@group(0) @binding(2)
+var<uniform> bloom: BloomSettings;
+
+// the linear sampler averages 2x2 source pixels on the way down
+@fragment
+fn fs_extract(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(source_texture, source_sampler, in.uv).rgb;
+    let brightness = max(color.r, max(color.g, color.b));
+    // soft knee, only what's above the threshold glows
+    let contribution = max(brightness - bloom.threshold, 0.0) / max(brightness, 0.0001);
+    return vec4<f32>(color * contribution, 1.0);
+}
+
+const max_blur_taps = 32;
+fn blur(uv: vec2<f32>, direction: vec2<f32>) -> vec4<f32> {
+    let texel = direction / vec2<f32>(textureDimensions(source_texture));
+    let sigma = max(bloom.radius, 0.5);
+    let taps = min(i32(ceil(sigma * 3.0)), max_blur_taps);
+    var color = vec3<f32>(0.0);
+    var total = 0.0;
+    for (var i = -taps; i <= taps; i = i + 1) {
+        let x = f32(i);
+        let weight = exp(-x * x / (2.0 * sigma * sigma));
+        color = color + textureSampleLevel(source_texture, source_sampler, uv + texel * x, 0.0).rgb * weight;
+        total = total + weight;
+    }
+    return vec4<f32>(color / total, 1.0);
+}
+
+@fragment
+fn fs_blur_horizontal(in: VertexOutput) -> @location(0) vec4<f32> {
+    return blur(in.uv, vec2<f32>(1.0, 0.0));
+}
+
+@fragment
+fn fs_blur_vertical(in: VertexOutput) -> @location(0) vec4<f32> {
+    return blur(in.uv, vec2<f32>(0.0, 1.0));
+}
+
+// blended additively onto the color target
+@fragment
+fn fs_composite(in: VertexOutput) -> @location(0) vec4<f32> {
+    let glow = textureSample(source_texture, source_sampler, in.uv).rgb;
+    return vec4<f32>(glow * bloom.intensity, 0.0);
+}
@@ -0,0 +1,181 @@
+// shared by every shader that reads the level's primitives and camera,
+// prepended via `concat!` so the layouts only live in one place
+
+struct Primitive {
+    position: vec3<f32>,
+    _speed: f32,
+    rotation: vec4<f32>,
+    _rotation_delta: vec4<f32>,
+    // per typus: box frame half extents and girth, ellipsoid radii, octahedron size in x,
+    // chain link length, arc radius and girth (see `SDFPrimitive::data`)
+    data: vec4<f32>,
+    instances: vec3<u32>,
+    instances_distance: f32,
+    rgba: vec4<f32>,
+    typus: u32,
+    twist: f32,
+    rounding: f32,
+    displacement: f32,
+    noise_scale: f32,
+    metalness: f32,
+    // 0 is full detail, see `PrimitiveManager::set_lod`
+    lod: u32,
+    bend: f32,
+    // bit 0 highlighted, bit 1 selected, see `SDFPrimitive::HIGHLIGHTED`
+    flags: u32,
+    // bit 0 color blend, see `SDFPrimitive::COLOR_BLEND`
+    operation: u32,
+    // `palette` color replacing rgba's rgb, see `SDFPrimitive::set_palette_index`
+    palette_index: u32,
+    // seconds added to the animation clock, see `SDFPrimitive::set_phase`
+    phase: f32,
+    // added unlit where the primitive is hit, see `SDFPrimitive::set_emissive`
+    emissive: vec3<f32>,
+    // 0 is solid, see `SDFPrimitive::set_shell_thickness`
+    shell_thickness: f32,
+    // half size of the box around the instance grid, see `SDFPrimitive::instance_extent`
+    instance_extent: vec3<f32>,
+    // rows of the world to local transform, see `SDFPrimitive::from_matrix`
+    inverse_transform: array<vec4<f32>, 3>,
+    // 0 uses position and rotation, otherwise the distance is divided by it
+    transform_stretch: f32,
+    // scales the bounds while the transform is used
+    forward_stretch: f32,
+    // atlas layer projected onto the primitive, see `SDFPrimitive::set_texture`
+    texture_index: u32,
+    texture_scale: f32,
+    // blend_strength: f32,
+}
+
+struct Primitives {
+    // length: u32,
+    prims: array<Primitive>,
+}
+
+@group(0) @binding(0)
+var<storage, read> primitives: Primitives;
+
+// spatial acceleration grid, see `SpatialGrid` in grid.rs for the layout
+struct GridHeader {
+    origin: vec3<f32>,
+    enabled: u32,
+    cell_size: vec3<f32>,
+    resolution: vec3<u32>,
+}
+struct GridCellOffsets {
+    offsets: array<u32>,
+}
+struct GridIndices {
+    indices: array<u32>,
+}
+@group(0) @binding(1)
+var<storage, read> grid: GridHeader;
+@group(0) @binding(2)
+var<storage, read> grid_cells: GridCellOffsets;
+@group(0) @binding(3)
+var<storage, read> grid_indices: GridIndices;
+
+// primitives from `transparent_start` on are translucent and sorted far to near,
+// see `PrimitiveManager::set_transparency`
+struct PrimitiveOrder {
+    transparent_start: u32,
+    // how many primitives `calc_step` folds at most, see `PrimitiveManager::set_complexity_budget`
+    max_primitives_per_pixel: u32,
+    // bit 0 position, bit 1 rgba, see `DynamicChannels` in primitives.rs
+    dynamic_channels: u32,
+}
+@group(0) @binding(4)
+var<storage, read> order: PrimitiveOrder;
+
+// the dynamic channels, the slots' positions (xyz) followed by their colors, see
+// `PrimitiveManager::set_dynamic_channels`
+@group(0) @binding(8)
+var<storage, read> channels: array<vec4<f32>>;
+
+// the primitive in buffer slot `slot`, its dynamic channels taken from `channels`
+fn load_primitive(slot: u32) -> Primitive {
+    var prim = primitives.prims[slot];
+    if ((order.dynamic_channels & 1u) != 0u) {
+        prim.position = channels[slot].xyz;
+    }
+    if ((order.dynamic_channels & 2u) != 0u) {
+        prim.rgba = channels[arrayLength(&primitives.prims) + slot];
+    }
+    return prim;
+}
+
+// colors shared by the primitives, see `PaletteManager` in palette.rs
+struct Palette {
+    count: u32,
+    colors: array<vec4<f32>, 256>,
+}
+@group(0) @binding(5)
+var<uniform> palette: Palette;
+
+// the palette color if the primitive has a valid index, keeping its own alpha
+fn primitive_rgba(prim: Primitive) -> vec4<f32> {
+    if (prim.palette_index < palette.count) {
+        return vec4<f32>(palette.colors[prim.palette_index].rgb, prim.rgba.a);
+    }
+    return prim.rgba;
+}
+
+// see `ContourSettings` in camera.rs
+struct ContourSettings {
+    frequency: f32,
+    enabled: u32,
+}
+
+// see `EdgeAA` in camera.rs
+struct EdgeAA {
+    width: f32,
+    enabled: u32,
+    // of a pixel in world units, per unit of distance in perspective
+    pixel_size: f32,
+}
+
+struct CameraUniform {
+    view_position: vec4<f32>,
+    // view_proj: mat4x4<f32>,
+    // inverse_proj: mat4x4<f32>,
+    // cam_to_world: mat4x4<f32>,
+    world_to_screen: mat4x4<f32>,
+    screen_to_world: mat4x4<f32>,
+    pixel_normalization: mat4x4<f32>,
+    // last frame's `world_to_screen`, for temporal effects
+    prev_world_to_screen: mat4x4<f32>,
+    effect: u32,
+    // 0 perspective, 1 orthographic, see `ProjectionKind` in camera.rs
+    projection_kind: u32,
+    // adapted to the frame time, see `AdaptiveSteps` in quality.rs
+    max_steps: u32,
+    // 0 off, 1 step count, 2 distance, 3 normals, see `DebugMode` in camera.rs
+    debug_mode: u32,
+    contour: ContourSettings,
+    @align(16) edge_aa: EdgeAA,
+};
+@group(1) @binding(0) // 1.
+var<uniform> camera: CameraUniform;
+
+struct Ray {
+    origin: vec3<f32>,
+    direction: vec3<f32>,
+}
+
+fn unproject(screen: vec2<f32>, depth: f32) -> vec3<f32> {
+    let world = camera.screen_to_world * vec4<f32>(screen, depth, 1.0);
+    return world.xyz / world.w;
+}
+
+// perspective rays all start at the eye, orthographic ones are parallel and start on the
+// near plane, has to match `RenderCamera::screen_ray` in camera.rs
+fn camera_ray(screen: vec2<f32>) -> Ray {
+    let near = unproject(screen, 0.0);
+    if (camera.projection_kind == 1u) {
+        return Ray(near, normalize(unproject(screen, 1.0) - near));
+    }
+    let origin = camera.view_position.xyz;
+    return Ray(origin, normalize(near - origin));
+}
+
+const max_distance = 1000.0;
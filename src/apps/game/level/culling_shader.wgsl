@@ -0,0 +1,74 @@
+// frustum culling pre-pass, compacts the indices of all primitives that might be hit
+// by a camera ray into `visible.indices` (see `get_ith_primitive` in main_shader.wgsl)
+
+// same buffer as `VisiblePrimitives` in main_shader.wgsl, but writable
+struct VisiblePrimitivesRW {
+    count: atomic<u32>,
+    enabled: u32,
+    indices: array<u32>,
+}
+@group(2) @binding(0)
+var<storage, read_write> visible: VisiblePrimitivesRW;
+
+const infinite_repition_period = 1000.0;
+
+// conservative, works for every typus since none of them reaches further than the sum of its data
+fn bounding_radius(primitive: Primitive) -> f32 {
+    let d = abs(primitive.data);
+    let base = d.x + d.y + d.z + d.w + max(primitive.rounding, 0.0) + max(primitive.shell_thickness, 0.0)
+        + abs(primitive.displacement);
+    let grid = primitive.instances_distance * length(vec3<f32>(primitive.instances));
+    return (base + grid) * select(1.0, primitive.forward_stretch, primitive.transform_stretch > 0.0);
+}
+
+fn frustum_row(i: u32) -> vec4<f32> {
+    let m = camera.world_to_screen;
+    return vec4<f32>(m[0][i], m[1][i], m[2][i], m[3][i]);
+}
+
+// the marcher starts at the eye and stops at `max_distance`, so the near plane is
+// irrelevant and the far plane is replaced by a distance check
+fn sphere_visible(center: vec3<f32>, radius: f32) -> bool {
+    if (length(center - camera.view_position.xyz) - radius > max_distance) {
+        return false;
+    }
+    let r0 = frustum_row(0u);
+    let r1 = frustum_row(1u);
+    let r3 = frustum_row(3u);
+    var planes = array<vec4<f32>, 4>(r3 + r0, r3 - r0, r3 + r1, r3 - r1);
+    for (var i = 0u; i < 4u; i = i + 1u) {
+        let plane = planes[i] / length(planes[i].xyz);
+        if (dot(plane.xyz, center) + plane.w < -radius) {
+            return false;
+        }
+    }
+    return true;
+}
+
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let i = id.x;
+    if (i >= arrayLength(&primitives.prims)) {
+        return;
+    }
+    let primitive = load_primitive(i);
+    let radius = bounding_radius(primitive);
+    // primitives repeat infinitely along x and y, so every copy in reach has to be checked
+    let reach = i32(ceil((max_distance + radius) / infinite_repition_period));
+    let eye = camera.view_position.xy;
+    let nearest = primitive.position.xy
+        + round((eye - primitive.position.xy) / infinite_repition_period) * infinite_repition_period;
+    for (var x = -reach; x <= reach; x = x + 1) {
+        for (var y = -reach; y <= reach; y = y + 1) {
+            let offset = vec2<f32>(f32(x), f32(y)) * infinite_repition_period;
+            if (sphere_visible(vec3<f32>(nearest + offset, primitive.position.z), radius)) {
+                let slot = atomicAdd(&visible.count, 1u);
+                // the list is sized with the primitives buffer, but never write past it
+                if (slot < arrayLength(&visible.indices)) {
+                    visible.indices[slot] = i;
+                }
+                return;
+            }
+        }
+    }
+}
@@ -1,11 +1,11 @@
 use cgmath::{
-     BaseFloat, ElementWise, InnerSpace, Quaternion, Rotation, Vector2,
+     BaseFloat, ElementWise, InnerSpace, Point3, Quaternion, Rotation, Vector2,
     Vector3, 
 };
 // use winit::dpi::Pixel;
 
-use super::{primitives::{SDFPrimitive, Typus}, PRIMITIVE_COUNT};
-static mut NUMBERS: &'static mut [bool] = &mut [false;PRIMITIVE_COUNT as usize];
+use super::{camera::Ray, primitives::{SDFPrimitive, Typus}, PRIMITIVE_COUNT, VIEW_DST};
+static mut NUMBERS: &'static mut [bool] = &mut [false;PRIMITIVE_COUNT];
 
 //TODO: this is not working! sometimes it does, most times it doesn't
 pub fn get_min_dst_to_primitives(
@@ -55,6 +55,58 @@ pub fn get_min_dst_to_primitives(
     min_dst
 }
 
+// same step budget and hit threshold as `march` in main_shader.wgsl, just with more steps
+// as a missed pick is more annoying than a frame's artifact
+const MAX_STEPS: u32 = 128;
+const EPSILON: f32 = 1.0;
+
+/// cpu version of the marcher, returns the distance along the ray and the index of
+/// the primitive that was hit, `None` if the ray leaves the view distance first
+pub fn march(ray: &Ray, primitives: &[SDFPrimitive]) -> Option<(f32, usize)> {
+    march_through(ray, primitives, |_| false)
+}
+
+/// like `march`, but the ray passes through the primitives `see_through` is true for
+pub fn march_through(
+    ray: &Ray,
+    primitives: &[SDFPrimitive],
+    see_through: impl Fn(&SDFPrimitive) -> bool,
+) -> Option<(f32, usize)> {
+    let mut dst = 0.0;
+    for _ in 0..MAX_STEPS {
+        let point = ray.origin + ray.direction * dst;
+        let (step, closest) = closest_matching(point, primitives, &see_through)?;
+        if step < EPSILON {
+            return Some((dst, closest));
+        }
+        dst += step;
+        if dst > VIEW_DST {
+            break;
+        }
+    }
+    None
+}
+
+pub fn closest_primitive(point: Point3<f32>, primitives: &[SDFPrimitive]) -> Option<(f32, usize)> {
+    closest_matching(point, primitives, |_| false)
+}
+
+/// `closest_primitive` of the primitives `skip` is false for, keeping their indices
+fn closest_matching(
+    point: Point3<f32>,
+    primitives: &[SDFPrimitive],
+    skip: impl Fn(&SDFPrimitive) -> bool,
+) -> Option<(f32, usize)> {
+    // same offset as in `get_min_dst_to_primitives`, the shader's repetition is shifted by half a period
+    let point = Vector3::new(point.x - 500.0, point.y - 500.0, point.z);
+    primitives
+        .iter()
+        .enumerate()
+        .filter(|(_, primitive)| !skip(primitive))
+        .map(|(i, primitive)| (distance_to_primitive(point, primitive), i))
+        .min_by(|a, b| a.0.total_cmp(&b.0))
+}
+
 fn distance_to_primitive(from_point: Vector3<f32>, primitive: &SDFPrimitive) -> f32 {
     let infinite_repetition_period = Vector2::new(1000.0, 1000.0);
     //translate to primitive space
@@ -67,17 +119,14 @@ fn distance_to_primitive(from_point: Vector3<f32>, primitive: &SDFPrimitive) ->
     );
     let mod_point = Vector3::new(x, y, q.z);
     q = mod_point;
-    //rotate to primitive space
-    let relative_point = fast_inverse_qrotate_vector(primitive.rotation, q);
+    //rotate to primitive space, or transform with `SDFPrimitive::from_matrix`
+    let (relative_point, stretch) = if primitive.uses_matrix() {
+        (primitive.inverse_linear() * q, primitive.transform_stretch)
+    } else {
+        (fast_inverse_qrotate_vector(primitive.rotation, q), 1.0)
+    };
     q = relative_point;
     //// let relative_point = qrotate_vector(qinverse(primitive.rotation),from_point) - qrotate_vector(qinverse(primitive.rotation),primitive.position);
-    // // twisting //FIXME: this is not working, it brings enourmous amounts of noise
-    // let twist = primitive.twist;
-    // let cos_twist = cos(twist*q.y);
-    // let sin_twist = sin(twist*q.y);
-    // let twist_matrix = mat2x2(cos_twist,-sin_twist,sin_twist,cos_twist);
-    // let twisted_point = Vector3(twist_matrix*q.xz,q.y);
-    // q = twisted_point;
     // finite instancing
     let dis = q
         .div_element_wise(vec3_from(primitive.instances_distance))
@@ -91,13 +140,43 @@ fn distance_to_primitive(from_point: Vector3<f32>, primitive: &SDFPrimitive) ->
         q - vec3_from(primitive.instances_distance).mul_element_wise( clamp_element_wise(dis, ZERO_VEC3 - bound, bound));
     //// dst = distance_to_box_frame(relative_point_q, primitive.data);
     q = instanced_point;
+    q = deform(q, primitive);
     let dst = match primitive.typus {
         Typus::BoxFrame => distance_to_box_frame(q, primitive.data),
         Typus::Ellipsoid => distance_to_ellipsoid(q, primitive.data),
         Typus::Octahedron => distance_to_octahedron(q, primitive.data),
         Typus::ChainLink => distance_to_chain_link(q, primitive.data),
     };
-    dst
+    let displaced = if primitive.displacement != 0.0 {
+        dst + primitive.displacement * value_noise(q * primitive.noise_scale)
+    } else {
+        dst
+    };
+    let rounded = displaced / deformation_stretch(primitive) - primitive.rounding;
+    let dst = if primitive.shell_thickness > 0.0 {
+        rounded.abs() - primitive.shell_thickness
+    } else {
+        rounded
+    };
+    dst / stretch
+}
+
+// twist and bend, mirrors `deform` in main_shader.wgsl
+fn deform(mut q: Vector3<f32>, primitive: &SDFPrimitive) -> Vector3<f32> {
+    if primitive.twist != 0.0 {
+        let (sin, cos) = (primitive.twist * q.y).sin_cos();
+        q = Vector3::new(cos * q.x - sin * q.z, q.y, sin * q.x + cos * q.z);
+    }
+    if primitive.bend != 0.0 {
+        let (sin, cos) = (primitive.bend * q.x).sin_cos();
+        q = Vector3::new(cos * q.x - sin * q.y, sin * q.x + cos * q.y, q.z);
+    }
+    q
+}
+
+fn deformation_stretch(primitive: &SDFPrimitive) -> f32 {
+    let r: f32 = primitive.data.iter().map(|d| d.abs()).sum();
+    1.0 + (primitive.twist.abs() + primitive.bend.abs()) * r
 }
 
 const ZERO_VEC3: Vector3<f32> = Vector3::new(0.0, 0.0, 0.0);
@@ -159,6 +238,25 @@ fn distance_to_chain_link(from_point: Vector3<f32>, chain_data: [f32; 4]) -> f32
     return Vector2::new(Vector2::new(q.x, q.y).magnitude() - arc_radius, q.z).magnitude() - girth;
 }
 
+fn hash(p: Vector3<f32>) -> f32 {
+    let h = (p.dot(Vector3::new(127.1, 311.7, 74.7))).sin() * 43758.5453;
+    h - h.floor()
+}
+
+// trilinearly interpolated hash noise in [-1, 1], has to match `value_noise` in main_shader.wgsl
+fn value_noise(p: Vector3<f32>) -> f32 {
+    let i = Vector3::new(p.x.floor(), p.y.floor(), p.z.floor());
+    let f = p - i;
+    let u = f.mul_element_wise(f).mul_element_wise(vec3_from(3.0) - 2.0 * f);
+    let mix = |a: f32, b: f32, t: f32| a + (b - a) * t;
+    let corner = |x: f32, y: f32, z: f32| hash(i + Vector3::new(x, y, z));
+    let x00 = mix(corner(0.0, 0.0, 0.0), corner(1.0, 0.0, 0.0), u.x);
+    let x10 = mix(corner(0.0, 1.0, 0.0), corner(1.0, 1.0, 0.0), u.x);
+    let x01 = mix(corner(0.0, 0.0, 1.0), corner(1.0, 0.0, 1.0), u.x);
+    let x11 = mix(corner(0.0, 1.0, 1.0), corner(1.0, 1.0, 1.0), u.x);
+    mix(mix(x00, x10, u.y), mix(x01, x11, u.y), u.z) * 2.0 - 1.0
+}
+
 fn fast_inverse_qrotate_vector(r: [f32; 4], v: Vector3<f32>) -> Vector3<f32> {
     // let rr = r / dot(r, r);
     // let xyz = v * rr.w + v.cross(rr.xyz);
@@ -0,0 +1,21 @@
+// optional equirectangular background, see `Environment` in environment.rs
+struct EnvironmentSettings {
+    enabled: u32,
+    intensity: f32,
+}
+@group(3) @binding(0)
+var environment_texture: texture_2d<f32>;
+@group(3) @binding(1)
+var environment_sampler: sampler;
+@group(3) @binding(2)
+var<uniform> environment: EnvironmentSettings;
+
+fn sample_environment(direction: vec3<f32>) -> vec4<f32> {
+    let pi = 3.14159265359;
+    let uv = vec2<f32>(
+        atan2(direction.z, direction.x) / (2.0 * pi) + 0.5,
+        acos(clamp(direction.y, -1.0, 1.0)) / pi,
+    );
+    // explicit level as this is called from non-uniform control flow
+    return textureSampleLevel(environment_texture, environment_sampler, uv, 0.0) * environment.intensity;
+}
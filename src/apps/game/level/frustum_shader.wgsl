@@ -0,0 +1,47 @@
+// other cameras' frustums as wireframes, each in its own color
+// (see `FrustumPass` in frustum.rs)
+
+struct Frustum {
+    // bit 0 of the index is the right side, bit 1 the top and bit 2 the far plane,
+    // see `RenderCamera::frustum_corners`
+    corners: array<vec4<f32>, 8>,
+    color: vec4<f32>,
+}
+
+struct Frustums {
+    frustums: array<Frustum, 8>,
+}
+@group(2) @binding(0)
+var<uniform> frustums: Frustums;
+
+struct FrustumVertex {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+}
+
+// two vertices per edge and one instance per frustum, the 12 edges run along x, y and z
+// (4 each) between the corners whose index only differs in that axis' bit
+@vertex
+fn vs_main(
+    @builtin(vertex_index) v_idx: u32,
+    @builtin(instance_index) frustum: u32,
+) -> FrustumVertex {
+    let edge = v_idx / 2u;
+    let axis = edge / 4u;
+    let others = edge % 4u;
+    // the other two bits with the axis' bit inserted as 0, set for the edge's second vertex
+    let start = (others & ((1u << axis) - 1u)) | ((others >> axis) << (axis + 1u));
+    let corner = start | ((v_idx % 2u) << axis);
+    let world = frustums.frustums[frustum].corners[corner];
+    let screen = camera.world_to_screen * vec4<f32>(world.xyz, 1.0);
+    var out: FrustumVertex;
+    // `world_to_screen` only maps the view to the central half of the ndc range
+    out.clip_position = vec4<f32>(screen.xy * 2.0, screen.z, screen.w);
+    out.color = frustums.frustums[frustum].color;
+    return out;
+}
+
+@fragment
+fn fs_main(in: FrustumVertex) -> @location(0) vec4<f32> {
+    return in.color;
+}
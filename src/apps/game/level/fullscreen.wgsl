@@ -0,0 +1,15 @@
+// a single triangle covering the whole target, for post-processing passes
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@vertex
+fn vs_main(@builtin(vertex_index) v_idx: u32) -> VertexOutput {
+    let xy = vec2<f32>(f32((v_idx << 1u) & 2u), f32(v_idx & 2u)) * 2.0 - 1.0;
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(xy, 0.0, 1.0);
+    out.uv = vec2<f32>(xy.x * 0.5 + 0.5, 0.5 - xy.y * 0.5);
+    return out;
+}
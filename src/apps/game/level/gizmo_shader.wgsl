@@ -0,0 +1,38 @@
+// the selection's local axes as three lines, x red, y green and z blue
+// (see `GizmoPass` in gizmo.rs)
+
+struct Gizmo {
+    origin: vec3<f32>,
+    length: f32,
+    rotation: vec4<f32>,
+}
+@group(2) @binding(0)
+var<uniform> gizmo: Gizmo;
+
+struct GizmoVertex {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec3<f32>,
+}
+
+fn rotate(q: vec4<f32>, v: vec3<f32>) -> vec3<f32> {
+    return v + 2.0 * cross(q.xyz, cross(q.xyz, v) + q.w * v);
+}
+
+// two vertices per axis, the first at the origin
+@vertex
+fn vs_main(@builtin(vertex_index) v_idx: u32) -> GizmoVertex {
+    var axis = vec3<f32>(0.0);
+    axis[v_idx / 2u] = 1.0;
+    let world = gizmo.origin + rotate(gizmo.rotation, axis) * gizmo.length * f32(v_idx % 2u);
+    let screen = camera.world_to_screen * vec4<f32>(world, 1.0);
+    var out: GizmoVertex;
+    // `world_to_screen` only maps the view to the central half of the ndc range
+    out.clip_position = vec4<f32>(screen.xy * 2.0, screen.z, screen.w);
+    out.color = axis;
+    return out;
+}
+
+@fragment
+fn fs_main(in: GizmoVertex) -> @location(0) vec4<f32> {
+    return vec4<f32>(in.color, 1.0);
+}
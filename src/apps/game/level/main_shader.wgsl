@@ -1,39 +1,11 @@
-
-struct Primitive {
-    position: vec3<f32>,
-    _speed: f32,
-    rotation: vec4<f32>,
-    _rotation_delta: vec4<f32>,
-    data: vec4<f32>,
-    instances: vec3<u32>,
-    instances_distance: f32,
-    rgba: vec4<f32>,
-    typus: u32,
-    twist: f32,
-    // operation: u32,
-    // blend_strength: f32,
-}
-
-struct Primitives {
-    // length: u32,
-    prims: array<Primitive>,
-}
-
-@group(0) @binding(0)
-var<storage, read> primitives: Primitives;
-
-struct CameraUniform {
-    view_position: vec4<f32>,
-    // view_proj: mat4x4<f32>,
-    // inverse_proj: mat4x4<f32>,
-    // cam_to_world: mat4x4<f32>,
-    world_to_screen: mat4x4<f32>,
-    screen_to_world: mat4x4<f32>,
-    pixel_normalization: mat4x4<f32>,
-    effect: u32,
-};
-@group(1) @binding(0) // 1.
-var<uniform> camera: CameraUniform;
+// filled by the culling compute pass (see `culling_shader.wgsl`), only read when `enabled != 0u`
+struct VisiblePrimitives {
+    count: u32,
+    enabled: u32,
+    indices: array<u32>,
+}
+@group(2) @binding(0)
+var<storage, read> visible: VisiblePrimitives;
 
 // Vertex shader
 struct VertexInput {
@@ -70,16 +42,27 @@ fn vs_main(
 }
 
 // Fragment shader
-// @group(0) @binding(0)
-// var t_diffuse: texture_2d<f32>;
-// @group(0)@binding(1)
-// var s_diffuse: sampler;
+// images projected onto the primitives, see `TextureAtlas` in textures.rs
+@group(0) @binding(6)
+var textures: texture_2d_array<f32>;
+@group(0) @binding(7)
+var texture_sampler: sampler;
+
+// the marcher renders offscreen, the gbuffer is read by the post-processing passes
+struct FragmentOutput {
+    @location(0) color: vec4<f32>,
+    // distance to the hit (`max_distance` if nothing was hit) and its metalness
+    @location(1) gbuffer: vec2<f32>,
+    // the hit's ndc depth (the far plane if nothing was hit), the gizmo and frustum passes
+    // test against it. translucent primitives don't write it
+    @builtin(frag_depth) depth: f32,
+}
 
 @fragment
 fn fs_main(
     // @builtin(position) @invariant position: vec4<f32>,
     in: VertexOutput
-    ) -> @location(0) vec4<f32> {
+    ) -> FragmentOutput {
         // return vec4<f32>(normalize(in.clip_position.xyz/1000.0), 1.0);
     // return textureSample(t_diffuse, s_diffuse, in.tex_coords);
     // return vec4<f32>(in.color, 1.0);
@@ -88,43 +71,116 @@ fn fs_main(
     // return vec4<f32>(ray.direction,1.0);
     let out = march(ray);
     // // return vec4<f32>(f32(out.steps)/32.0, vec3<f32>(1.0));
-    return vec4<f32>(out.color.xyz , 1.0);
+    var gbuffer = vec2<f32>(max_distance, 0.0);
+    var depth = 1.0;
+    if (out.hit) {
+        gbuffer = vec2<f32>(out.distance, out.metalness);
+        depth = hit_depth(ray.origin + ray.direction * out.distance);
+    }
+    var color = composite_transparent(ray, out.color, select(max_distance, out.distance, out.hit));
+    if (camera.contour.enabled != 0u && !out.hit) {
+        color = vec4<f32>(contour_color(out.closest), 1.0);
+    }
+    if (camera.debug_mode == 1u) {
+        color = vec4<f32>(heatmap(f32(out.steps) / f32(max(camera.max_steps, 1u))), 1.0);
+    } else if (camera.debug_mode == 2u) {
+        color = vec4<f32>(vec3<f32>(min(out.distance / max_distance, 1.0)), 1.0);
+    } else if (camera.debug_mode == 3u) {
+        color = vec4<f32>(0.0, 0.0, 0.0, 1.0);
+        if (out.hit) {
+            let normal = calc_normal(ray.origin + ray.direction * out.distance);
+            color = vec4<f32>(normal * 0.5 + 0.5, 1.0);
+        }
+    }
+    return FragmentOutput(vec4<f32>(color.xyz , 1.0), gbuffer, depth);
     // return vec4<f32>(1.0);
 }
 
+// the depth the rasterizer would give `point`, `world_to_screen` is in wgpu's 0..1 convention
+fn hit_depth(point: vec3<f32>) -> f32 {
+    let clip = camera.world_to_screen * vec4<f32>(point, 1.0);
+    return clamp(clip.z / clip.w, 0.0, 1.0);
+}
+
 // ray marching
 
 struct MarchOutput {
     distance: f32,
     color: vec4<f32>,
     steps: u32,
+    hit: bool,
+    metalness: f32,
+    // the smallest distance to the primitives along the ray, for the contour bands
+    closest: f32,
+}
+
+// the gradient of the distance field from four samples around `point` (the tetrahedron
+// technique), only the normals debug mode needs it as there's no lighting
+fn calc_normal(point: vec3<f32>) -> vec3<f32> {
+    // the hit threshold is large, so sample at a matching scale
+    let h = epsilon * 0.5;
+    let k = vec2<f32>(1.0, -1.0);
+    return normalize(
+        k.xyy * calc_step(point + k.xyy * h).distance +
+        k.yyx * calc_step(point + k.yyx * h).distance +
+        k.yxy * calc_step(point + k.yxy * h).distance +
+        k.xxx * calc_step(point + k.xxx * h).distance
+    );
 }
 
-const max_steps = 32u;
-const max_distance = 1000.0;
+// blue at 0 over green to red at 1
+fn heatmap(t: f32) -> vec3<f32> {
+    let x = clamp(t, 0.0, 1.0) * 2.0 - 1.0;
+    return clamp(vec3<f32>(x, 1.0 - abs(x), -x), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+// a rainbow ramp over each band with a dark line where the bands meet
+fn contour_color(distance: f32) -> vec3<f32> {
+    let t = fract(distance * camera.contour.frequency);
+    let ramp = 0.5 + 0.5 * cos(6.28318 * (t + vec3<f32>(0.0, 0.33, 0.67)));
+    return ramp * smoothstep(0.0, 0.05, t);
+}
+
+// the glow is scaled to the default step count, so it doesn't flicker as the steps adapt
+const glow_steps = 32u;
 const epsilon = 1.0;
     
 fn march(ray: Ray) -> MarchOutput {
+    let max_steps = camera.max_steps;
     var dst = 0.0;
-    var steps = 0u;
-    let color_damper = f32(max_steps)/6.0;
+    // stays at the limit if the ray neither hits nor leaves the view
+    var steps = max_steps;
+    let color_damper = f32(glow_steps)/6.0;
     // let max_steps_f32_x3 = max_steps_f32;
     var color = vec4<f32>(.0);
+    var hit = false;
+    var metalness = 0.0;
+    var closest = max_distance;
+    // where along the ray `closest` was and how a hit there would have looked, for the edge aa
+    var closest_dst = 0.0;
+    var edge_color = vec4<f32>(0.0);
     for (var i = 0u; i < max_steps; i = i + 1u) {
-        let out = calc_step(ray.origin + ray.direction * dst);
-        dst = dst + out.distance;
+        let point = ray.origin + ray.direction * dst;
+        let out = calc_step(point);
+        let closest_here = out.distance < closest;
+        closest = min(closest, out.distance);
+        if (grid.enabled != 0u) {
+            // primitives in neighbouring cells weren't considered, so don't step past the cell
+            dst = dst + min(out.distance, distance_to_cell_exit(point, ray.direction) + grid_nudge);
+        } else {
+            dst = dst + out.distance;
+        }
+        if (closest_here && camera.edge_aa.enabled != 0u) {
+            closest_dst = dst - out.distance;
+            edge_color = shade_hit(color, dst, out.emissive);
+        }
         if (out.distance < epsilon) {
             steps = i;
+            hit = true;
+            metalness = out.metalness;
             // color = out.color;
-            if (camera.effect == 4u) { //4u = black-body
-                color = vec4<f32>(0.0);
-            } else if (camera.effect == 5u || camera.effect == 1u || camera.effect == 3u ) { //5u = white-body
-                color = vec4<f32>(1.0);
-            } else if ( camera.effect == 2u ){
-                color = vec4<f32>(1.0) * (max_distance - dst) / max_distance;
-            } else { //shattered glass looking default shader
-                color = color * (max_distance - dst) / max_distance;
-            }
+            color = shade_hit(color, dst, out.emissive);
+            color = texture_hit(color, point, out.nearest);
             break;
         }
         if (camera.effect != 2u) { //2u = glow-off
@@ -140,23 +196,111 @@ fn march(ray: Ray) -> MarchOutput {
             if (camera.effect == 1u) { //1u = glassy-onion
                 color = out.color;
             }
+            if (environment.enabled != 0u) {
+                color = color + sample_environment(ray.direction);
+            }
             break;
         }
     }
     // color = color * (max_distance - dst) / max_distance;
-    return MarchOutput(dst, color, steps);
+    if (!hit && camera.edge_aa.enabled != 0u) {
+        // how much of the pixel's footprint the surface the ray just missed covers
+        let footprint = camera.edge_aa.pixel_size * select(closest_dst, 1.0, camera.projection_kind == 1u);
+        let coverage = 1.0 - clamp((closest - epsilon) / (camera.edge_aa.width * footprint), 0.0, 1.0);
+        color = mix(color, edge_color, coverage);
+    }
+    return MarchOutput(dst, color, steps, hit, metalness, closest);
+}
+
+// what the ray's accumulated `color` turns into when it hits a surface after `dst`
+fn shade_hit(color: vec4<f32>, dst: f32, emissive: vec3<f32>) -> vec4<f32> {
+    var out = color;
+    if (camera.effect == 4u) { //4u = black-body
+        out = vec4<f32>(0.0);
+    } else if (camera.effect == 5u || camera.effect == 1u || camera.effect == 3u ) { //5u = white-body
+        out = vec4<f32>(1.0);
+    } else if ( camera.effect == 2u ){
+        out = vec4<f32>(1.0) * (max_distance - dst) / max_distance;
+    } else { //shattered glass looking default shader
+        out = out * (max_distance - dst) / max_distance;
+    }
+    return out + vec4<f32>(emissive, 0.0);
+}
+
+// the atlas layer count, `SDFPrimitive::texture_index` past it is `NO_TEXTURE`
+const max_textures = 16u;
+// how sharply the triplanar projection favours the plane facing the normal
+const triplanar_sharpness = 4.0;
+
+// tints `color` by the texture of the primitive hit at `point`, projected along the
+// primitive's local axes and blended by how much the normal faces each of them
+fn texture_hit(color: vec4<f32>, point: vec3<f32>, nearest: u32) -> vec4<f32> {
+    if (nearest >= arrayLength(&primitives.prims)) {
+        return color;
+    }
+    let prim = load_primitive(nearest);
+    if (prim.texture_index >= max_textures) {
+        return color;
+    }
+    let world_normal = calc_normal(point);
+    // the same wrapping as `distance_to_primitive`, so every repetition looks the same
+    let period = vec2<f32>(1000.0, 1000.0);
+    let q = point - prim.position;
+    var whole = vec2<f32>();
+    let mod_point = vec3<f32>((modf(q.xy / period + 0.5 * period, &whole) - vec2<f32>(0.5)) * period, q.z);
+    var local = fast_inverse_qrotate_vector(prim.rotation, mod_point);
+    var normal = fast_inverse_qrotate_vector(prim.rotation, world_normal);
+    if (prim.transform_stretch > 0.0) {
+        // only the weights use the normal, so the inverse's shear is close enough
+        let m = prim.inverse_transform;
+        local = vec3<f32>(dot(m[0].xyz, mod_point), dot(m[1].xyz, mod_point), dot(m[2].xyz, mod_point));
+        normal = vec3<f32>(dot(m[0].xyz, world_normal), dot(m[1].xyz, world_normal), dot(m[2].xyz, world_normal));
+    }
+    let p = local * prim.texture_scale;
+    var weights = pow(abs(normalize(normal)), vec3<f32>(triplanar_sharpness));
+    weights = weights / (weights.x + weights.y + weights.z);
+    let layer = i32(prim.texture_index);
+    // not `textureSample`, the marching loop isn't uniform control flow
+    let texel =
+        textureSampleLevel(textures, texture_sampler, p.yz, layer, 0.0) * weights.x +
+        textureSampleLevel(textures, texture_sampler, p.xz, layer, 0.0) * weights.y +
+        textureSampleLevel(textures, texture_sampler, p.xy, layer, 0.0) * weights.z;
+    // an unset layer is transparent and leaves the color alone
+    return vec4<f32>(mix(color.rgb, color.rgb * texel.rgb, texel.a), color.a);
 }
 
-struct Ray {
-    origin: vec3<f32>,
-    direction: vec3<f32>,
+// blends the translucent primitives the ray hits before `limit` over `color`, back to front
+// as they are uploaded from far to near
+fn composite_transparent(ray: Ray, color: vec4<f32>, limit: f32) -> vec4<f32> {
+    var out = color;
+    let count = arrayLength(&primitives.prims);
+    for (var i = order.transparent_start; i < count; i = i + 1u) {
+        let prim = load_primitive(i);
+        if (hits_primitive(ray, prim, limit)) {
+            let rgba = primitive_rgba(prim);
+            out = vec4<f32>(mix(out.rgb, rgba.rgb, rgba.a), out.a);
+        }
+    }
+    return out;
+}
+
+fn hits_primitive(ray: Ray, prim: Primitive, limit: f32) -> bool {
+    var dst = 0.0;
+    for (var i = 0u; i < camera.max_steps; i = i + 1u) {
+        let step = distance_to_primitive(ray.origin + ray.direction * dst, prim);
+        if (step < epsilon) {
+            return true;
+        }
+        dst = dst + step;
+        if (dst > limit) {
+            break;
+        }
+    }
+    return false;
 }
 
 fn mk_ray_from_camera(uv: vec2<f32>) -> Ray {
-    let origin = camera.view_position.xyz;
-    var direction = (camera.screen_to_world * vec4<f32>(uv,0.0,1.0)).xyz;
-    direction = normalize(direction-origin);
-    return Ray(origin, direction);
+    return camera_ray(uv);
 }
 
 fn distance_to_primitive(from_point: vec3<f32>, primitive: Primitive) -> f32 {
@@ -168,22 +312,33 @@ fn distance_to_primitive(from_point: vec3<f32>, primitive: Primitive) -> f32 {
     //// let relative_point_q = (modf(q/c+0.5).fract-vec3<f32>(0.5))*c; //to spec
     var whole = vec2<f32>();
     let mod_point = vec3<f32>((modf(q.xy/infinite_repition_period+0.5*infinite_repition_period,&whole)-vec2<f32>(0.5))*infinite_repition_period,q.z); //to old spec
-    let relative_point = fast_inverse_qrotate_vector(primitive.rotation,mod_point);// - fast_inverse_qrotate_vector(primitive.rotation,primitive.position); 
+    var relative_point = fast_inverse_qrotate_vector(primitive.rotation,mod_point);// - fast_inverse_qrotate_vector(primitive.rotation,primitive.position); 
+    // 1 unless `SDFPrimitive::from_matrix` placed it, local distances are divided by it
+    var stretch = 1.0;
+    if (primitive.transform_stretch > 0.0) {
+        // the inverse's translation is the offset from `position`, which is already wrapped
+        let m = primitive.inverse_transform;
+        relative_point = vec3<f32>(dot(m[0].xyz, mod_point), dot(m[1].xyz, mod_point), dot(m[2].xyz, mod_point));
+        stretch = primitive.transform_stretch;
+    }
     q = relative_point;
     //// let relative_point = qrotate_vector(qinverse(primitive.rotation),from_point) - qrotate_vector(qinverse(primitive.rotation),primitive.position); 
-    // // twisting //FIXME: this is not working, it brings enourmous amounts of noise
-    // let twist = primitive.twist;
-    // let cos_twist = cos(twist*q.y);
-    // let sin_twist = sin(twist*q.y);
-    // let twist_matrix = mat2x2(cos_twist,-sin_twist,sin_twist,cos_twist);
-    // let twisted_point = vec3(twist_matrix*q.xz,q.y);
-    // q = twisted_point;
+    // far from an instance grid the distance to its box is a cheap lower bound,
+    // closer than one instance spacing it's too loose and the instances are evaluated
+    if (any(primitive.instances != vec3<u32>(0u))) {
+        let box_dst = length(max(abs(q) - primitive.instance_extent, vec3<f32>(0.0)));
+        if (box_dst > primitive.instances_distance) {
+            return box_dst / stretch;
+        }
+    }
     // finite instancing
     let dis : vec3<f32> = round(q/primitive.instances_distance);
-    let bound = vec3<f32>(primitive.instances);
+    // every lod halves the instance count per axis
+    let bound = vec3<f32>(primitive.instances >> vec3<u32>(primitive.lod));
     let instanced_point : vec3<f32> = q-primitive.instances_distance*clamp(dis,-bound,bound);
     //// dst = distance_to_box_frame(relative_point_q, primitive.data);
     q = instanced_point;
+    q = deform(q, primitive);
     switch(primitive.typus) {
         case 0u: {dst = distance_to_box_frame(q, primitive.data);}
         case 1u: {dst = distance_to_ellipsoid(q, primitive.data);}
@@ -191,49 +346,198 @@ fn distance_to_primitive(from_point: vec3<f32>, primitive: Primitive) -> f32 {
         case 3u: {dst = distance_to_chain_link(q, primitive.data);}
         default: {}
     }
-    return dst;
+    if (primitive.displacement != 0.0 && primitive.lod == 0u) {
+        dst = dst + primitive.displacement * value_noise(q * primitive.noise_scale);
+    }
+    dst = dst / deformation_stretch(primitive) - primitive.rounding;
+    if (primitive.shell_thickness > 0.0) {
+        // hollow, only the band around the surface is inside
+        dst = abs(dst) - primitive.shell_thickness;
+    }
+    return dst / stretch;
+}
+
+// twist and bend, see `SDFPrimitive::set_twist` and `set_bend`
+fn deform(p: vec3<f32>, primitive: Primitive) -> vec3<f32> {
+    var q = p;
+    if (primitive.twist != 0.0) {
+        let a = primitive.twist * q.y;
+        q = vec3<f32>(cos(a) * q.x - sin(a) * q.z, q.y, sin(a) * q.x + cos(a) * q.z);
+    }
+    if (primitive.bend != 0.0) {
+        let a = primitive.bend * q.x;
+        q = vec3<f32>(cos(a) * q.x - sin(a) * q.y, sin(a) * q.x + cos(a) * q.y, q.z);
+    }
+    return q;
+}
+
+// how much `deform` stretches space at most, the distance has to shrink by that
+fn deformation_stretch(primitive: Primitive) -> f32 {
+    let r = dot(abs(primitive.data), vec4<f32>(1.0));
+    return 1.0 + (abs(primitive.twist) + abs(primitive.bend)) * r;
 }
 
 struct StepOutput {
     distance: f32,
     color: vec4<f32>,
+    // of the closest primitive
+    metalness: f32,
+    emissive: vec3<f32>,
+    // index of the closest opaque primitive, `0xffffffffu` if there's none
+    nearest: u32,
 }
 
 fn calc_step(from_point: vec3<f32>) -> StepOutput {
     var min_dst = 100000.0;
     var color = vec4<f32>(0.0);
-    for (var i:u32 = 0u; i < arrayLength(&primitives.prims); i = i + 1u) {
-        let prim = get_ith_primitive(i);
+    var metalness = 0.0;
+    var emissive = vec3<f32>(0.0);
+    var nearest = 0xffffffffu;
+    var range = vec2<u32>(0u, primitive_count());
+    if (grid.enabled != 0u) {
+        range = grid_cell_range(from_point);
+    }
+    var folded = 0u;
+    for (var i:u32 = range.x; i < range.y; i = i + 1u) {
+        let index = get_ith_index(i);
+        if (index >= order.transparent_start) {
+            // composited afterwards, see `composite_transparent`
+            continue;
+        }
+        if (folded >= order.max_primitives_per_pixel) {
+            // the rest is skipped, an approximation for the sake of the frame time
+            break;
+        }
+        folded = folded + 1u;
+        let prim = load_primitive(index);
         let dst = distance_to_primitive(from_point, prim);
+        let rgba = primitive_rgba(prim);
         if (camera.effect == 3u) {//clean-from-water
-            color = color + prim.rgba / max(dst*dst*dst/max_distance,1.0);
+            color = color + rgba / max(dst*dst*dst/max_distance,1.0);
         } else {
-            color = color + prim.rgba / max(dst/6.0,1.0);
+            color = color + rgba / max(dst/6.0,1.0);
         }
+        color = color + editor_glow(prim, dst);
 
+        if (dst < min_dst) {
+            nearest = index;
+        }
+        if ((prim.operation & 1u) != 0u) {
+            // color blend, the same weights as the distances
+            let h = combine_weight(min_dst, dst);
+            metalness = mix(prim.metalness, metalness, h);
+            emissive = mix(prim.emissive, emissive, h);
+        } else if (dst < min_dst) {
+            metalness = prim.metalness;
+            emissive = prim.emissive;
+        }
         min_dst = combine(min_dst, dst);
     }
-    return StepOutput(min_dst, color);
+    return StepOutput(min_dst, color, metalness, emissive, nearest);
+}
+
+// emissive rim around highlighted primitives and a thinner outline around selected ones,
+// the glow accumulates along rays passing close by
+const highlight_color = vec4<f32>(1.0, 0.8, 0.2, 1.0);
+const highlight_width = 3.0;
+const selection_color = vec4<f32>(0.2, 0.6, 1.0, 1.0);
+const selection_width = 1.0;
+fn editor_glow(prim: Primitive, dst: f32) -> vec4<f32> {
+    var glow = vec4<f32>(0.0);
+    if ((prim.flags & 1u) != 0u) {
+        glow = glow + highlight_color / max(dst / highlight_width, 1.0);
+    }
+    if ((prim.flags & 2u) != 0u) {
+        glow = glow + selection_color / max(dst * dst / selection_width, 1.0);
+    }
+    return glow;
 }
 
 const smoothed = false;
+const smoothness = 100.0;
 fn combine(d1: f32, d2: f32)->f32{//, c1: vec4<f32>, c2: vec4<f32>)->f32{
     if smoothed {
-        return smooth_min(d1,d2,100.0);
+        return smooth_min(d1,d2,smoothness);
     } else {
         return min(d1,d2);
     }
 }
 
+// how much of `d1`'s side `combine` keeps, the `h` of `smooth_min`
+fn combine_weight(d1: f32, d2: f32) -> f32 {
+    if smoothed {
+        return clamp(0.5 + 0.5 * (d2 - d1) / smoothness, 0.0, 1.0);
+    } else {
+        return select(0.0, 1.0, d1 <= d2);
+    }
+}
+
 fn smooth_min( d1 : f32, d2 : f32  , k  : f32) -> f32 {
     let h : f32 = clamp( 0.5 + 0.5*(d2-d1)/k, 0.0, 1.0 );
     return mix( d2, d1, h ) - k*h*(1.0-h); 
 }
 
+fn primitive_count() -> u32 {
+    if (visible.enabled != 0u) {
+        // the culling pass counts past the list if it's too small, it drops those
+        return min(visible.count, arrayLength(&visible.indices));
+    }
+    return arrayLength(&primitives.prims);
+}
+
+fn get_ith_index(i: u32) -> u32 {
+    if (grid.enabled != 0u) {
+        return grid_indices.indices[i];
+    }
+    if (visible.enabled != 0u) {
+        return visible.indices[i];
+    }
+    return i;
+}
+
 fn get_ith_primitive(i: u32) -> Primitive {
-    return primitives.prims[i];
+    return load_primitive(get_ith_index(i));
+}
+
+
+// spatial grid lookup
+
+const infinite_repition_period = 1000.0;
+const grid_nudge = 0.01;
+
+// position relative to the grid origin, x and y wrapped into a single repetition period
+fn grid_local(from_point: vec3<f32>) -> vec3<f32> {
+    let period = vec2<f32>(infinite_repition_period);
+    return vec3<f32>(fract(from_point.xy / period) * period, from_point.z - grid.origin.z);
 }
 
+// range of `grid_indices` holding the primitives of the cell `from_point` lies in
+fn grid_cell_range(from_point: vec3<f32>) -> vec2<u32> {
+    let cell = floor(grid_local(from_point) / grid.cell_size);
+    if (cell.z < 0.0 || cell.z >= f32(grid.resolution.z)) {
+        return vec2<u32>(0u);
+    }
+    let c = min(vec3<u32>(cell), grid.resolution - vec3<u32>(1u));
+    let i = c.x + grid.resolution.x * (c.y + grid.resolution.y * c.z);
+    return vec2<u32>(grid_cells.offsets[i], grid_cells.offsets[i + 1u]);
+}
+
+// how far the ray can travel before it leaves the current cell (or enters the grid's z range)
+fn distance_to_cell_exit(from_point: vec3<f32>, direction: vec3<f32>) -> f32 {
+    let local = grid_local(from_point);
+    let extent_z = grid.cell_size.z * f32(grid.resolution.z);
+    if (local.z < 0.0) {
+        return select(max_distance, -local.z / direction.z, direction.z > 0.0);
+    }
+    if (local.z >= extent_z) {
+        return select(max_distance, (extent_z - local.z) / direction.z, direction.z < 0.0);
+    }
+    let low = floor(local / grid.cell_size) * grid.cell_size;
+    let high = low + grid.cell_size;
+    let t = select((low - local) / direction, (high - local) / direction, direction > vec3<f32>(0.0));
+    let safe_t = select(t, vec3<f32>(max_distance), direction == vec3<f32>(0.0));
+    return min(safe_t.x, min(safe_t.y, safe_t.z));
+}
 
 // primitive signed distance functions
 
@@ -275,6 +579,24 @@ fn distance_to_chain_link(from_point: vec3<f32>, chain_data: vec4<f32>) -> f32 {
     return length(vec2(length(q.xy)-arc_radius,q.z)) - girth;
 }
 
+// noise
+
+fn hash(p: vec3<f32>) -> f32 {
+    return fract(sin(dot(p, vec3<f32>(127.1, 311.7, 74.7))) * 43758.5453);
+}
+
+// trilinearly interpolated hash noise in [-1, 1], has to match `value_noise` in distance.rs
+fn value_noise(p: vec3<f32>) -> f32 {
+    let i = floor(p);
+    let f = fract(p);
+    let u = f * f * (3.0 - 2.0 * f);
+    let x00 = mix(hash(i), hash(i + vec3<f32>(1.0, 0.0, 0.0)), u.x);
+    let x10 = mix(hash(i + vec3<f32>(0.0, 1.0, 0.0)), hash(i + vec3<f32>(1.0, 1.0, 0.0)), u.x);
+    let x01 = mix(hash(i + vec3<f32>(0.0, 0.0, 1.0)), hash(i + vec3<f32>(1.0, 0.0, 1.0)), u.x);
+    let x11 = mix(hash(i + vec3<f32>(0.0, 1.0, 1.0)), hash(i + vec3<f32>(1.0, 1.0, 1.0)), u.x);
+    return mix(mix(x00, x10, u.y), mix(x01, x11, u.y), u.z) * 2.0 - 1.0;
+}
+
 // quaternions
 fn qmul(q1: vec4<f32>,  q2:vec4<f32>)->vec4<f32>
 {
@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use cgmath::{InnerSpace, Point3, Vector3};
+
+use super::distance::closest_primitive;
+use super::primitives::PrimitiveManager;
+
+/// the six tetrahedra a voxel is split into, all around the diagonal from corner 0 to 7.
+/// corner `i` sits at `(i & 1, (i >> 1) & 1, (i >> 2) & 1)`
+const TETRAHEDRA: [[usize; 4]; 6] = [
+    [0, 7, 1, 3],
+    [0, 7, 3, 2],
+    [0, 7, 2, 6],
+    [0, 7, 6, 4],
+    [0, 7, 4, 5],
+    [0, 7, 5, 1],
+];
+
+impl PrimitiveManager {
+    /// a triangle mesh of the primitives' surface within `bounds` (min and max corner) as the
+    /// text of an obj file, for using the scene in other tools
+    ///
+    /// the distance field is sampled on a grid of cubic voxels, `resolution` of them along the
+    /// longest side of `bounds`, so the cost grows with its cube. every voxel is split into
+    /// tetrahedra (marching tetrahedra, marching cubes without the ambiguous cases), the
+    /// faces are wound counter-clockwise seen from outside. the cpu distance is the plain union,
+    /// see `SingleLevelManager::pick_at`. nothing in `bounds` gives a file without faces
+    pub fn export_obj(&self, bounds: (Point3<f32>, Point3<f32>), resolution: u32) -> String {
+        let (min, max) = bounds;
+        let extent = max - min;
+        let voxel = extent.x.max(extent.y).max(extent.z) / resolution.max(1) as f32;
+        let mut obj = String::from("# exported from ShapeStormer\n");
+        if voxel.is_nan() || voxel <= 0.0 {
+            return obj;
+        }
+        let cells = [extent.x, extent.y, extent.z].map(|side| ((side / voxel).ceil() as usize).max(1));
+        let corners = [cells[0] + 1, cells[1] + 1, cells[2] + 1];
+        let corner_index = |x: usize, y: usize, z: usize| (z * corners[1] + y) * corners[0] + x;
+        let position = |index: usize| {
+            let (x, y, z) = (index % corners[0], index / corners[0] % corners[1], index / (corners[0] * corners[1]));
+            min + Vector3::new(x as f32, y as f32, z as f32) * voxel
+        };
+        let samples: Vec<f32> = (0..corners[0] * corners[1] * corners[2])
+            .map(|index| match closest_primitive(position(index), &self.primitives) {
+                // nan counts as outside
+                Some((distance, _)) if !distance.is_nan() => distance,
+                _ => f32::INFINITY,
+            })
+            .collect();
+
+        let mut mesh = Mesh::default();
+        for z in 0..cells[2] {
+            for y in 0..cells[1] {
+                for x in 0..cells[0] {
+                    let cube = [0, 1, 2, 3, 4, 5, 6, 7]
+                        .map(|i| corner_index(x + (i & 1), y + ((i >> 1) & 1), z + ((i >> 2) & 1)));
+                    for tetrahedron in TETRAHEDRA {
+                        let tetrahedron = tetrahedron.map(|i| cube[i]);
+                        mesh.polygonize(tetrahedron, &samples, position);
+                    }
+                }
+            }
+        }
+
+        for vertex in &mesh.vertices {
+            let _ = writeln!(obj, "v {} {} {}", vertex.x, vertex.y, vertex.z);
+        }
+        for [a, b, c] in &mesh.faces {
+            // obj indices start at 1
+            let _ = writeln!(obj, "f {} {} {}", a + 1, b + 1, c + 1);
+        }
+        obj
+    }
+}
+
+#[derive(Default)]
+struct Mesh {
+    vertices: Vec<Point3<f32>>,
+    faces: Vec<[usize; 3]>,
+    /// the vertex on the grid edge between two corners, shared by all voxels touching it
+    edge_vertices: HashMap<(usize, usize), usize>,
+}
+
+impl Mesh {
+    /// adds the surface passing through the tetrahedron with the corners `tetrahedron`
+    fn polygonize(
+        &mut self,
+        tetrahedron: [usize; 4],
+        samples: &[f32],
+        position: impl Fn(usize) -> Point3<f32> + Copy,
+    ) {
+        let (inside, outside): (Vec<usize>, Vec<usize>) =
+            tetrahedron.iter().partition(|&&corner| samples[corner] < 0.0);
+        let mut edge = |a, b| self.edge_vertex(a, b, samples, position);
+        let triangles = match (inside.as_slice(), outside.as_slice()) {
+            (&[a], &[b, c, d]) | (&[b, c, d], &[a]) => vec![[edge(a, b), edge(a, c), edge(a, d)]],
+            (&[a, b], &[c, d]) => {
+                let (ac, ad, bd, bc) = (edge(a, c), edge(a, d), edge(b, d), edge(b, c));
+                vec![[ac, ad, bd], [ac, bd, bc]]
+            }
+            _ => return,
+        };
+        // the normal has to point from the inside corners to the outside ones
+        let center = |corners: &[usize]| {
+            corners.iter().map(|&corner| position(corner).to_homogeneous().truncate()).sum::<Vector3<f32>>()
+                / corners.len() as f32
+        };
+        let outwards = center(&outside) - center(&inside);
+        for [a, b, c] in triangles {
+            let (pa, pb, pc) = (self.vertices[a], self.vertices[b], self.vertices[c]);
+            let normal = (pb - pa).cross(pc - pa);
+            self.faces.push(if normal.dot(outwards) < 0.0 { [a, c, b] } else { [a, b, c] });
+        }
+    }
+
+    /// the vertex where the distance crosses zero between the corners `a` and `b`
+    fn edge_vertex(
+        &mut self,
+        a: usize,
+        b: usize,
+        samples: &[f32],
+        position: impl Fn(usize) -> Point3<f32>,
+    ) -> usize {
+        let key = (a.min(b), a.max(b));
+        if let Some(&vertex) = self.edge_vertices.get(&key) {
+            return vertex;
+        }
+        let (da, db) = (samples[key.0], samples[key.1]);
+        // the outside corner may be infinitely far from the surface
+        let t = if db.is_finite() && da.is_finite() { da / (da - db) } else { 0.5 };
+        let (pa, pb) = (position(key.0), position(key.1));
+        let vertex = self.vertices.len();
+        self.vertices.push(pa + (pb - pa) * t.clamp(0.0, 1.0));
+        self.edge_vertices.insert(key, vertex);
+        vertex
+    }
+}
@@ -0,0 +1,43 @@
+// camera motion blur, gathers the color along each pixel's screen-space velocity
+// (see `MotionBlurPass` in motion_blur.rs)
+
+struct MotionBlurSettings {
+    samples: u32,
+    intensity: f32,
+}
+@group(2) @binding(0)
+var color_texture: texture_2d<f32>;
+@group(2) @binding(1)
+var gbuffer_texture: texture_2d<f32>;
+@group(2) @binding(2)
+var<uniform> motion_blur: MotionBlurSettings;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let size = vec2<i32>(textureDimensions(color_texture));
+    let pixel = vec2<i32>(in.clip_position.xy);
+    let uv = (vec2<f32>(pixel) + 0.5) / vec2<f32>(size);
+
+    // where the surface seen through this pixel was on screen last frame,
+    // rays that hit nothing are treated as hitting the far end
+    let ray = camera_ray(vec2<f32>(uv.x - 0.5, 0.5 - uv.y));
+    let distance = min(textureLoad(gbuffer_texture, pixel, 0).x, max_distance);
+    let world = ray.origin + ray.direction * distance;
+    let previous = camera.prev_world_to_screen * vec4<f32>(world, 1.0);
+    if (previous.w <= 0.0) {
+        return textureLoad(color_texture, pixel, 0);
+    }
+    let previous_screen = previous.xy / previous.w;
+    let previous_uv = vec2<f32>(previous_screen.x + 0.5, 0.5 - previous_screen.y);
+    let velocity = (uv - previous_uv) * motion_blur.intensity;
+
+    let samples = max(motion_blur.samples, 1u);
+    var color = vec4<f32>(0.0);
+    for (var i = 0u; i < samples; i = i + 1u) {
+        // centered on the pixel, spanning the whole distance travelled
+        let t = (f32(i) + 0.5) / f32(samples) - 0.5;
+        let sample_pixel = vec2<i32>((uv - velocity * t) * vec2<f32>(size));
+        color = color + textureLoad(color_texture, clamp(sample_pixel, vec2<i32>(0), size - vec2<i32>(1)), 0);
+    }
+    return color / f32(samples);
+}
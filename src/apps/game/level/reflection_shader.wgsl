@@ -0,0 +1,81 @@
+// screen-space reflections, marches the reflected ray against the marcher's gbuffer
+// (see `ReflectionPass` in reflections.rs)
+
+struct ReflectionSettings {
+    max_steps: u32,
+    thickness: f32,
+    intensity: f32,
+    metalness_threshold: f32,
+}
+@group(2) @binding(0)
+var color_texture: texture_2d<f32>;
+@group(2) @binding(1)
+var gbuffer_texture: texture_2d<f32>;
+@group(2) @binding(2)
+var<uniform> reflection: ReflectionSettings;
+
+// same mapping as `mk_ray_from_camera` in main_shader.wgsl
+fn uv_ray(uv: vec2<f32>) -> Ray {
+    return camera_ray(vec2<f32>(uv.x - 0.5, 0.5 - uv.y));
+}
+
+fn world_position(pixel: vec2<i32>, size: vec2<i32>) -> vec3<f32> {
+    let p = clamp(pixel, vec2<i32>(0), size - vec2<i32>(1));
+    let uv = (vec2<f32>(p) + 0.5) / vec2<f32>(size);
+    let distance = textureLoad(gbuffer_texture, p, 0).x;
+    let ray = uv_ray(uv);
+    return ray.origin + ray.direction * distance;
+}
+
+// inverse of `uv_ray`, `z < 0` means behind the camera
+fn project(world: vec3<f32>) -> vec3<f32> {
+    let clip = camera.world_to_screen * vec4<f32>(world, 1.0);
+    let screen = clip.xy / clip.w;
+    return vec3<f32>(screen.x + 0.5, 0.5 - screen.y, clip.w);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let size = vec2<i32>(textureDimensions(color_texture));
+    let pixel = vec2<i32>(in.clip_position.xy);
+    let color = textureLoad(color_texture, pixel, 0);
+    let gbuffer = textureLoad(gbuffer_texture, pixel, 0);
+    let metalness = gbuffer.y;
+    if (gbuffer.x >= max_distance || metalness < reflection.metalness_threshold) {
+        return color;
+    }
+
+    let position = world_position(pixel, size);
+    let view = uv_ray((vec2<f32>(pixel) + 0.5) / vec2<f32>(size)).direction;
+    // no normals in the gbuffer, so reconstruct them from the neighbouring hits
+    var normal = normalize(cross(
+        world_position(pixel + vec2<i32>(1, 0), size) - position,
+        world_position(pixel + vec2<i32>(0, 1), size) - position,
+    ));
+    if (dot(normal, view) > 0.0) {
+        normal = -normal;
+    }
+    let direction = reflect(view, normal);
+
+    // whatever we can't find on screen comes from the environment (or stays unreflected)
+    var reflected = color;
+    if (environment.enabled != 0u) {
+        reflected = sample_environment(direction);
+    }
+    let step = max_distance / f32(max(reflection.max_steps, 1u));
+    for (var i = 1u; i <= reflection.max_steps; i = i + 1u) {
+        let sample_point = position + direction * step * f32(i);
+        let screen = project(sample_point);
+        if (screen.z <= 0.0 || any(screen.xy < vec2<f32>(0.0)) || any(screen.xy >= vec2<f32>(1.0))) {
+            break;
+        }
+        let sample_pixel = vec2<i32>(screen.xy * vec2<f32>(size));
+        let scene_distance = textureLoad(gbuffer_texture, sample_pixel, 0).x;
+        let ray_distance = length(sample_point - uv_ray(screen.xy).origin);
+        if (ray_distance > scene_distance && ray_distance - scene_distance < reflection.thickness) {
+            reflected = textureLoad(color_texture, sample_pixel, 0);
+            break;
+        }
+    }
+    return mix(color, reflected, clamp(reflection.intensity * metalness, 0.0, 1.0));
+}
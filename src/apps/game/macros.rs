@@ -10,4 +10,20 @@ macro_rules! x3 {
     ($x:expr) => {
         [$x, $x, $x]
     };
-}
\ No newline at end of file
+}
+
+/// logs a buffer upload (which buffer, where, how many bytes) at trace level
+/// when the `trace_uploads` feature is enabled, expands to nothing otherwise
+#[macro_export]
+macro_rules! trace_upload {
+    ($buffer:expr, $offset:expr, $bytes:expr) => {
+        #[cfg(feature = "trace_uploads")]
+        tracing::trace!(
+            target: "uploads",
+            buffer = $buffer,
+            offset = $offset as u64,
+            bytes = $bytes as u64,
+            "buffer upload"
+        );
+    };
+}
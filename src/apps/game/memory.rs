@@ -0,0 +1,25 @@
+use super::wgpu::TextureFormat;
+
+/// how much gpu memory the renderer's buffers and textures take, see `State::gpu_memory_report`
+///
+/// only what the renderer allocates itself, not pipelines or what the driver adds on top
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct GpuMemoryReport {
+    /// a name and the bytes of every part, in the order they were added
+    pub entries: Vec<(&'static str, u64)>,
+}
+
+impl GpuMemoryReport {
+    pub(crate) fn add(&mut self, name: &'static str, bytes: u64) {
+        self.entries.push((name, bytes));
+    }
+
+    pub fn total(&self) -> u64 {
+        self.entries.iter().map(|(_, bytes)| bytes).sum()
+    }
+}
+
+/// the size of a 2d texture without mips
+pub(crate) fn texture_bytes(size: (u32, u32), format: TextureFormat) -> u64 {
+    size.0 as u64 * size.1 as u64 * format.describe().block_size as u64
+}
@@ -1,5 +1,6 @@
 use std::{
     num::NonZeroU64,
+    path::{Path, PathBuf},
     sync::Arc,
     time::{Duration, Instant},
 };
@@ -8,16 +9,86 @@ use eframe::{
     egui_wgpu::wgpu::util::DeviceExt,
     egui_wgpu::{self, wgpu},
 };
-use egui::{Window, ComboBox};
+use egui::{mutex::RwLock, Window, ComboBox};
 
 use self::camera::Effect;
 
+mod bloom;
 mod camera;
+mod capabilities;
+mod culling;
+mod device;
+mod environment;
+#[cfg(feature = "expressions")]
+mod expressions;
+mod frustum;
+mod gizmo;
+mod grid;
 mod level;
 mod macros;
+mod memory;
+mod motion_blur;
+mod palette;
 mod primitives;
+mod quality;
+#[cfg(not(target_arch = "wasm32"))]
+mod recording;
+mod reflections;
+mod render_targets;
+mod renderer;
+mod replay;
+#[cfg(feature = "serde")]
+mod scene;
+mod settings;
+#[cfg(all(feature = "hot_reload", not(target_arch = "wasm32")))]
+mod shader_reload;
+mod textures;
+mod viewports;
+#[cfg(all(feature = "hot_reload", not(target_arch = "wasm32")))]
+mod watcher;
 mod controller;
 
+pub use bloom::BloomSettings;
+pub use camera::{CameraDebugState, CameraUniform, ContourSettings, DebugMode, EdgeAA, MoveDirection, ScrollDelta};
+pub use capabilities::{
+    preferred_surface_format, supported_present_mode, Capabilities, CapacityError,
+};
+#[cfg(feature = "expressions")]
+pub use expressions::{ExpressionChannel, ExpressionError, PrimitiveExpression, PrimitiveExpressions};
+pub use device::{init_headless, init_wgpu, InitError, InitOptions, REQUIRED_DOWNLEVEL_FLAGS};
+pub use frustum::{DebugFrustum, FRUSTUM_COLORS, MAX_DEBUG_FRUSTUMS};
+pub use gizmo::{Gizmo, GizmoSpace};
+pub use level::PickMode;
+pub use memory::GpuMemoryReport;
+pub use motion_blur::MotionBlurSettings;
+pub use palette::{PaletteError, PaletteManager, MAX_PALETTE_COLORS, NO_PALETTE};
+pub use primitives::{Axis, DynamicChannels, Falloff, LodSettings, PrimitiveError, DEFAULT_MAX_INSTANCES};
+pub use quality::{AdaptiveSteps, DEFAULT_MAX_STEPS};
+#[cfg(not(target_arch = "wasm32"))]
+pub use recording::{RecordError, FRAME_PLACEHOLDER};
+pub use reflections::ReflectionSettings;
+pub use render_targets::REQUIRED_TARGET_USAGES;
+pub use renderer::{Renderer, RendererError};
+pub use replay::{InputEvent, InputPlayer, InputRecorder, InputRecording, TimedInput};
+pub use settings::RendererSettings;
+#[cfg(all(feature = "hot_reload", not(target_arch = "wasm32")))]
+pub use shader_reload::{LEVEL_SHADER_FILES, SHADER_DIR};
+pub use textures::{TextureAtlas, TextureError, MAX_TEXTURES, NO_TEXTURE, TEXTURE_SIZE};
+pub use viewports::{ViewLayout, ViewRect};
+#[cfg(feature = "serde")]
+pub use scene::{Scene, SceneError};
+#[cfg(feature = "serde")]
+pub use settings::SettingsError;
+
+/// who gets keys both could use, the arrow keys in particular
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum InputMode {
+    #[default]
+    Camera,
+    /// the arrow keys nudge the selection, see `State::set_nudge_step`
+    Edit,
+}
+
 enum CurrentScene {
     Level(level::SingleLevelManager),
     GameOver,
@@ -28,13 +99,49 @@ pub(crate) struct State {
     // surface: wgpu::Surface,
     device: Arc<wgpu::Device>,
     queue: Arc<wgpu::Queue>,
+    /// owns the `GameRendering` resources used by the paint callback
+    renderer: Arc<RwLock<egui_wgpu::Renderer>>,
     // config: wgpu::SurfaceConfiguration,
+    /// of the render targets, `render_scale` times the painted rect's physical pixels
     size: (u32, u32),
+    render_scale: f32,
     clear_color: wgpu::Color,
     // render_pipeline: wgpu::RenderPipeline,
     mouse_pressed: bool,
     scene: CurrentScene,
     last_time: Option<Instant>,
+    culling_enabled: bool,
+    reflections: Option<ReflectionSettings>,
+    motion_blur: Option<MotionBlurSettings>,
+    bloom: Option<BloomSettings>,
+    dither: bool,
+    /// draw the selection's axes, see `Gizmo`
+    gizmo: bool,
+    /// draw the extra views' frustums in the main view, see `show_frustums`
+    frustums: bool,
+    /// the gizmo and frustums are hidden behind the primitives, see `set_overlay_depth_test`
+    overlay_depth_test: bool,
+    /// the image behind `set_environment`, kept for `RendererSettings`
+    environment: Option<PathBuf>,
+    /// see `set_debug_mode`, kept for `RendererSettings`
+    debug_mode: DebugMode,
+    view_layout: ViewLayout,
+    input_mode: InputMode,
+    /// how far one arrow key press moves the selection
+    nudge_step: f32,
+    capabilities: Capabilities,
+    /// what egui's render pass (and so the blit) draws into, picked by eframe
+    target_format: wgpu::TextureFormat,
+    /// the marcher's step count follows the frame time while set
+    adaptive_steps: Option<AdaptiveSteps>,
+    /// dragging with the middle mouse button pans, see `RenderCamera::process_pan`
+    middle_mouse_pan: bool,
+    /// reloads the scene file when it changes, see `watch_scene`
+    #[cfg(all(feature = "hot_reload", not(target_arch = "wasm32")))]
+    scene_watcher: Option<watcher::FileWatcher>,
+    /// rebuilds the marcher's pipeline when its wgsl changes, see `watch_shaders`
+    #[cfg(all(feature = "hot_reload", not(target_arch = "wasm32")))]
+    shader_watcher: Option<watcher::FileWatcher>,
 }
 
 impl State {
@@ -48,6 +155,7 @@ impl State {
             wgpu_render_state.device.clone(),
             wgpu_render_state.queue.clone(),
         );
+        let capabilities = Capabilities::from_device(&device);
 
         let size_vec2 = // cc.integration_info.window_info.size; //thats the full window size..
         // cc.egui_ctx.used_size();
@@ -55,64 +163,35 @@ impl State {
         let size: (u32, u32) = (size_vec2.x as u32, size_vec2.y as u32);
 
         let random_seed = fastrand::u64(..); //XXX: set according to level (from level-system)
-        let (mut single_level_manager, shader, render_pipeline_layout, bind_groups) =
-            level::SingleLevelManager::new(0.7, random_seed, &device, size);
+        let (mut single_level_manager, level_rendering) =
+            match level::SingleLevelManager::new(0.7, random_seed, &device, &queue, size) {
+                Ok(level) => level,
+                Err(err) => {
+                    tracing::error!("can't start the game: {err}");
+                    return None;
+                }
+            };
         single_level_manager.start(&queue);
-
+        let rendering = GameRendering::new(
+            &device,
+            &single_level_manager,
+            level_rendering,
+            size,
+            wgpu_render_state.target_format,
+        );
         let scene = CurrentScene::Level(single_level_manager);
 
-        //XXX: put that in the level man or a state match block?
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main", // 1.
-                buffers: &[Vertex::desc()],
-            },
-            fragment: Some(wgpu::FragmentState {
-                // 3.
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu_render_state.target_format.into())], //ah-ha
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList, // 1.
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw, // 2.
-                // cull_mode: Some(wgpu::Face::Back),
-                cull_mode: None,
-                // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
-                polygon_mode: wgpu::PolygonMode::Fill,
-                // Requires Features::DEPTH_CLIP_CONTROL
-                unclipped_depth: false,
-                // Requires Features::CONSERVATIVE_RASTERIZATION
-                conservative: false,
-            },
-            depth_stencil: None, // 1.
-            multisample: wgpu::MultisampleState {
-                count: 1,                         // 2.
-                mask: !0,                         // 3.
-                alpha_to_coverage_enabled: false, // 4.
-            },
-            multiview: None, // 5.
-        });
-
         wgpu_render_state
             .renderer
             .write()
             .paint_callback_resources
-            .insert(GameRendering {
-                render_pipeline,
-                bind_groups,
-                // diffuse_bind_group,
-                // diffuse_texture,
-            });
+            .insert(rendering);
 
-        Some(Self {
+        let mut state = Self {
             // surface,
             device,
             queue,
+            renderer: wgpu_render_state.renderer.clone(),
             size,
             clear_color: wgpu::Color::BLUE,
             // render_pipeline,
@@ -122,9 +201,593 @@ impl State {
             mouse_pressed: false,
             scene,
             last_time: None,
+            culling_enabled: false,
+            reflections: None,
+            motion_blur: None,
+            bloom: None,
+            dither: false,
+            gizmo: true,
+            frustums: false,
+            overlay_depth_test: true,
+            environment: None,
+            debug_mode: DebugMode::default(),
+            view_layout: ViewLayout::default(),
+            input_mode: InputMode::default(),
+            nudge_step: 1.0,
+            capabilities,
+            target_format: wgpu_render_state.target_format,
+            adaptive_steps: None,
+            render_scale: 1.0,
+            middle_mouse_pan: true,
+            #[cfg(all(feature = "hot_reload", not(target_arch = "wasm32")))]
+            scene_watcher: None,
+            #[cfg(all(feature = "hot_reload", not(target_arch = "wasm32")))]
+            shader_watcher: None,
+        };
+        #[cfg(all(feature = "serde", not(target_arch = "wasm32")))]
+        state.load_settings();
+        Some(state)
+    }
+
+    /// applies `RendererSettings::PATH` if there is one
+    #[cfg(all(feature = "serde", not(target_arch = "wasm32")))]
+    fn load_settings(&mut self) {
+        if !Path::new(RendererSettings::PATH).exists() {
+            return;
+        }
+        let applied = RendererSettings::load(RendererSettings::PATH)
+            .map(|settings| settings.apply(self));
+        match applied {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => tracing::warn!("can't load the environment: {err}"),
+            Err(err) => tracing::warn!("{err}"),
+        }
+    }
+
+    /// writes the current settings to `RendererSettings::PATH`, to be loaded on the next start
+    #[cfg(all(feature = "serde", not(target_arch = "wasm32")))]
+    pub fn save_settings(&self) {
+        if let Err(err) = RendererSettings::capture(self).save(RendererSettings::PATH) {
+            tracing::warn!("{err}");
+        }
+    }
+
+    /// replaces the level's primitives with the `Scene` in the toml file at `path`,
+    /// on failure the current ones are kept
+    #[cfg(all(feature = "serde", not(target_arch = "wasm32")))]
+    pub fn load_scene<P: AsRef<Path>>(&mut self, path: P) -> Result<(), SceneError> {
+        let CurrentScene::Level(single_level_manager) = &mut self.scene else {
+            return Ok(());
+        };
+        let scene = Scene::load(path)?;
+        #[cfg(feature = "expressions")]
+        let expressions = scene
+            .expressions
+            .iter()
+            .map(expressions::CompiledExpression::new)
+            .collect::<Result<Vec<_>, _>>()?;
+        let bind_group = single_level_manager.primitive_manager.replace_all(
+            &scene.primitives,
+            &self.device,
+            &self.queue,
+        )?;
+        #[cfg(feature = "expressions")]
+        single_level_manager.primitive_manager.expressions_mut().replace_all(expressions);
+        if let Some(bind_group) = bind_group {
+            let mut renderer = self.renderer.write();
+            let resources: &mut GameRendering = renderer.paint_callback_resources.get_mut().unwrap();
+            resources.set_primitives_bind_group(&self.device, bind_group, scene.primitives.len());
+        }
+        Ok(())
+    }
+
+    /// writes the level's primitives to a toml file, see `load_scene`
+    #[cfg(all(feature = "serde", not(target_arch = "wasm32")))]
+    pub fn save_scene<P: AsRef<Path>>(&self, path: P) -> Result<(), SceneError> {
+        match self.level() {
+            Some(level) => Scene::capture(&level.primitive_manager).save(path),
+            None => Ok(()),
+        }
+    }
+
+    /// loads the scene at `path` now and again whenever the file changes, a few saves in
+    /// quick succession only reload once. a file that fails to load is logged and the last
+    /// good scene kept. replaces any scene watched before
+    #[cfg(all(feature = "hot_reload", not(target_arch = "wasm32")))]
+    pub fn watch_scene<P: AsRef<Path>>(&mut self, path: P) -> Result<(), notify::Error> {
+        self.scene_watcher = Some(watcher::FileWatcher::new(&[path.as_ref()])?);
+        if let Err(err) = self.load_scene(path) {
+            tracing::warn!("{err}");
+        }
+        Ok(())
+    }
+
+    #[cfg(all(feature = "hot_reload", not(target_arch = "wasm32")))]
+    pub fn stop_watching_scene(&mut self) {
+        self.scene_watcher = None;
+    }
+
+    #[cfg(all(feature = "hot_reload", not(target_arch = "wasm32")))]
+    fn reload_changed_scene(&mut self) {
+        let Some(watcher) = &mut self.scene_watcher else {
+            return;
+        };
+        if !watcher.poll() {
+            return;
+        }
+        let path = watcher.paths()[0].clone();
+        match self.load_scene(&path) {
+            Ok(()) => tracing::info!("reloaded {}", path.display()),
+            Err(err) => tracing::warn!("{err}, keeping the last scene"),
+        }
+    }
+
+    /// rebuilds the marcher's pipeline whenever one of its wgsl files (see `LEVEL_SHADER_FILES`)
+    /// in `SHADER_DIR` changes. a shader that fails to compile is logged and the last working
+    /// pipeline kept. the other passes keep the shaders they were built with
+    #[cfg(all(feature = "hot_reload", not(target_arch = "wasm32")))]
+    pub fn watch_shaders(&mut self) -> Result<(), notify::Error> {
+        let paths = shader_reload::level_shader_paths();
+        let paths: Vec<&Path> = paths.iter().map(PathBuf::as_path).collect();
+        self.shader_watcher = Some(watcher::FileWatcher::new(&paths)?);
+        Ok(())
+    }
+
+    #[cfg(all(feature = "hot_reload", not(target_arch = "wasm32")))]
+    pub fn stop_watching_shaders(&mut self) {
+        self.shader_watcher = None;
+    }
+
+    #[cfg(all(feature = "hot_reload", not(target_arch = "wasm32")))]
+    fn reload_changed_shaders(&mut self) {
+        let Some(watcher) = &mut self.shader_watcher else {
+            return;
+        };
+        if !watcher.poll() {
+            return;
+        }
+        let source = match shader_reload::read_level_shader() {
+            Ok(source) => source,
+            Err(err) => {
+                tracing::warn!("can't read the shader: {err}, keeping the last one");
+                return;
+            }
+        };
+        let mut renderer = self.renderer.write();
+        let resources: &mut GameRendering = renderer.paint_callback_resources.get_mut().unwrap();
+        match resources.reload_level_shader(&self.device, source) {
+            Ok(()) => tracing::info!("reloaded the level shader"),
+            Err(err) => tracing::warn!("{err}, keeping the last shader"),
+        }
+    }
+
+    /// what the renderer's buffers and textures take on the gpu, to compare against
+    /// `capabilities`. the post passes' targets count whether or not the passes are enabled
+    pub fn gpu_memory_report(&self) -> GpuMemoryReport {
+        let mut report = GpuMemoryReport::default();
+        if let Some(level) = self.level() {
+            let primitive_manager = &level.primitive_manager;
+            report.add("primitives", primitive_manager.memory_usage() as u64);
+            report.add("primitive order", primitive_manager.order_memory_usage());
+            report.add("primitive channels", primitive_manager.channels_memory_usage());
+            report.add("spatial grid", primitive_manager.grid.memory_usage());
+            report.add("palette", primitive_manager.palette().memory_usage());
+            report.add("textures", primitive_manager.textures().memory_usage());
+            let cameras = std::iter::once(&level.camera).chain(level.views.iter());
+            report.add("cameras", cameras.map(|camera| camera.buffer.size()).sum());
+        }
+        let renderer = self.renderer.read();
+        let resources: &GameRendering = renderer.paint_callback_resources.get().unwrap();
+        report.add("render targets", resources.targets.memory_usage());
+        report.add("culling", resources.culling.memory_usage());
+        report.add("environment", resources.environment.memory_usage());
+        report.add("reflections", resources.reflections.memory_usage());
+        report.add("motion blur", resources.motion_blur.memory_usage());
+        report.add("bloom", resources.bloom.memory_usage());
+        report.add("gizmo", resources.gizmo.memory_usage());
+        report.add("frustums", resources.frustums.memory_usage());
+        report.add("blit", resources.blit.memory_usage());
+        report
+    }
+
+    fn level(&self) -> Option<&level::SingleLevelManager> {
+        match &self.scene {
+            CurrentScene::Level(single_level_manager) => Some(single_level_manager),
+            CurrentScene::GameOver => None,
+        }
+    }
+
+    /// the device limits queried at startup
+    pub fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    /// the format of the surface the level ends up on, pipelines drawing into egui's
+    /// render pass (like the blit) have to target it, the offscreen passes use `COLOR_FORMAT`
+    pub fn target_format(&self) -> wgpu::TextureFormat {
+        self.target_format
+    }
+
+    /// opt-in frustum culling compute pre-pass, worth it for scenes with many primitives
+    pub fn enable_culling(&mut self, enabled: bool) {
+        self.culling_enabled = enabled;
+    }
+
+    /// opt-in screen-space reflections on primitives with a `metalness` above the threshold,
+    /// `None` skips the pass
+    pub fn set_reflections(&mut self, reflections: Option<ReflectionSettings>) {
+        self.reflections = reflections;
+    }
+
+    /// opt-in camera motion blur, `None` skips the pass
+    pub fn set_motion_blur(&mut self, motion_blur: Option<MotionBlurSettings>) {
+        self.motion_blur = motion_blur;
+    }
+
+    /// opt-in glow around what's brighter than the threshold (e.g. emissive primitives),
+    /// `None` skips the pass
+    pub fn set_bloom(&mut self, bloom: Option<BloomSettings>) {
+        self.bloom = bloom;
+    }
+
+    /// whether the selection's axes are drawn, on by default
+    pub fn show_gizmo(&mut self, show: bool) {
+        self.gizmo = show;
+    }
+
+    /// whether the extra views' frustums (see `add_view`) are drawn as wireframes in the
+    /// main view, in the `FRUSTUM_COLORS` by view, off by default
+    pub fn show_frustums(&mut self, show: bool) {
+        self.frustums = show;
+    }
+
+    /// whether the primitives hide the gizmo and the frustums behind them (on by default),
+    /// off draws them on top of everything
+    pub fn set_overlay_depth_test(&mut self, depth_test: bool) {
+        self.overlay_depth_test = depth_test;
+    }
+
+    /// whether the gizmo shows and drags along the selected primitive's axes or the world's
+    pub fn set_gizmo_space(&mut self, space: GizmoSpace) {
+        if let CurrentScene::Level(single_level_manager) = &mut self.scene {
+            single_level_manager.gizmo_space = space;
+        }
+    }
+
+    /// whether hovering and focusing pick transparent primitives or look through them
+    pub fn set_pick_mode(&mut self, mode: PickMode) {
+        if let CurrentScene::Level(single_level_manager) = &mut self.scene {
+            single_level_manager.pick_mode = mode;
+        }
+    }
+
+    /// lowers the detail of distant primitives, see `PrimitiveManager::set_lod`
+    pub fn set_lod(&mut self, lod: Option<LodSettings>) {
+        if let CurrentScene::Level(single_level_manager) = &mut self.scene {
+            single_level_manager.primitive_manager.set_lod(lod, &self.queue);
+        }
+    }
+
+    /// caps how many primitives each distance evaluation folds, see
+    /// `PrimitiveManager::set_complexity_budget`
+    pub fn set_complexity_budget(&mut self, budget: Option<u32>) {
+        if let CurrentScene::Level(single_level_manager) = &mut self.scene {
+            single_level_manager.primitive_manager.set_complexity_budget(budget, &self.queue);
+        }
+    }
+
+    /// creates the render targets with `usage` too, see `REQUIRED_TARGET_USAGES` for which
+    /// features need which. `record_sequence` adds what it needs by itself
+    pub fn add_target_usage(&mut self, usage: wgpu::TextureUsages) {
+        let mut renderer = self.renderer.write();
+        let resources: &mut GameRendering = renderer.paint_callback_resources.get_mut().unwrap();
+        resources.add_target_usage(&self.device, usage);
+    }
+
+    /// calls `f` with the level's bind group layouts in pipeline order (see `PRIMITIVES_GROUP`
+    /// and following), to build pipelines that are compatible with the level's bind groups.
+    /// `None` if there's no level
+    pub fn with_bind_group_layouts<R>(
+        &self,
+        f: impl FnOnce(&[&wgpu::BindGroupLayout]) -> R,
+    ) -> Option<R> {
+        let CurrentScene::Level(single_level_manager) = &self.scene else {
+            return None;
+        };
+        let renderer = self.renderer.read();
+        let resources: &GameRendering = renderer.paint_callback_resources.get().unwrap();
+        Some(f(&[
+            &single_level_manager.primitive_manager.bind_group_layout,
+            &single_level_manager.camera.bind_group_layout,
+            &resources.culling.render_bind_group_layout,
+            &resources.environment.bind_group_layout,
+        ]))
+    }
+
+    /// a pipeline layout matching the level's, for custom shaders over the same buffers
+    pub fn create_pipeline_layout(&self, label: Option<&str>) -> Option<wgpu::PipelineLayout> {
+        self.with_bind_group_layouts(|bind_group_layouts| {
+            self.device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label,
+                bind_group_layouts,
+                push_constant_ranges: &[],
+            })
         })
     }
 
+    /// adds another camera rendering the same scene next to the existing ones (see
+    /// `set_view_layout`), returns its index in `SingleLevelManager::views`
+    ///
+    /// all views share the primitives, only their camera bind groups differ. the post passes and
+    /// the culling pre-pass only know the main camera, so culling is skipped while there are
+    /// extra views and reflections/motion blur are only right in the first one
+    pub fn add_view(&mut self) -> Option<usize> {
+        let CurrentScene::Level(single_level_manager) = &mut self.scene else {
+            return None;
+        };
+        let (mut view, _) = camera::RenderCamera::new(&self.device, self.size, level::VIEW_DST);
+        view.uniform.contour = single_level_manager.camera.uniform.contour;
+        view.uniform.debug_mode = single_level_manager.camera.uniform.debug_mode;
+        view.uniform.edge_aa = single_level_manager.camera.uniform.edge_aa;
+        let mut renderer = self.renderer.write();
+        let resources: &mut GameRendering = renderer.paint_callback_resources.get_mut().unwrap();
+        resources
+            .view_bind_groups
+            .push(view.bind_group(&self.device, &single_level_manager.camera.bind_group_layout));
+        single_level_manager.views.push(view);
+        single_level_manager.resize(self.size, self.view_layout);
+        Some(single_level_manager.views.len() - 1)
+    }
+
+    /// back to the main camera only
+    pub fn clear_views(&mut self) {
+        if let CurrentScene::Level(single_level_manager) = &mut self.scene {
+            single_level_manager.views.clear();
+            single_level_manager.resize(self.size, self.view_layout);
+        }
+        let mut renderer = self.renderer.write();
+        let resources: &mut GameRendering = renderer.paint_callback_resources.get_mut().unwrap();
+        resources.view_bind_groups.clear();
+    }
+
+    /// side by side stereo from the main camera, with the eyes `ipd` apart (see
+    /// `RenderCamera::stereo_pair`), `None` goes back to a single view.
+    /// replaces any extra views
+    pub fn set_stereo(&mut self, ipd: Option<f32>) {
+        self.clear_views();
+        if ipd.is_some() {
+            // only used for its buffer, the right eye is written into it every frame
+            self.add_view();
+        }
+        if let CurrentScene::Level(single_level_manager) = &mut self.scene {
+            single_level_manager.stereo = ipd;
+            single_level_manager.resize(self.size, self.view_layout);
+        }
+    }
+
+    pub fn set_view_layout(&mut self, layout: ViewLayout) {
+        self.view_layout = layout;
+        if let CurrentScene::Level(single_level_manager) = &mut self.scene {
+            single_level_manager.resize(self.size, layout);
+        }
+    }
+
+    pub fn set_input_mode(&mut self, input_mode: InputMode) {
+        self.input_mode = input_mode;
+    }
+
+    pub fn set_nudge_step(&mut self, nudge_step: f32) {
+        self.nudge_step = nudge_step;
+    }
+
+    /// in edit mode with a selection, left/right nudge it along x, up/down along y
+    /// and along z while holding shift
+    fn nudge_with_keys(&mut self, ctx: &egui::Context) {
+        if self.input_mode != InputMode::Edit {
+            return;
+        }
+        let CurrentScene::Level(single_level_manager) = &mut self.scene else {
+            return;
+        };
+        if single_level_manager.primitive_manager.selection().is_empty() {
+            return;
+        }
+        let nudges: Vec<(Axis, f32)> = ctx.input(|input| {
+            input
+                .events
+                .iter()
+                .filter_map(|event| match event {
+                    egui::Event::Key { key, pressed: true, modifiers, .. } => {
+                        let vertical = if modifiers.shift { Axis::Z } else { Axis::Y };
+                        match key {
+                            egui::Key::ArrowLeft => Some((Axis::X, -1.0)),
+                            egui::Key::ArrowRight => Some((Axis::X, 1.0)),
+                            egui::Key::ArrowUp => Some((vertical, 1.0)),
+                            egui::Key::ArrowDown => Some((vertical, -1.0)),
+                            _ => None,
+                        }
+                    }
+                    _ => None,
+                })
+                .collect()
+        });
+        for (axis, direction) in nudges {
+            single_level_manager.primitive_manager.nudge_selection(
+                axis,
+                direction * self.nudge_step,
+                &self.queue,
+            );
+        }
+    }
+
+    /// constant simulation steps instead of the frame time, see `SingleLevelManager::set_fixed_timestep`
+    pub fn set_fixed_timestep(&mut self, step: Option<Duration>, with_camera: bool) {
+        if let CurrentScene::Level(single_level_manager) = &mut self.scene {
+            single_level_manager.set_fixed_timestep(step);
+            single_level_manager.fixed_timestep_camera = with_camera;
+        }
+    }
+
+    /// records the camera input from now on, see `InputRecorder`. replaces a running recording
+    pub fn start_input_recording(&mut self) {
+        if let CurrentScene::Level(single_level_manager) = &mut self.scene {
+            single_level_manager.input_recorder = Some(InputRecorder::new());
+        }
+    }
+
+    /// `None` if nothing was being recorded
+    pub fn stop_input_recording(&mut self) -> Option<InputRecording> {
+        match &mut self.scene {
+            CurrentScene::Level(single_level_manager) => {
+                single_level_manager.input_recorder.take().map(InputRecorder::finish)
+            }
+            CurrentScene::GameOver => None,
+        }
+    }
+
+    /// feeds `recording` into the camera at its recorded times instead of the live input,
+    /// until it's over. start from the same state (seed, camera, fixed timestep) as the
+    /// recording to get the same session
+    pub fn play_input(&mut self, recording: InputRecording) {
+        if let CurrentScene::Level(single_level_manager) = &mut self.scene {
+            single_level_manager.input_player = Some(InputPlayer::new(recording));
+        }
+    }
+
+    pub fn is_playing_input(&self) -> bool {
+        self.level().map_or(false, |level| level.input_player.is_some())
+    }
+
+    /// how much level time has passed, scaled by the time scale, see `SingleLevelManager::elapsed`.
+    /// zero without a level
+    pub fn elapsed(&self) -> Duration {
+        self.level().map_or(Duration::ZERO, |level| level.elapsed())
+    }
+
+    /// slows down or speeds up the animation, see `SingleLevelManager::set_time_scale`
+    pub fn set_time_scale(&mut self, time_scale: f32, with_camera: bool) {
+        if let CurrentScene::Level(single_level_manager) = &mut self.scene {
+            single_level_manager.set_time_scale(time_scale);
+            single_level_manager.time_scale_camera = with_camera;
+        }
+    }
+
+    /// bands the background by the distance to the primitives, see `ContourSettings`
+    pub fn set_contour(&mut self, contour: ContourSettings) {
+        if let CurrentScene::Level(single_level_manager) = &mut self.scene {
+            single_level_manager.set_contour(contour);
+        }
+    }
+
+    /// softens the silhouettes, on by default, see `EdgeAA`
+    pub fn set_edge_aa(&mut self, edge_aa: EdgeAA) {
+        if let CurrentScene::Level(single_level_manager) = &mut self.scene {
+            single_level_manager.set_edge_aa(edge_aa);
+        }
+    }
+
+    /// the camera's numbers for a debug readout, see `RenderCamera::debug_state`
+    pub fn camera_debug_state(&self) -> Option<CameraDebugState> {
+        self.level().map(|level| level.camera.debug_state())
+    }
+
+    /// moves the camera's far plane out with the distance to the primitives, see
+    /// `Projection::set_auto_clip`
+    pub fn set_auto_clip(&mut self, enabled: bool) {
+        if let CurrentScene::Level(single_level_manager) = &mut self.scene {
+            single_level_manager.camera.projection.set_auto_clip(enabled);
+        }
+    }
+
+    /// scales the camera's speed with its distance to the primitives, see
+    /// `CameraController::set_auto_speed`
+    pub fn set_auto_speed(&mut self, enabled: bool) {
+        if let CurrentScene::Level(single_level_manager) = &mut self.scene {
+            single_level_manager.camera.controller.set_auto_speed(enabled);
+        }
+    }
+
+    /// `CameraController::set_scroll_decay`
+    pub fn set_scroll_decay(&mut self, scroll_decay: f32) {
+        if let CurrentScene::Level(single_level_manager) = &mut self.scene {
+            single_level_manager.camera.controller.set_scroll_decay(scroll_decay);
+        }
+    }
+
+    /// shows the marcher's step counts, distances or normals instead of the shading, see `DebugMode`
+    pub fn set_debug_mode(&mut self, debug_mode: DebugMode) {
+        self.debug_mode = debug_mode;
+        if let CurrentScene::Level(single_level_manager) = &mut self.scene {
+            single_level_manager.set_debug_mode(debug_mode);
+        }
+    }
+
+    /// whether dragging with the middle mouse button pans instead of moving the camera
+    /// like the other buttons, on by default
+    pub fn set_middle_mouse_pan(&mut self, middle_mouse_pan: bool) {
+        self.middle_mouse_pan = middle_mouse_pan;
+    }
+
+    /// renders the level at a fraction (0.25..=1) of the painted rect's resolution, the blit
+    /// scales it back up. cursor positions are scaled the same way, so picking stays where the
+    /// cursor is. takes effect with the next paint
+    pub fn set_render_scale(&mut self, render_scale: f32) {
+        self.render_scale = render_scale.clamp(0.25, 1.0);
+    }
+
+    pub fn render_scale(&self) -> f32 {
+        self.render_scale
+    }
+
+    /// adapts the marcher's step count every frame to hold `target` (e.g. 16.6ms for 60fps),
+    /// within the bounds of `AdaptiveSteps`. `None` goes back to `DEFAULT_MAX_STEPS`
+    pub fn set_target_frame_time(&mut self, target: Option<Duration>) {
+        self.adaptive_steps = target.map(AdaptiveSteps::new);
+        if let (None, CurrentScene::Level(single_level_manager)) = (target, &mut self.scene) {
+            single_level_manager.set_max_steps(DEFAULT_MAX_STEPS);
+        }
+    }
+
+    pub fn adaptive_steps(&self) -> Option<&AdaptiveSteps> {
+        self.adaptive_steps.as_ref()
+    }
+
+    /// opt-in dithering of the final output against banding in smooth gradients
+    pub fn set_dither(&mut self, dither: bool) {
+        self.dither = dither;
+    }
+
+    /// uses an equirectangular image as the background instead of the plain clear color
+    pub fn set_environment<P: AsRef<Path>>(&mut self, path: P) -> Result<(), image::ImageError> {
+        let mut renderer = self.renderer.write();
+        let resources: &mut GameRendering = renderer.paint_callback_resources.get_mut().unwrap();
+        resources.bind_groups.environment_bind_group =
+            resources.environment.load(&self.device, &self.queue, path.as_ref())?;
+        self.environment = Some(path.as_ref().to_owned());
+        Ok(())
+    }
+
+    /// goes back to the plain background
+    pub fn clear_environment(&mut self) {
+        let mut renderer = self.renderer.write();
+        let resources: &mut GameRendering = renderer.paint_callback_resources.get_mut().unwrap();
+        resources.bind_groups.environment_bind_group =
+            resources.environment.clear(&self.device, &self.queue);
+        self.environment = None;
+    }
+
+    /// loads an image into layer `index` of the primitives' texture atlas, see
+    /// `SDFPrimitive::set_texture`
+    pub fn load_texture<P: AsRef<Path>>(&mut self, index: u32, path: P) -> Result<(), TextureError> {
+        if let CurrentScene::Level(single_level_manager) = &mut self.scene {
+            single_level_manager
+                .primitive_manager
+                .textures_mut()
+                .load(index, path, &self.queue)?;
+        }
+        Ok(())
+    }
+
     pub fn resize(&mut self, new_size: (u32, u32)) {
         if new_size.0 > 0 && new_size.1 > 0 {
             self.size = new_size;
@@ -133,7 +796,7 @@ impl State {
             // self.surface.configure(&self.device, &self.config);
             match &mut self.scene {
                 CurrentScene::Level(ref mut single_level_manager) => {
-                    single_level_manager.resize(new_size);
+                    single_level_manager.resize(new_size, self.view_layout);
                 }
                 _ => {}
             }
@@ -171,8 +834,16 @@ impl State {
             Duration::from_secs(0)
         };
         self.last_time = Some(now);
+        #[cfg(all(feature = "hot_reload", not(target_arch = "wasm32")))]
+        {
+            self.reload_changed_scene();
+            self.reload_changed_shaders();
+        }
         match &mut self.scene {
             CurrentScene::Level(single_level_manager) => {
+                if let Some(adaptive_steps) = &mut self.adaptive_steps {
+                    single_level_manager.set_max_steps(adaptive_steps.update(dt));
+                }
                 single_level_manager.update(dt, &self.queue);
                 if single_level_manager.game_over {
                     self.scene = CurrentScene::GameOver;
@@ -185,6 +856,12 @@ impl State {
 
 impl eframe::App for State {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        if !ctx.input(|input| input.raw.has_focus) {
+            if let CurrentScene::Level(single_level_manager) = &mut self.scene {
+                single_level_manager.camera.reset_input();
+            }
+        }
+        self.nudge_with_keys(ctx);
         self.update();
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.vertical(|ui| {
@@ -230,34 +907,108 @@ impl eframe::App for State {
 }
 
 impl State {
+    /// what the renderer needs to know to draw the current state
+    fn frame_settings(&self) -> FrameSettings {
+        let (primitive_count, views, stereo, gizmo) = match &self.scene {
+            CurrentScene::Level(single_level_manager) => (
+                single_level_manager.primitive_manager.len(),
+                1 + single_level_manager.views.len(),
+                single_level_manager.stereo.is_some(),
+                single_level_manager.gizmo().filter(|_| self.gizmo),
+            ),
+            CurrentScene::GameOver => {
+                //TODO: render game over screen
+                (0, 1, false, None)
+            }
+        };
+        let mut frustums = [None; MAX_DEBUG_FRUSTUMS];
+        if let (true, Some(level)) = (self.frustums, self.level()) {
+            for (i, (frustum, view)) in frustums.iter_mut().zip(&level.views).enumerate() {
+                *frustum = Some(DebugFrustum {
+                    corners: view.frustum_corners(),
+                    color: FRUSTUM_COLORS[i % FRUSTUM_COLORS.len()],
+                });
+            }
+        }
+        FrameSettings {
+            size: self.size,
+            culling_enabled: self.culling_enabled,
+            primitive_count,
+            reflections: self.reflections,
+            motion_blur: self.motion_blur,
+            bloom: self.bloom,
+            dither: self.dither,
+            gizmo,
+            frustums,
+            overlay_depth_test: self.overlay_depth_test,
+            views,
+            view_layout: if stereo { ViewLayout::SideBySide } else { self.view_layout },
+        }
+    }
+
     fn custom_painting(&mut self, ui: &mut egui::Ui) {
-        let (rect, response) = ui.allocate_exact_size(ui.available_size(), egui::Sense::drag());
+        let (rect, response) =
+            ui.allocate_exact_size(ui.available_size(), egui::Sense::click_and_drag());
 
         // let angle += response.drag_delta().x * 0.01;
         // this shouldnt be the final controls but i think i'll go back to bevy for that
         let drag = response.drag_delta();
+        // from points to the pixels of the (possibly smaller) render targets
+        let scale = ui.ctx().pixels_per_point() * self.render_scale;
+        let scroll = ui.input(|input| input.scroll_delta.y);
         match &mut self.scene {
             CurrentScene::Level(single_level_manager) => {
-                let delta = cgmath::Vector3::new(drag.x, drag.y, 0.0);
-                single_level_manager.move_by(delta);
+                let to_pixel = |cursor: egui::Pos2| {
+                    let pixel = (cursor - rect.min) * scale;
+                    cgmath::Vector2::new(pixel.x, pixel.y)
+                };
+                let cursor_pixel = response.interact_pointer_pos().map(to_pixel);
+                // dragging a gizmo axis moves the selection instead of the camera, the drag
+                // only starts after moving a bit so grab where the button went down
+                if self.gizmo && response.drag_started() {
+                    if let Some(press) = ui.input(|input| input.pointer.press_origin()) {
+                        single_level_manager.grab_gizmo_at(to_pixel(press));
+                    }
+                }
+                if single_level_manager.is_dragging_gizmo() {
+                    if let Some(pixel) = cursor_pixel {
+                        single_level_manager.drag_gizmo_to(pixel, &self.queue);
+                    }
+                } else if self.middle_mouse_pan && response.dragged_by(egui::PointerButton::Middle) {
+                    let (dx, dy) = (drag.x * scale, drag.y * scale);
+                    single_level_manager.process_input(InputEvent::Pan {
+                        dx: dx as f64,
+                        dy: dy as f64,
+                    });
+                } else {
+                    let delta = cgmath::Vector3::new(drag.x, drag.y, 0.0);
+                    single_level_manager.move_by(delta);
+                }
+                if response.drag_released() {
+                    single_level_manager.release_gizmo();
+                }
+                if let Some(cursor) = response.interact_pointer_pos().filter(|_| response.double_clicked()) {
+                    let pixel = (cursor - rect.min) * scale;
+                    single_level_manager.focus_at(cgmath::Vector2::new(pixel.x, pixel.y));
+                }
+                if let Some(cursor) = response.hover_pos().filter(|_| scroll != 0.0) {
+                    let pixel = (cursor - rect.min) * scale;
+                    single_level_manager.scroll_at(
+                        camera::ScrollDelta::Pixels(scroll),
+                        cgmath::Vector2::new(pixel.x, pixel.y),
+                    );
+                }
             }
             CurrentScene::GameOver => {}
         }
 
-        let scale = ui.ctx().pixels_per_point();
-
         self.resize((
             (rect.width() * scale) as u32,
             (rect.height() * scale) as u32,
         ));
         // let time_delta = response.ctx
 
-        match &mut self.scene {
-            CurrentScene::Level(single_level_manager) => {}
-            CurrentScene::GameOver => {
-                //TODO: render game over screen
-            }
-        }
+        let frame = self.frame_settings();
 
         // The callback function for WGPU is in two stages: prepare, and paint.
         //
@@ -273,14 +1024,14 @@ impl State {
         // The paint callback is called after prepare and is given access to the render pass, which
         // can be used to issue draw commands.
         let cb = egui_wgpu::CallbackFn::new()
-            .prepare(move |device, queue, _encoder, paint_callback_resources| {
-                // let resources = paint_callback_resources.get().unwrap();
-                // resources.prepare(device, queue, angle);
+            .prepare(move |device, queue, encoder, paint_callback_resources| {
+                let resources: &mut GameRendering = paint_callback_resources.get_mut().unwrap();
+                resources.prepare(device, queue, encoder, &frame);
                 Vec::new()
             })
             .paint(move |_info, render_pass, paint_callback_resources| {
                 let resources: &GameRendering = paint_callback_resources.get().unwrap();
-                resources.render(render_pass);
+                resources.render(render_pass, &frame);
             });
 
         let callback = egui::PaintCallback {
@@ -292,26 +1043,370 @@ impl State {
     }
 }
 
+// bind group indices of the level's pipelines, custom shaders reusing the level's buffers
+// have to declare them the same way (common.wgsl has the wgsl side)
+/// the primitives (binding 0), the spatial grid (1..=3) and the transparency order (4)
+pub const PRIMITIVES_GROUP: u32 = 0;
+/// the `CameraUniform` (binding 0)
+pub const CAMERA_GROUP: u32 = 1;
+/// the culling pass' visible list (header binding 0, indices 1), post passes bind their inputs here
+pub const VISIBLE_GROUP: u32 = 2;
+/// the environment map (texture binding 0, sampler 1, header 2)
+pub const ENVIRONMENT_GROUP: u32 = 3;
+
 pub(crate) struct BindGroups {
     camera_bind_group: wgpu::BindGroup,
     primitives_bind_group: wgpu::BindGroup,
+    visible_bind_group: wgpu::BindGroup,
+    environment_bind_group: wgpu::BindGroup,
+}
+
+/// what the paint callback needs to know about the current frame
+#[derive(Debug, Clone, Copy)]
+struct FrameSettings {
+    size: (u32, u32),
+    culling_enabled: bool,
+    primitive_count: usize,
+    reflections: Option<ReflectionSettings>,
+    motion_blur: Option<MotionBlurSettings>,
+    bloom: Option<BloomSettings>,
+    dither: bool,
+    gizmo: Option<Gizmo>,
+    /// drawn in the main view, the first `MAX_DEBUG_FRUSTUMS` extra views' while
+    /// `State::show_frustums` is on
+    frustums: [Option<DebugFrustum>; MAX_DEBUG_FRUSTUMS],
+    /// whether the gizmo and frustums test against the marched depth
+    overlay_depth_test: bool,
+    /// how many cameras share the targets, the main one included
+    views: usize,
+    view_layout: ViewLayout,
 }
+
+//XXX: put that in the level man or a state match block?
+/// the marcher's pipeline, `shader` being common.wgsl, environment.wgsl and main_shader.wgsl
+fn mk_level_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Render Pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main", // 1.
+            buffers: &[Vertex::desc()],
+        },
+        fragment: Some(wgpu::FragmentState {
+            // 3.
+            module: shader,
+            entry_point: "fs_main",
+            targets: &[
+                Some(render_targets::COLOR_FORMAT.into()),
+                Some(render_targets::GBUFFER_FORMAT.into()),
+            ],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList, // 1.
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw, // 2.
+            // cull_mode: Some(wgpu::Face::Back),
+            cull_mode: None,
+            // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
+            polygon_mode: wgpu::PolygonMode::Fill,
+            // Requires Features::DEPTH_CLIP_CONTROL
+            unclipped_depth: false,
+            // Requires Features::CONSERVATIVE_RASTERIZATION
+            conservative: false,
+        },
+        // the hits' depth, for the overlays drawn afterwards. every pixel is written
+        // (misses at the far plane), so the test always passes
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: render_targets::DEPTH_FORMAT,
+            depth_write_enabled: true,
+            depth_compare: wgpu::CompareFunction::Always,
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: 1,                         // 2.
+            mask: !0,                         // 3.
+            alpha_to_coverage_enabled: false, // 4.
+        },
+        multiview: None, // 5.
+    })
+}
+
 struct GameRendering {
     render_pipeline: wgpu::RenderPipeline,
+    /// kept to rebuild `render_pipeline` when the shader is reloaded, see `State::watch_shaders`
+    #[cfg(all(feature = "hot_reload", not(target_arch = "wasm32")))]
+    render_pipeline_layout: wgpu::PipelineLayout,
     bind_groups: BindGroups,
+    culling: culling::CullingPass,
+    environment: environment::Environment,
+    targets: render_targets::RenderTargets,
+    reflections: reflections::ReflectionPass,
+    motion_blur: motion_blur::MotionBlurPass,
+    bloom: bloom::BloomPass,
+    gizmo: gizmo::GizmoPass,
+    frustums: frustum::FrustumPass,
+    blit: render_targets::Blit,
+    blit_color_bind_group: wgpu::BindGroup,
+    blit_post_bind_group: wgpu::BindGroup,
+    blit_blurred_bind_group: wgpu::BindGroup,
+    /// camera bind groups of the extra views, see `State::add_view`
+    view_bind_groups: Vec<wgpu::BindGroup>,
 }
 
 impl GameRendering {
-    fn render<'rp>(&'rp self, render_pass: &mut wgpu::RenderPass<'rp>) {
-        render_pass.set_pipeline(&self.render_pipeline);
-        // render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
+    /// everything drawing `level` into a `target_format` pass, `size` being the render targets'
+    fn new(
+        device: &wgpu::Device,
+        level: &level::SingleLevelManager,
+        level_rendering: level::LevelRendering,
+        size: (u32, u32),
+        target_format: wgpu::TextureFormat,
+    ) -> Self {
+        let level::LevelRendering {
+            shader,
+            pipeline_layout: render_pipeline_layout,
+            bind_groups,
+            culling,
+            environment,
+        } = level_rendering;
+        let targets = render_targets::RenderTargets::new(device, size, wgpu::TextureUsages::empty());
+        let reflections = reflections::ReflectionPass::new(
+            device,
+            &level.primitive_manager.bind_group_layout,
+            &level.camera.bind_group_layout,
+            &environment.bind_group_layout,
+            &targets,
+        );
+        let motion_blur = motion_blur::MotionBlurPass::new(
+            device,
+            &level.primitive_manager.bind_group_layout,
+            &level.camera.bind_group_layout,
+            &targets,
+        );
+        let bloom = bloom::BloomPass::new(device, &targets);
+        let gizmo = gizmo::GizmoPass::new(
+            device,
+            &level.primitive_manager.bind_group_layout,
+            &level.camera.bind_group_layout,
+        );
+        let frustums = frustum::FrustumPass::new(
+            device,
+            &level.primitive_manager.bind_group_layout,
+            &level.camera.bind_group_layout,
+        );
 
-        render_pass.set_bind_group(1, &self.bind_groups.camera_bind_group, &[]);
-        render_pass.set_bind_group(0, &self.bind_groups.primitives_bind_group, &[]);
+        let render_pipeline = mk_level_pipeline(device, &render_pipeline_layout, &shader);
+        let blit = render_targets::Blit::new(device, target_format);
+        let blit_color_bind_group = blit.bind_group(device, &targets.color);
+        let blit_post_bind_group = blit.bind_group(device, &targets.post);
+        let blit_blurred_bind_group = blit.bind_group(device, &targets.blurred);
+
+        Self {
+            render_pipeline,
+            #[cfg(all(feature = "hot_reload", not(target_arch = "wasm32")))]
+            render_pipeline_layout,
+            bind_groups,
+            culling,
+            environment,
+            targets,
+            reflections,
+            motion_blur,
+            bloom,
+            gizmo,
+            frustums,
+            blit,
+            blit_color_bind_group,
+            blit_post_bind_group,
+            blit_blurred_bind_group,
+            view_bind_groups: Vec::new(),
+            // diffuse_bind_group,
+            // diffuse_texture,
+        }
+    }
 
-        // render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-        // render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16); // 1.
-        render_pass.draw(0..6, 0..1);
+    /// swaps in a pipeline built from `source` (see `mk_level_pipeline`), keeps the current
+    /// one if it doesn't compile
+    #[cfg(all(feature = "hot_reload", not(target_arch = "wasm32")))]
+    fn reload_level_shader(&mut self, device: &wgpu::Device, source: String) -> Result<(), wgpu::Error> {
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Main Shader"),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+        let render_pipeline = mk_level_pipeline(device, &self.render_pipeline_layout, &shader);
+        if let Some(Some(err)) = shader_reload::now_or_never(device.pop_error_scope()) {
+            return Err(err);
+        }
+        self.render_pipeline = render_pipeline;
+        Ok(())
+    }
+
+    /// recreates the render targets with `usage` on top of what they have, see
+    /// `REQUIRED_TARGET_USAGES` for which features need which
+    fn add_target_usage(&mut self, device: &wgpu::Device, usage: wgpu::TextureUsages) {
+        if self.targets.add_usage(device, usage) {
+            self.rebind_targets(device);
+        }
+    }
+
+    /// swaps in the bind group `PrimitiveManager::resize` returned, with the culling pass'
+    /// visible list resized to match (it has one slot per primitive)
+    fn set_primitives_bind_group(
+        &mut self,
+        device: &wgpu::Device,
+        bind_group: wgpu::BindGroup,
+        primitive_count: usize,
+    ) {
+        self.bind_groups.primitives_bind_group = bind_group;
+        self.bind_groups.visible_bind_group = self.culling.resize(device, primitive_count);
+    }
+
+    /// the passes reading the targets after they were recreated
+    fn rebind_targets(&mut self, device: &wgpu::Device) {
+        self.reflections.rebind(device, &self.targets);
+        self.motion_blur.rebind(device, &self.targets);
+        self.bloom.rebind(device, &self.targets);
+        self.blit_color_bind_group = self.blit.bind_group(device, &self.targets.color);
+        self.blit_post_bind_group = self.blit.bind_group(device, &self.targets.post);
+        self.blit_blurred_bind_group = self.blit.bind_group(device, &self.targets.blurred);
+    }
+
+    /// marches the level into the offscreen targets and runs the post passes,
+    /// `render` then only has to blit the result into egui's render pass
+    fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        frame: &FrameSettings,
+    ) {
+        if self.targets.resize(device, frame.size) {
+            self.rebind_targets(device);
+        }
+        self.blit.set_dither(queue, frame.dither);
+        self.culling.run(
+            queue,
+            encoder,
+            &self.bind_groups,
+            // the visible list is only valid for the main camera
+            frame.culling_enabled && frame.views == 1,
+            frame.primitive_count,
+        );
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Level Pass"),
+                color_attachments: &[
+                    Some(wgpu::RenderPassColorAttachment {
+                        view: &self.targets.color,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                            store: true,
+                        },
+                    }),
+                    Some(wgpu::RenderPassColorAttachment {
+                        view: &self.targets.gbuffer,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                            store: true,
+                        },
+                    }),
+                ],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.targets.depth,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: true,
+                    }),
+                    stencil_ops: None,
+                }),
+            });
+            render_pass.set_pipeline(&self.render_pipeline);
+            // render_pass.set_bind_group(0, &self.diffuse_bind_group, &[]);
+
+            render_pass.set_bind_group(PRIMITIVES_GROUP, &self.bind_groups.primitives_bind_group, &[]);
+            render_pass.set_bind_group(VISIBLE_GROUP, &self.bind_groups.visible_bind_group, &[]);
+            render_pass.set_bind_group(ENVIRONMENT_GROUP, &self.bind_groups.environment_bind_group, &[]);
+
+            // render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+            // render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16); // 1.
+            let cameras = std::iter::once(&self.bind_groups.camera_bind_group)
+                .chain(self.view_bind_groups.iter())
+                .take(frame.views);
+            let rects = frame.view_layout.rects(frame.views, self.targets.size());
+            Self::render_views(&mut render_pass, cameras, rects);
+        }
+        if let Some(settings) = &frame.reflections {
+            self.reflections
+                .run(queue, encoder, &self.bind_groups, &self.targets, settings);
+        }
+        if let Some(settings) = &frame.bloom {
+            // in place, so the motion blur and the blit don't need to know about it
+            self.bloom
+                .run(queue, encoder, &self.targets, settings, frame.reflections.is_some());
+        }
+        if let Some(settings) = &frame.motion_blur {
+            self.motion_blur.run(
+                queue,
+                encoder,
+                &self.bind_groups,
+                &self.targets,
+                settings,
+                frame.reflections.is_some(),
+            );
+        }
+        let overlay = render_targets::OverlayTarget {
+            view: self.output(frame).1,
+            depth: self.targets.overlay_depth(frame.overlay_depth_test),
+            rect: frame.view_layout.rects(frame.views, self.targets.size())[0],
+        };
+        if frame.frustums.iter().any(Option::is_some) {
+            self.frustums
+                .run(queue, encoder, &self.bind_groups, &overlay, &frame.frustums);
+        }
+        if let Some(gizmo) = &frame.gizmo {
+            self.gizmo.run(queue, encoder, &self.bind_groups, &overlay, gizmo);
+        }
+    }
+
+    /// draws the level once per camera into its rect of the targets, the caller binds
+    /// everything but the camera
+    fn render_views<'rp>(
+        render_pass: &mut wgpu::RenderPass<'rp>,
+        cameras: impl Iterator<Item = &'rp wgpu::BindGroup>,
+        rects: Vec<ViewRect>,
+    ) {
+        for (camera, rect) in cameras.zip(rects) {
+            let (x, y) = (rect.origin.0 as f32, rect.origin.1 as f32);
+            let (width, height) = (rect.size.0 as f32, rect.size.1 as f32);
+            render_pass.set_viewport(x, y, width, height, 0.0, 1.0);
+            render_pass.set_bind_group(CAMERA_GROUP, camera, &[]);
+            render_pass.draw(0..6, 0..1);
+        }
+    }
+
+    /// the target holding the finished image
+    fn output(&self, frame: &FrameSettings) -> (&wgpu::Texture, &wgpu::TextureView) {
+        self.targets
+            .output(frame.reflections.is_some(), frame.motion_blur.is_some())
+    }
+
+    fn render<'rp>(&'rp self, render_pass: &mut wgpu::RenderPass<'rp>, frame: &FrameSettings) {
+        let source = if frame.motion_blur.is_some() {
+            &self.blit_blurred_bind_group
+        } else if frame.reflections.is_some() {
+            &self.blit_post_bind_group
+        } else {
+            &self.blit_color_bind_group
+        };
+        self.blit.render(render_pass, source);
     }
 }
 
@@ -0,0 +1,189 @@
+use super::render_targets::{post_process_pipeline, RenderTargets};
+use super::wgpu::{self, util::DeviceExt, BindGroup, BindGroupLayout, Buffer, Device, Queue, TextureView};
+use super::{BindGroups, CAMERA_GROUP, PRIMITIVES_GROUP, VISIBLE_GROUP};
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct MotionBlurSettings {
+    /// how many color samples are averaged along the velocity
+    pub samples: u32,
+    /// scales the velocity, 1 blurs over exactly the distance moved since the last frame
+    pub intensity: f32,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    _pad: [f32; 2],
+}
+
+impl MotionBlurSettings {
+    pub fn new(samples: u32, intensity: f32) -> Self {
+        Self {
+            samples,
+            intensity,
+            _pad: [0.0; 2],
+        }
+    }
+}
+
+impl Default for MotionBlurSettings {
+    fn default() -> Self {
+        Self::new(8, 1.0)
+    }
+}
+
+/// velocity based camera motion blur, reprojects each pixel's world position with
+/// `CameraUniform::prev_world_to_screen` and blurs along the difference into `RenderTargets::blurred`
+///
+/// moving primitives don't contribute any velocity, only the camera does
+pub(crate) struct MotionBlurPass {
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: BindGroupLayout,
+    settings_buffer: Buffer,
+    /// reading `RenderTargets::color` and `RenderTargets::post`
+    from_color: BindGroup,
+    from_post: BindGroup,
+}
+
+impl MotionBlurPass {
+    pub fn new(
+        device: &Device,
+        primitives_bind_group_layout: &BindGroupLayout,
+        camera_bind_group_layout: &BindGroupLayout,
+        targets: &RenderTargets,
+    ) -> Self {
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                texture_entry(0),
+                texture_entry(1),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("motion_blur_bind_group_layout"),
+        });
+        let settings_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Motion Blur Settings Buffer"),
+            contents: bytemuck::bytes_of(&MotionBlurSettings::default()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Motion Blur Shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                concat!(
+                    include_str!("level/common.wgsl"),
+                    include_str!("level/fullscreen.wgsl"),
+                    include_str!("level/motion_blur_shader.wgsl")
+                )
+                .into(),
+            ),
+        });
+        let pipeline = post_process_pipeline(
+            device,
+            "Motion Blur Pipeline",
+            &shader,
+            &[
+                primitives_bind_group_layout,
+                camera_bind_group_layout,
+                &bind_group_layout,
+            ],
+        );
+        let from_color =
+            mk_bind_group(device, &bind_group_layout, &settings_buffer, &targets.color, targets);
+        let from_post =
+            mk_bind_group(device, &bind_group_layout, &settings_buffer, &targets.post, targets);
+        Self {
+            pipeline,
+            bind_group_layout,
+            settings_buffer,
+            from_color,
+            from_post,
+        }
+    }
+
+    /// the settings buffer, in bytes
+    pub fn memory_usage(&self) -> u64 {
+        self.settings_buffer.size()
+    }
+
+    /// has to be called whenever the targets were recreated
+    pub fn rebind(&mut self, device: &Device, targets: &RenderTargets) {
+        let layout = &self.bind_group_layout;
+        self.from_color = mk_bind_group(device, layout, &self.settings_buffer, &targets.color, targets);
+        self.from_post = mk_bind_group(device, layout, &self.settings_buffer, &targets.post, targets);
+    }
+
+    /// blurs `RenderTargets::post` if `after_post` (another pass ran before), `RenderTargets::color` otherwise
+    pub fn run(
+        &self,
+        queue: &Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        bind_groups: &BindGroups,
+        targets: &RenderTargets,
+        settings: &MotionBlurSettings,
+        after_post: bool,
+    ) {
+        crate::trace_upload!("motion blur settings", 0, std::mem::size_of::<MotionBlurSettings>());
+        queue.write_buffer(&self.settings_buffer, 0, bytemuck::bytes_of(settings));
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Motion Blur Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &targets.blurred,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(PRIMITIVES_GROUP, &bind_groups.primitives_bind_group, &[]);
+        pass.set_bind_group(CAMERA_GROUP, &bind_groups.camera_bind_group, &[]);
+        pass.set_bind_group(VISIBLE_GROUP, if after_post { &self.from_post } else { &self.from_color }, &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
+
+fn mk_bind_group(
+    device: &Device,
+    layout: &BindGroupLayout,
+    settings_buffer: &Buffer,
+    source: &TextureView,
+    targets: &RenderTargets,
+) -> BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(source),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(&targets.gbuffer),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: settings_buffer.as_entire_binding(),
+            },
+        ],
+        label: Some("motion_blur_bind_group"),
+    })
+}
@@ -0,0 +1,92 @@
+use std::fmt;
+
+use super::wgpu::{self, Buffer, Device, Queue};
+
+/// how many colors a palette can hold, the whole palette is a small uniform buffer
+pub const MAX_PALETTE_COLORS: usize = 256;
+/// `SDFPrimitive::palette_index` of a primitive using its own `rgba`
+pub const NO_PALETTE: u32 = u32::MAX;
+
+/// colors the primitives can reference by index instead of storing their own, swapping
+/// the palette recolors every primitive using it at once
+///
+/// bound next to the primitives (binding 5 of the primitives' group, `Palette` in common.wgsl)
+/// as all four bind groups are taken. empty by default, so every primitive uses its `rgba`
+pub struct PaletteManager {
+    colors: Vec<[f32; 4]>,
+    /// `count` (padded to 16 bytes) followed by `MAX_PALETTE_COLORS` colors
+    buffer: Buffer,
+}
+
+impl PaletteManager {
+    pub fn new(device: &Device) -> Self {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Palette Buffer"),
+            size: (16 + MAX_PALETTE_COLORS * 16) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            // zeroed, an empty palette
+            mapped_at_creation: false,
+        });
+        Self {
+            colors: Vec::new(),
+            buffer,
+        }
+    }
+
+    /// the buffer, in bytes, it always has room for `MAX_PALETTE_COLORS`
+    pub fn memory_usage(&self) -> u64 {
+        self.buffer.size()
+    }
+
+    pub fn colors(&self) -> &[[f32; 4]] {
+        &self.colors
+    }
+
+    /// replaces the whole palette, primitives with an index past its end fall back to their
+    /// `rgba`. only the color's rgb is used, the alpha stays the primitive's own
+    pub fn set_colors(&mut self, colors: &[[f32; 4]], queue: &Queue) -> Result<(), PaletteError> {
+        if colors.len() > MAX_PALETTE_COLORS {
+            return Err(PaletteError {
+                requested: colors.len(),
+            });
+        }
+        self.colors = colors.to_vec();
+        crate::trace_upload!("palette", 0, 16 + colors.len() * 16);
+        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[colors.len() as u32, 0, 0, 0]));
+        queue.write_buffer(&self.buffer, 16, bytemuck::cast_slice(colors));
+        Ok(())
+    }
+
+    /// changes a single color, panics if `index` is out of bounds
+    pub fn set_color(&mut self, index: usize, color: [f32; 4], queue: &Queue) {
+        self.colors[index] = color;
+        let offset = 16 + index * 16;
+        crate::trace_upload!("palette", offset, 16);
+        queue.write_buffer(&self.buffer, offset as wgpu::BufferAddress, bytemuck::bytes_of(&color));
+    }
+
+    pub(crate) fn bind_group_entry(&self) -> wgpu::BindGroupEntry<'_> {
+        wgpu::BindGroupEntry {
+            binding: 5,
+            resource: self.buffer.as_entire_binding(),
+        }
+    }
+}
+
+/// more colors than `MAX_PALETTE_COLORS`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PaletteError {
+    pub requested: usize,
+}
+
+impl fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} palette colors were requested but a palette only fits {}",
+            self.requested, MAX_PALETTE_COLORS
+        )
+    }
+}
+
+impl std::error::Error for PaletteError {}
@@ -1,9 +1,20 @@
-use std::{time::Duration};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    time::Duration,
+};
 
-use super::wgpu::{self, util::DeviceExt, BindGroup, BindGroupLayout, Buffer, Device};
+use super::capabilities::{Capabilities, CapacityError};
+#[cfg(feature = "expressions")]
+use super::expressions::PrimitiveExpressions;
+use super::grid::{SpatialGrid, INFINITE_REPETITION_PERIOD};
+use super::palette::{PaletteManager, NO_PALETTE};
+use super::textures::{TextureAtlas, NO_TEXTURE};
+use super::wgpu::{self, util::DeviceExt, BindGroup, BindGroupLayout, Buffer, Device, ShaderStages};
 
 #[repr(u32)]
 #[derive(Debug, Copy, Clone)] //, bytemuck::Pod, bytemuck::Zeroable)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub enum Typus {
     BoxFrame,
     Ellipsoid,
@@ -30,35 +41,644 @@ impl Default for Typus {
 
 // #[repr(C , align(16))]
 // the paddings allow alignment of 16bytes for my actual variables
+/// one primitive as the shaders read it, `Primitive` in common.wgsl
+///
+/// the layout (byte offsets, 240 bytes in total), checked at compile time below. a new field
+/// has to go where the wgsl struct puts it, a `vec3` starts at a multiple of 16 and the
+/// padding fields fill the gaps:
+///
+/// | offset | field | wgsl type |
+/// |---|---|---|
+/// | 0 | `position` | `vec3<f32>` |
+/// | 12 | `speed` | `f32` |
+/// | 16 | `rotation` | `vec4<f32>` |
+/// | 32 | `rotation_delta` | `vec4<f32>` |
+/// | 48 | `data` | `vec4<f32>` |
+/// | 64 | `instances` | `vec3<u32>` |
+/// | 76 | `instances_distance` | `f32` |
+/// | 80 | `rgba` | `vec4<f32>` |
+/// | 96 | `typus` | `u32` |
+/// | 100 | `twist` | `f32` |
+/// | 104 | `rounding` | `f32` |
+/// | 108 | `displacement` | `f32` |
+/// | 112 | `noise_scale` | `f32` |
+/// | 116 | `metalness` | `f32` |
+/// | 120 | `lod` | `u32` |
+/// | 124 | `bend` | `f32` |
+/// | 128 | `flags` | `u32` |
+/// | 132 | `operation` | `u32` |
+/// | 136 | `palette_index` | `u32` |
+/// | 140 | `phase` | `f32` |
+/// | 144 | `emissive` | `vec3<f32>` |
+/// | 156 | `shell_thickness` | `f32` |
+/// | 160 | `instance_extent` | `vec3<f32>` |
+/// | 172 | padding | |
+/// | 176 | `inverse_transform` | `array<vec4<f32>, 3>` |
+/// | 224 | `transform_stretch` | `f32` |
+/// | 228 | `forward_stretch` | `f32` |
+/// | 232 | `texture_index` | `u32` |
+/// | 236 | `texture_scale` | `f32` |
 #[repr(C)]
 #[derive(Debug, Copy, Clone, Default, bytemuck::Pod, bytemuck::Zeroable)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default = "SDFPrimitive::new"))]
 pub struct SDFPrimitive {
     pub position: [f32; 3],
     pub speed: f32,
     pub rotation: [f32; 4],
     pub rotation_delta: [f32; 4],
+    /// the typus' dimensions in its local (unrotated) space, packed per typus:
+    ///
+    /// | typus | `data[0..3]` | `data[3]` |
+    /// |---|---|---|
+    /// | `BoxFrame` | half extents along x, y and z | frame girth, at least the largest half extent gives a solid box |
+    /// | `Ellipsoid` | radii along x, y and z | unused |
+    /// | `Octahedron` | size, then unused | unused |
+    /// | `ChainLink` | length, arc radius and girth | unused |
+    ///
+    /// see `box_nonuniform` and `ellipsoid`
     pub data: [f32; 4],
     pub instances: [u32; 3],
     pub instances_distance: f32,
     pub rgba: [f32; 4],
     pub typus: Typus,
+    /// radians of rotation around the local y axis per unit of height, see `set_twist`
     pub twist: f32,
-    _pad4: [f32; 2],
-    // operation: u32,
+    /// subtracted from the raw distance, rounds off edges (turns a box's corners into fillets)
+    pub rounding: f32,
+    /// amplitude of the noise added to the distance, 0 keeps the surface smooth
+    pub displacement: f32,
+    /// frequency of that noise, see `set_displacement`
+    pub noise_scale: f32,
+    /// how mirror-like the surface is in `0..=1`, only used by the reflection pass
+    pub metalness: f32,
+    /// level of detail, 0 is full detail, written by `PrimitiveManager` while lod is enabled
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub lod: u32,
+    /// radians of bending around the local z axis per unit along x, see `set_bend`
+    pub bend: f32,
+    /// editor state the shader visualizes, e.g. `SDFPrimitive::HIGHLIGHTED`
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub flags: u32,
+    /// how the primitive combines with the others, e.g. `SDFPrimitive::COLOR_BLEND`
+    pub operation: u32,
+    /// the `PaletteManager` color used instead of `rgba`'s rgb, see `set_palette_index`
+    pub palette_index: u32,
+    /// seconds added to the animation clock of this primitive, see `set_phase`
+    pub phase: f32,
+    /// light the primitive emits on top of its shading, at byte offset 144 (a `vec3<f32>`
+    /// in wgsl), see `set_emissive`
+    pub emissive: [f32; 3],
+    /// wall thickness of a hollow primitive, 0 (the default) is solid, see `set_shell_thickness`
+    pub shell_thickness: f32,
+    /// `instance_extent`, written by `PrimitiveManager` on every upload for the shader's early-out
+    #[cfg_attr(feature = "serde", serde(skip))]
+    uploaded_extent: [f32; 3],
+    #[cfg_attr(feature = "serde", serde(skip))]
+    _pad7: u32,
+    /// the rows of the world to local transform while `uses_matrix`, see `from_matrix`
+    pub inverse_transform: [[f32; 4]; 3],
+    /// how much `inverse_transform` stretches space at most, the distance is divided by it.
+    /// 0 (the default) uses `position` and `rotation` instead
+    pub transform_stretch: f32,
+    /// how much the transform itself stretches space at most, scales the bounds
+    pub forward_stretch: f32,
+    /// the `TextureAtlas` layer projected onto the primitive, see `set_texture`
+    pub texture_index: u32,
+    /// how often the texture repeats per world unit, see `set_texture`
+    pub texture_scale: f32,
     // blend_strength: f32,
     // filler: [u32; 5], // 32 byte alignment
 }
 
 impl SDFPrimitive {
+    /// glows in the highlight color, see `PrimitiveManager::set_highlighted`
+    pub const HIGHLIGHTED: u32 = 1 << 0;
+    /// outlined, see `PrimitiveManager::select`
+    pub const SELECTED: u32 = 1 << 1;
+    /// `operation` bit, see `set_color_blend`
+    pub const COLOR_BLEND: u32 = 1 << 0;
+
     pub fn new() -> Self {
         Self {
             position: [0.0, 0.0, -10000.0],
             rotation: [0.0, 0.0, 0.0, 1.0],
             rotation_delta: [0.0, 0.0, 0.0, 1.0],
+            rgba: [0.0, 0.0, 0.0, 1.0],
+            palette_index: NO_PALETTE,
+            texture_index: NO_TEXTURE,
+            texture_scale: 0.01,
             // typus: Typus::Sphere,
             ..Default::default()
         }
     }
+
+    /// a solid box with the half extents `hx`, `hy` and `hz`, a `BoxFrame` whose girth
+    /// fills it completely
+    pub fn box_nonuniform(hx: f32, hy: f32, hz: f32) -> Self {
+        Self {
+            typus: Typus::BoxFrame,
+            data: [hx, hy, hz, hx.max(hy).max(hz)],
+            ..Self::new()
+        }
+    }
+
+    /// an ellipsoid with the radii `rx`, `ry` and `rz`
+    ///
+    /// its sdf is only an approximation (`k0 * (k0 - 1) / k1`, see `distance_to_ellipsoid` in
+    /// main_shader.wgsl and distance.rs). it's exact for equal radii and on the surface, elsewhere
+    /// the distance is an estimate that gets worse the more the radii differ, so the marcher takes
+    /// a few more steps and cpu queries (`closest_primitive`, the collision check) are only as
+    /// precise near the surface
+    pub fn ellipsoid(rx: f32, ry: f32, rz: f32) -> Self {
+        Self {
+            typus: Typus::Ellipsoid,
+            data: [rx, ry, rz, 0.0],
+            ..Self::new()
+        }
+    }
+
+    /// a primitive placed by an affine `transform` (e.g. from a scene graph), which unlike
+    /// `position` and `rotation` can also scale and shear it. `None` if it isn't an
+    /// invertible affine transform
+    ///
+    /// its inverse is stored in `inverse_transform` and takes the sample point `p` into the
+    /// primitive's space before the base sdf: the shader applies its linear part to the offset
+    /// from `position` (set to the transform's translation, so the infinite repetition keeps
+    /// working), which is the same as transforming `p` by the whole inverse. `rotation` is
+    /// ignored. scaling and shearing bend the distance, so it's divided by the most the
+    /// inverse stretches space (`transform_stretch`), which keeps it a bound. `rounding` and
+    /// `shell_thickness` are in the primitive's space
+    pub fn from_matrix(transform: cgmath::Matrix4<f32>) -> Option<Self> {
+        if cgmath::Matrix::row(&transform, 3) != cgmath::Vector4::unit_w() {
+            return None;
+        }
+        let inverse = cgmath::SquareMatrix::invert(&transform)?;
+        let row = |i: usize| [inverse.x[i], inverse.y[i], inverse.z[i], inverse.w[i]];
+        let linear = |m: cgmath::Matrix4<f32>| {
+            cgmath::Matrix3::from_cols(m.x.truncate(), m.y.truncate(), m.z.truncate())
+        };
+        Some(Self {
+            position: transform.w.truncate().into(),
+            inverse_transform: [row(0), row(1), row(2)],
+            transform_stretch: largest_stretch(linear(inverse)),
+            forward_stretch: largest_stretch(linear(transform)),
+            ..Self::new()
+        })
+    }
+
+    /// whether it's placed by `inverse_transform` instead of `position` and `rotation`
+    pub fn uses_matrix(&self) -> bool {
+        self.transform_stretch > 0.0
+    }
+
+    /// the linear part of `inverse_transform`
+    pub(crate) fn inverse_linear(&self) -> cgmath::Matrix3<f32> {
+        let [r0, r1, r2] = self.inverse_transform;
+        cgmath::Matrix3::new(r0[0], r1[0], r2[0], r0[1], r1[1], r2[1], r0[2], r1[2], r2[2])
+    }
+
+    /// whether the primitive is out of play: `new` parks it far below the scene and the
+    /// level lets the ones that left it wait there to respawn, see
+    /// `PrimitiveManager::get_spawnable_primitive`
+    pub fn is_parked(&self) -> bool {
+        self.position[2] < -1000.0 || self.position[2] > 3000.0
+    }
+
+    /// only composited as glass while `PrimitiveManager::set_transparency` is on
+    pub fn is_transparent(&self) -> bool {
+        self.rgba[3] < 1.0
+    }
+
+    /// takes the color from the palette (keeping `rgba`'s alpha), `None` uses `rgba`.
+    /// an index past the palette's end falls back to `rgba` too
+    pub fn set_palette_index(&mut self, index: Option<u32>) {
+        self.palette_index = index.unwrap_or(NO_PALETTE);
+    }
+
+    /// projects a `TextureAtlas` layer onto the primitive along its local axes, tinted by its
+    /// color, `None` uses the plain color. `scale` is how often the texture repeats per unit
+    /// (the default of 0.01 is once every 100 units)
+    pub fn set_texture(&mut self, index: Option<u32>, scale: f32) {
+        self.texture_index = index.unwrap_or(NO_TEXTURE);
+        self.texture_scale = scale;
+    }
+
+    /// also grows the primitive by `rounding` in every direction
+    pub fn set_rounding(&mut self, rounding: f32) {
+        self.rounding = rounding;
+    }
+
+    /// where the primitive smoothly blends into the ones before it, its surface properties
+    /// (`metalness`, `emissive`) are mixed with theirs instead of switching at the seam. the
+    /// weight is the smooth min's `h = clamp(0.5 + 0.5 * (d_new - d_before) / k, 0, 1)` of the
+    /// ones before, `mix(new, before, h)` (see `calc_step` in main_shader.wgsl). while the
+    /// shader doesn't smooth the union, `h` is 0 or 1 and nothing changes
+    pub fn set_color_blend(&mut self, color_blend: bool) {
+        if color_blend {
+            self.operation |= Self::COLOR_BLEND;
+        } else {
+            self.operation &= !Self::COLOR_BLEND;
+        }
+    }
+
+    /// hollows the primitive out into a shell around its surface, the distance `d` becomes
+    /// `abs(d) - shell_thickness` (after the rounding, see `distance_to_primitive` in
+    /// main_shader.wgsl and distance.rs), so the wall reaches `shell_thickness` to both sides
+    /// of the original surface. 0 keeps it solid
+    pub fn set_shell_thickness(&mut self, shell_thickness: f32) {
+        self.shell_thickness = shell_thickness;
+    }
+
+    /// adds `displacement * value_noise(q * noise_scale)` to the distance, where `q` is the
+    /// primitive-local sample point and `value_noise` is trilinearly interpolated hash noise
+    /// in `[-1, 1]` (see `value_noise` in main_shader.wgsl and distance.rs)
+    ///
+    /// this breaks the distance bound, keep `displacement * noise_scale` well below 1
+    /// or the marcher starts overshooting
+    pub fn set_displacement(&mut self, displacement: f32, noise_scale: f32) {
+        self.displacement = displacement;
+        self.noise_scale = noise_scale;
+    }
+
+    /// twists the primitive around its local y axis, before the base sdf is evaluated each
+    /// (instanced) sample point `q` is rotated in the xz plane by `twist * q.y` radians:
+    ///
+    /// `q = (cos(a) * q.x - sin(a) * q.z, q.y, sin(a) * q.x + cos(a) * q.z)` with `a = twist * q.y`
+    ///
+    /// 0 leaves it undeformed, see `deform` in main_shader.wgsl and distance.rs
+    pub fn set_twist(&mut self, twist: f32) {
+        self.twist = twist;
+    }
+
+    /// bends the primitive around its local z axis, the sample point is rotated in the xy plane
+    /// by `bend * q.x` radians (after the twist):
+    ///
+    /// `q = (cos(a) * q.x - sin(a) * q.y, sin(a) * q.x + cos(a) * q.y, q.z)` with `a = bend * q.x`
+    ///
+    /// both stretch space, so the distance is divided by `1 + (|twist| + |bend|) * r`
+    /// (`r` being the sum of `data`) to keep the marcher from overshooting
+    pub fn set_bend(&mut self, bend: f32) {
+        self.bend = bend;
+    }
+
+    /// added to the color where the primitive is hit, unaffected by the effects and lighting,
+    /// black (the default) emits nothing. values above 1 are fine for a bloom pass to pick up
+    pub fn set_emissive(&mut self, r: f32, g: f32, b: f32) {
+        self.emissive = [r, g, b];
+    }
+
+    /// repeats the primitive `counts` times to each side along its local x, y and z,
+    /// `distance` apart (see `instance_count`). `[0; 3]` turns the repetition off
+    pub fn set_instances(&mut self, counts: [u32; 3], distance: f32) {
+        self.instances = counts;
+        self.instances_distance = distance;
+    }
+
+    /// how many copies the instance grid holds, `2 * count + 1` per axis.
+    ///
+    /// the shader evaluates only the nearest copy, but the grid's bounds grow with it: a large
+    /// grid covers so much of the view that culling, the spatial grid and the marcher's
+    /// bounding box early-out (see `instance_extent`) never skip it, and rays graze copies all
+    /// the way to the horizon, taking many short steps. past a few thousand copies the frame
+    /// time climbs steeply, `PrimitiveManager::set_max_instances` guards against that
+    pub fn instance_count(&self) -> u64 {
+        self.instances.iter().map(|&count| 2 * count as u64 + 1).product()
+    }
+
+    /// shifts the primitive's animation by `phase` seconds, so identical primitives don't
+    /// move in unison. 0 (the default) runs on the shared clock, see `local_time`
+    pub fn set_phase(&mut self, phase: f32) {
+        self.phase = phase;
+    }
+
+    /// the animation clock of this primitive at the level's `time`, in seconds
+    pub fn local_time(&self, time: Duration) -> f32 {
+        time.as_secs_f32() + self.phase
+    }
+
+    /// checks that every value is finite and the typus' dimensions are usable,
+    /// nan or non-positive radii make the marcher produce garbage or never converge
+    pub fn validate(&self) -> Result<(), PrimitiveError> {
+        let floats: [(&'static str, &[f32]); 20] = [
+            ("position", &self.position),
+            ("speed", &[self.speed]),
+            ("rotation", &self.rotation),
+            ("rotation_delta", &self.rotation_delta),
+            ("data", &self.data),
+            ("instances_distance", &[self.instances_distance]),
+            ("rgba", &self.rgba),
+            ("twist", &[self.twist]),
+            ("rounding", &[self.rounding]),
+            ("displacement", &[self.displacement]),
+            ("noise_scale", &[self.noise_scale]),
+            ("metalness", &[self.metalness]),
+            ("bend", &[self.bend]),
+            ("phase", &[self.phase]),
+            ("emissive", &self.emissive),
+            ("shell_thickness", &[self.shell_thickness]),
+            ("inverse_transform", bytemuck::cast_slice(&self.inverse_transform)),
+            ("transform_stretch", &[self.transform_stretch]),
+            ("forward_stretch", &[self.forward_stretch]),
+            ("texture_scale", &[self.texture_scale]),
+        ];
+        for (field, values) in floats {
+            if !values.iter().all(|value| value.is_finite()) {
+                return Err(PrimitiveError::NonFinite { field });
+            }
+        }
+        let check = |field, values: &[f32], min_exclusive: bool| {
+            match values.iter().find(|&&value| value < 0.0 || (min_exclusive && value == 0.0)) {
+                None => Ok(()),
+                Some(_) if min_exclusive => Err(PrimitiveError::NonPositive { field }),
+                Some(_) => Err(PrimitiveError::Negative { field }),
+            }
+        };
+        let [x, y, z, w] = self.data;
+        match self.typus {
+            // half extents and the frame's girth, 0 collapses it to a point
+            Typus::BoxFrame => check("data", &[x, y, z, w], false)?,
+            // radii, the distance divides by them
+            Typus::Ellipsoid => check("data", &[x, y, z], true)?,
+            Typus::Octahedron => check("data", &[x], false)?,
+            // length, arc radius and girth
+            Typus::ChainLink => check("data", &[x, y, z], false)?,
+        }
+        check("emissive", &self.emissive, false)?;
+        check("shell_thickness", &[self.shell_thickness], false)?;
+        if self.instances != [0; 3] {
+            check("instances_distance", &[self.instances_distance], true)?;
+        }
+        for (field, rotation) in [("rotation", self.rotation), ("rotation_delta", self.rotation_delta)] {
+            if rotation.iter().all(|&value| value == 0.0) {
+                return Err(PrimitiveError::NonPositive { field });
+            }
+        }
+        Ok(())
+    }
+
+    /// conservative radius around `position` containing the whole primitive (and its instances),
+    /// none of the typuses reaches further than the sum of its data
+    /// (mirrors `bounding_radius` in culling_shader.wgsl)
+    pub fn bounding_radius(&self) -> f32 {
+        let base: f32 = self.data.iter().map(|d| d.abs()).sum::<f32>()
+            + self.rounding.max(0.0)
+            + self.shell_thickness.max(0.0)
+            + self.displacement.abs();
+        let grid = cgmath::Vector3::new(
+            self.instances[0] as f32,
+            self.instances[1] as f32,
+            self.instances[2] as f32,
+        );
+        let radius = base + self.instances_distance * cgmath::InnerSpace::magnitude(grid);
+        if self.uses_matrix() {
+            radius * self.forward_stretch
+        } else {
+            radius
+        }
+    }
+
+    /// half the size of the box (in the primitive's rotated or transformed space, centered on `position`)
+    /// holding the whole instance grid, the instance spacing times the count per axis plus
+    /// the reach of a single instance (see `bounding_radius`)
+    pub fn instance_extent(&self) -> cgmath::Vector3<f32> {
+        let base: f32 = self.data.iter().map(|d| d.abs()).sum::<f32>()
+            + self.rounding.max(0.0)
+            + self.shell_thickness.max(0.0)
+            + self.displacement.abs();
+        let grid = self.instances.map(|count| count as f32 * self.instances_distance.abs());
+        cgmath::Vector3::from(grid) + cgmath::Vector3::new(base, base, base)
+    }
+
+    /// the world space box (min and max corner) holding the whole instance grid, ignoring the
+    /// infinite repetition every `INFINITE_REPETITION_PERIOD` along x and y
+    pub fn instance_bounds(&self) -> (cgmath::Point3<f32>, cgmath::Point3<f32>) {
+        let rotation = cgmath::InnerSpace::normalize(cgmath::Quaternion::from(self.rotation));
+        let extent = self.instance_extent();
+        let axes = match cgmath::SquareMatrix::invert(&self.inverse_linear()) {
+            Some(transform) if self.uses_matrix() => [transform.x, transform.y, transform.z],
+            _ => [
+                rotation * cgmath::Vector3::unit_x(),
+                rotation * cgmath::Vector3::unit_y(),
+                rotation * cgmath::Vector3::unit_z(),
+            ],
+        };
+        // the rotated box reaches as far along each world axis as its rotated half axes together
+        let half_axes = [axes[0] * extent.x, axes[1] * extent.y, axes[2] * extent.z];
+        let reach = half_axes
+            .iter()
+            .fold(cgmath::Vector3::new(0.0, 0.0, 0.0), |reach, axis| {
+                reach + axis.map(f32::abs)
+            });
+        let center = cgmath::Point3::from(self.position);
+        (center - reach, center + reach)
+    }
+
+    /// copies `instance_extent` to where the shader reads it
+    fn refresh_extent(&mut self) {
+        self.uploaded_extent = self.instance_extent().into();
+    }
+}
+
+/// the most `m` lengthens a vector by (its spectral norm), by power iteration on `mᵀ m` from
+/// each axis, at least one of which isn't orthogonal to the direction it stretches most
+fn largest_stretch(m: cgmath::Matrix3<f32>) -> f32 {
+    use cgmath::{InnerSpace, Matrix};
+    let mtm = m.transpose() * m;
+    [cgmath::Vector3::unit_x(), cgmath::Vector3::unit_y(), cgmath::Vector3::unit_z()]
+        .into_iter()
+        .map(|mut direction| {
+            for _ in 0..16 {
+                let next = mtm * direction;
+                if next.magnitude2() == 0.0 {
+                    return 0.0;
+                }
+                direction = next.normalize();
+            }
+            (m * direction).magnitude()
+        })
+        .fold(0.0, f32::max)
+}
+
+/// why `SDFPrimitive::validate` rejected a primitive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrimitiveError {
+    /// nan or infinite
+    NonFinite { field: &'static str },
+    /// a size that can't be negative is
+    Negative { field: &'static str },
+    /// a size (or rotation) that has to be greater than zero isn't
+    NonPositive { field: &'static str },
+    /// the instance grid is larger than `PrimitiveManager::set_max_instances` allows
+    TooManyInstances { count: u64, max: u64 },
+}
+
+impl fmt::Display for PrimitiveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PrimitiveError::NonFinite { field } => write!(f, "`{field}` isn't finite"),
+            PrimitiveError::Negative { field } => write!(f, "`{field}` can't be negative"),
+            PrimitiveError::NonPositive { field } => write!(f, "`{field}` has to be positive"),
+            PrimitiveError::TooManyInstances { count, max } => {
+                write!(f, "{count} instances are more than the maximum of {max}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PrimitiveError {}
+
+// has to match the `Primitive` struct in common.wgsl, storage arrays are strided in 16 byte steps
+const _: () = assert!(std::mem::size_of::<SDFPrimitive>() == 240);
+const _: () = assert!(std::mem::size_of::<SDFPrimitive>() % 16 == 0);
+
+/// the byte offset of a field of `SDFPrimitive` in a const context, `std::mem::offset_of`
+/// needs a newer rust than the crate supports
+macro_rules! primitive_offset {
+    ($field:ident) => {{
+        let primitive = std::mem::MaybeUninit::<SDFPrimitive>::uninit();
+        let base = primitive.as_ptr();
+        // SAFETY: only the field's address is taken, the uninitialized memory is never read
+        unsafe { (std::ptr::addr_of!((*base).$field) as *const u8).offset_from(base as *const u8) as usize }
+    }};
+}
+
+// the offsets of the table on `SDFPrimitive`, which the shaders expect
+const _: () = {
+    assert!(primitive_offset!(position) == 0);
+    assert!(primitive_offset!(speed) == 12);
+    assert!(primitive_offset!(rotation) == 16);
+    assert!(primitive_offset!(rotation_delta) == 32);
+    assert!(primitive_offset!(data) == 48);
+    assert!(primitive_offset!(instances) == 64);
+    assert!(primitive_offset!(instances_distance) == 76);
+    assert!(primitive_offset!(rgba) == 80);
+    assert!(primitive_offset!(typus) == 96);
+    assert!(primitive_offset!(twist) == 100);
+    assert!(primitive_offset!(rounding) == 104);
+    assert!(primitive_offset!(displacement) == 108);
+    assert!(primitive_offset!(noise_scale) == 112);
+    assert!(primitive_offset!(metalness) == 116);
+    assert!(primitive_offset!(lod) == 120);
+    assert!(primitive_offset!(bend) == 124);
+    assert!(primitive_offset!(flags) == 128);
+    assert!(primitive_offset!(operation) == 132);
+    assert!(primitive_offset!(palette_index) == 136);
+    assert!(primitive_offset!(phase) == 140);
+    assert!(primitive_offset!(emissive) == 144);
+    assert!(primitive_offset!(shell_thickness) == 156);
+    assert!(primitive_offset!(uploaded_extent) == 160);
+    assert!(primitive_offset!(inverse_transform) == 176);
+    assert!(primitive_offset!(transform_stretch) == 224);
+    assert!(primitive_offset!(forward_stretch) == 228);
+    assert!(primitive_offset!(texture_index) == 232);
+    assert!(primitive_offset!(texture_scale) == 236);
+    // vec3s have to start at a multiple of 16 in wgsl
+    assert!(primitive_offset!(position) % 16 == 0);
+    assert!(primitive_offset!(instances) % 16 == 0);
+    assert!(primitive_offset!(emissive) % 16 == 0);
+    assert!(primitive_offset!(uploaded_extent) % 16 == 0);
+    assert!(primitive_offset!(inverse_transform) % 16 == 0);
+};
+
+/// a world axis, e.g. to nudge along
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+impl Axis {
+    pub fn unit(self) -> cgmath::Vector3<f32> {
+        match self {
+            Axis::X => cgmath::Vector3::unit_x(),
+            Axis::Y => cgmath::Vector3::unit_y(),
+            Axis::Z => cgmath::Vector3::unit_z(),
+        }
+    }
+}
+
+/// the fields of `SDFPrimitive` that are read from the tightly packed channels buffer
+/// instead of the primitives buffer, see `PrimitiveManager::set_dynamic_channels`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct DynamicChannels {
+    pub position: bool,
+    /// `rgba`
+    pub color: bool,
+}
+
+impl DynamicChannels {
+    /// `PrimitiveOrder::dynamic_channels` in common.wgsl
+    fn bits(self) -> u32 {
+        self.position as u32 | (self.color as u32) << 1
+    }
+
+    fn contains(self, other: Self) -> bool {
+        (self.position || !other.position) && (self.color || !other.color)
+    }
+
+    fn intersection(self, other: Self) -> Self {
+        Self {
+            position: self.position && other.position,
+            color: self.color && other.color,
+        }
+    }
+}
+
+/// how much of the movement a primitive gets from `PrimitiveManager::proportional_edit`
+/// the further it is from the moved one, the curves of blender's proportional editing
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum Falloff {
+    /// eases in and out, `3d² - 2d³` of the closeness `d`
+    #[default]
+    Smooth,
+    /// the closeness itself
+    Linear,
+    /// a hemisphere, keeps most of the movement until close to the radius
+    Sphere,
+}
+
+impl Falloff {
+    /// the share of the movement at `distance` over the radius, 1 at 0 and 0 from 1 on
+    pub fn weight(self, distance: f32) -> f32 {
+        let closeness = (1.0 - distance).clamp(0.0, 1.0);
+        match self {
+            Falloff::Smooth => closeness * closeness * (3.0 - 2.0 * closeness),
+            Falloff::Linear => closeness,
+            Falloff::Sphere => (closeness * (2.0 - closeness)).sqrt(),
+        }
+    }
+}
+
+/// distance thresholds for the primitives' level of detail, see `PrimitiveManager::set_lod`
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct LodSettings {
+    /// ascending, a primitive further from the camera than `n` of them gets lod `n`
+    pub distances: Vec<f32>,
+    /// lods are only recomputed once the camera moved this far since the last time..
+    pub min_camera_move: f32,
+    /// ..or this much time passed, as the primitives move on their own
+    pub max_age: Duration,
+}
+
+impl LodSettings {
+    pub fn lod_at(&self, distance: f32) -> u32 {
+        self.distances.iter().filter(|&&threshold| distance > threshold).count() as u32
+    }
+}
+
+impl Default for LodSettings {
+    fn default() -> Self {
+        Self {
+            distances: vec![250.0, 500.0],
+            min_camera_move: 10.0,
+            max_age: Duration::from_millis(250),
+        }
+    }
 }
 
 // #[repr(C)]
@@ -67,89 +687,1290 @@ impl SDFPrimitive {
 //     primitives: [SDFPrimitive; 10],
 // }
 
+/// owns the primitives and their gpu buffer
+///
+/// the order contract: the buffer holds the primitives in the order of `primitives` and the
+/// shader folds them in ascending index, each combining with the result of all the ones before
+/// (e.g. `SDFPrimitive::COLOR_BLEND`), so the same vec always renders the same. `move_to` is
+/// how to reorder them. two exceptions: with `set_transparency` the transparent primitives
+/// are moved behind the opaque ones (which keep their order), and the culling pass compacts
+/// the visible primitives in whatever order its threads finish
 pub struct PrimitiveManager {
     pub primitives: Vec<SDFPrimitive>,
     pub buffer: Buffer,
+    pub grid: SpatialGrid,
+    /// colors the primitives can share, see `SDFPrimitive::set_palette_index`
+    palette: PaletteManager,
+    /// images the primitives can share, see `SDFPrimitive::set_texture`
+    textures: TextureAtlas,
+    /// where the transparent primitives start in `buffer` and which channels are dynamic,
+    /// see `set_transparency` and `set_dynamic_channels`
+    order_buffer: Buffer,
+    /// the dynamic channels, one `vec4` per slot each, see `set_dynamic_channels`
+    channels_buffer: Buffer,
+    dynamic: DynamicChannels,
+    transparency: bool,
+    view_position: cgmath::Point3<f32>,
+    lod: Option<LodSettings>,
+    /// see `set_complexity_budget`
+    complexity_budget: Option<u32>,
+    /// where the camera was and how long ago the lods were last computed
+    lod_view_position: cgmath::Point3<f32>,
+    lod_age: Duration,
+    /// cpu-side names for editor code and scene files, see `name_primitive`
+    names: HashMap<String, usize>,
+    /// whether `update_primitives` and `set_primitive` check the primitives, see `set_validation`
+    validate: bool,
+    /// see `set_max_instances`
+    max_instances: u64,
+    /// the buffer slot of every primitive, they only differ while transparency is on
+    slots: Vec<usize>,
+    highlighted: Option<usize>,
+    selection: HashSet<usize>,
+    /// what `update` changed since the buffer was last written
+    pending: PendingChange,
+    /// how far each primitive's slot is behind it, see `debug_state`
+    stale: Vec<Staleness>,
+    /// the camera position the buffer was last ordered by
+    uploaded_view_position: cgmath::Point3<f32>,
+    /// what new slots are filled with, see `set_template`
+    template: SDFPrimitive,
+    /// animate the primitives' fields, see `expressions_mut`
+    #[cfg(feature = "expressions")]
+    expressions: PrimitiveExpressions,
     // pub bind_group: BindGroup,
     pub bind_group_layout: BindGroupLayout,
     // total_time: Duration,
 }
 
+/// how much (in world units or radians) the primitives have to move or turn before
+/// `PrimitiveManager::update` writes the buffer again
+pub const UPLOAD_EPSILON: f32 = 1e-4;
+
+/// what `PrimitiveManager::set_max_instances` starts at, a grid of about 100 copies per axis
+pub const DEFAULT_MAX_INSTANCES: u64 = 1 << 20;
+
+/// what `PrimitiveManager::update` changed since the buffer was last written
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+struct PendingChange {
+    /// the largest movement or turn (in world units or radians) of every frame, summed up
+    total: f32,
+    /// the part of `total` the dynamic channels can't take, turns and expressions
+    static_part: f32,
+}
+
+/// how far a primitive's slot is behind it, ordered by what it takes to catch up
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Staleness {
+    Uploaded,
+    /// the channels upload catches up, see `Upload::Channels`
+    Moved,
+    Changed,
+}
+
+/// what `PrimitiveManager::update` writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Upload {
+    Nothing,
+    /// the positions only, see `PrimitiveManager::set_dynamic_channels`
+    Channels,
+    Everything,
+}
+
+impl PendingChange {
+    /// a frame's largest change and the static part of it
+    fn add(&mut self, change: f32, static_change: f32) {
+        self.total += change;
+        self.static_part += static_change;
+    }
+
+    /// nothing until the changes add up to `UPLOAD_EPSILON`, unless the lods changed or the
+    /// camera moved with transparency on (`view_moved`). the positions alone if the rest is
+    /// below the epsilon and `channels_only` (see `PrimitiveManager::writes_channels_only`)
+    fn upload(&self, lods_changed: bool, view_moved: bool, channels_only: bool) -> Upload {
+        if self.total < UPLOAD_EPSILON && !lods_changed && !view_moved {
+            Upload::Nothing
+        } else if self.static_part < UPLOAD_EPSILON && !lods_changed && channels_only {
+            Upload::Channels
+        } else {
+            Upload::Everything
+        }
+    }
+}
+
+/// moves and turns `primitives` by one frame of `dt`, marking them in `stale`. returns the
+/// frame's largest change and the static part of it (see `PendingChange::add`), `None` for
+/// a zero `dt`, which leaves them as they are
+fn advance(
+    primitives: &mut [SDFPrimitive],
+    stale: &mut [Staleness],
+    dt: Duration,
+) -> Option<(f32, f32)> {
+    if dt.is_zero() {
+        return None;
+    }
+    let mut change: f32 = 0.0;
+    let mut static_change: f32 = 0.0;
+    for (primitive, stale) in primitives.iter_mut().zip(stale) {
+        let moved = primitive.speed * dt.as_secs_f32();
+        primitive.position[2] -= moved;
+        // let (v0, v1) = (Simd::from(primitive.rotation), Simd::from(primitive.rotation_delta));
+        // primitive.rotation = (v0 * v1).into();
+        let rotation_delta = cgmath::Quaternion::from(primitive.rotation_delta);
+        primitive.rotation = (cgmath::Quaternion::from(primitive.rotation) * rotation_delta).into();
+        let turned = rotation_angle(rotation_delta);
+        change = change.max(moved.abs()).max(turned);
+        static_change = static_change.max(turned);
+        if turned != 0.0 {
+            *stale = Staleness::Changed;
+        } else if moved != 0.0 {
+            *stale = (*stale).max(Staleness::Moved);
+        }
+    }
+    Some((change, static_change))
+}
+
+/// the angle (in radians) `rotation` turns by
+fn rotation_angle(rotation: cgmath::Quaternion<f32>) -> f32 {
+    let rotation = cgmath::InnerSpace::normalize(rotation);
+    2.0 * rotation.s.abs().min(1.0).acos()
+}
+
+/// the stages the primitives buffer is visible to unless requested otherwise
+pub const DEFAULT_PRIMITIVE_VISIBILITY: ShaderStages = ShaderStages::VERTEX_FRAGMENT;
+
 impl PrimitiveManager {
-    pub fn new(device: &Device, primitive_count: u8) -> (Self, BindGroup) {
-        let (bind_group, bind_group_layout, buffer) =
-            mk_primitive_bind_group(device, primitive_count);
+    /// fails if `primitive_count` exceeds what the device's storage buffers can hold,
+    /// see `Capabilities::max_primitive_count`
+    pub fn new(device: &Device, primitive_count: usize) -> Result<(Self, BindGroup), CapacityError> {
+        Self::with_visibility(device, primitive_count, DEFAULT_PRIMITIVE_VISIBILITY)
+    }
+
+    /// like `new`, but lets you pick which shader stages can read the primitives,
+    /// e.g. `VERTEX_FRAGMENT | COMPUTE` for a compute pre-pass
+    pub fn with_visibility(
+        device: &Device,
+        primitive_count: usize,
+        visibility: ShaderStages,
+    ) -> Result<(Self, BindGroup), CapacityError> {
+        Self::with_template(device, primitive_count, visibility, SDFPrimitive::new())
+    }
 
-        let primitives = vec![SDFPrimitive::new(); primitive_count as usize];
+    /// like `with_visibility`, but fills the slots with `template` instead of `SDFPrimitive::new()`,
+    /// and so do later `resize`s (see `set_template`)
+    pub fn with_template(
+        device: &Device,
+        primitive_count: usize,
+        visibility: ShaderStages,
+        template: SDFPrimitive,
+    ) -> Result<(Self, BindGroup), CapacityError> {
+        Capabilities::from_device(device).check_primitive_count(primitive_count)?;
+        let grid = SpatialGrid::new(device);
+        let palette = PaletteManager::new(device);
+        let textures = TextureAtlas::new(device);
+        let primitives = vec![template; primitive_count];
+        let order_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Primitive Order Buffer"),
+            // everything opaque and no complexity budget
+            contents: bytemuck::cast_slice(&[primitive_count as u32, u32::MAX, 0, 0]),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+        let bind_group_layout = mk_primitive_bind_group_layout(device, visibility);
+        let (bind_group, buffer, channels_buffer) =
+            mk_primitive_bind_group(device, &bind_group_layout, &primitives, &grid, &palette, &textures, &order_buffer);
 
-        (Self {
+        Ok((Self {
             primitives: primitives,
             buffer,
+            grid,
+            palette,
+            textures,
+            order_buffer,
+            channels_buffer,
+            dynamic: DynamicChannels::default(),
+            transparency: false,
+            view_position: cgmath::Point3::new(0.0, 0.0, 0.0),
+            lod: None,
+            complexity_budget: None,
+            lod_view_position: cgmath::Point3::new(0.0, 0.0, 0.0),
+            lod_age: Duration::ZERO,
+            names: HashMap::new(),
+            validate: cfg!(debug_assertions),
+            max_instances: DEFAULT_MAX_INSTANCES,
+            slots: (0..primitive_count).collect(),
+            highlighted: None,
+            selection: HashSet::new(),
+            pending: PendingChange::default(),
+            stale: vec![Staleness::Uploaded; primitive_count],
+            uploaded_view_position: cgmath::Point3::new(0.0, 0.0, 0.0),
+            template,
+            #[cfg(feature = "expressions")]
+            expressions: PrimitiveExpressions::new(),
             // bind_group,
             bind_group_layout,
             // total_time: Duration::from_secs(0),
-        }, bind_group)
+        }, bind_group))
+    }
+    pub fn len(&self) -> usize {
+        self.primitives.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.primitives.is_empty()
+    }
+
+    /// how many primitives there are, how many the buffer holds and how many differ from
+    /// their uploaded copy (moved or turned by `update` below `UPLOAD_EPSILON`), for a debug
+    /// readout
+    pub fn debug_state(&self) -> (usize, usize, usize) {
+        let capacity = (self.buffer.size() / std::mem::size_of::<SDFPrimitive>() as u64) as usize;
+        let dirty_count = self.stale.iter().filter(|&&stale| stale != Staleness::Uploaded).count();
+        (self.primitives.len(), capacity, dirty_count)
+    }
+
+    /// changes how many primitives there are in one go, e.g. when loading a scene of a different size
+    ///
+    /// the first `min(old, new)` primitives are kept, new slots are copies of the `template`.
+    /// the buffer is recreated, so the returned bind group has to replace the old one
+    /// (the layout stays the same, pipelines don't need to be rebuilt).
+    /// names (and expressions) of primitives that were dropped are forgotten.
+    /// the culling pass' visible list has to follow, see `CullingPass::resize`
+    pub fn resize(
+        &mut self,
+        new_count: usize,
+        device: &Device,
+        queue: &wgpu::Queue,
+    ) -> Result<BindGroup, CapacityError> {
+        Capabilities::from_device(device).check_primitive_count(new_count)?;
+        self.primitives.resize(new_count, self.template);
+        // names of dropped primitives would point past the end
+        self.names.retain(|_, index| *index < new_count);
+        self.highlighted = self.highlighted.filter(|&index| index < new_count);
+        self.selection.retain(|&index| index < new_count);
+        #[cfg(feature = "expressions")]
+        self.expressions.truncate_primitives(new_count);
+        let (bind_group, buffer, channels_buffer) = mk_primitive_bind_group(
+            device,
+            &self.bind_group_layout,
+            &self.primitives,
+            &self.grid,
+            &self.palette,
+            &self.textures,
+            &self.order_buffer,
+        );
+        self.buffer = buffer;
+        self.channels_buffer = channels_buffer;
+        self.upload(queue);
+        Ok(bind_group)
+    }
+
+    /// the bytes the primitives buffer takes on the gpu, one `SDFPrimitive` per slot. the grid,
+    /// the channels, the palette, the textures and the order buffer come on top, see
+    /// `State::gpu_memory_report`
+    pub fn memory_usage(&self) -> usize {
+        self.primitives.len() * std::mem::size_of::<SDFPrimitive>()
+    }
+
+    pub(crate) fn channels_memory_usage(&self) -> u64 {
+        self.channels_buffer.size()
+    }
+
+    pub(crate) fn order_memory_usage(&self) -> u64 {
+        self.order_buffer.size()
+    }
+
+    pub fn palette(&self) -> &PaletteManager {
+        &self.palette
+    }
+
+    /// the buffer stays the same, changing the colors needs no new bind group
+    pub fn palette_mut(&mut self) -> &mut PaletteManager {
+        &mut self.palette
+    }
+
+    pub fn textures(&self) -> &TextureAtlas {
+        &self.textures
+    }
+
+    /// the texture stays the same, changing its layers needs no new bind group
+    pub fn textures_mut(&mut self) -> &mut TextureAtlas {
+        &mut self.textures
+    }
+
+    #[cfg(feature = "expressions")]
+    pub fn expressions(&self) -> &PrimitiveExpressions {
+        &self.expressions
+    }
+
+    /// the expressions written into the primitives on every `update`, e.g.
+    /// `expressions_mut().set(&PrimitiveExpression::new(3, "position.y = sin(t + phase)"))`
+    #[cfg(feature = "expressions")]
+    pub fn expressions_mut(&mut self) -> &mut PrimitiveExpressions {
+        &mut self.expressions
+    }
+
+    pub fn template(&self) -> &SDFPrimitive {
+        &self.template
+    }
+
+    /// what `resize` fills new slots with, `SDFPrimitive::new()` unless created `with_template`.
+    /// the existing primitives are left alone
+    pub fn set_template(&mut self, template: SDFPrimitive) {
+        self.template = template;
+    }
+
+    /// gives the primitive at `index` a name to `find` it by, replacing whatever the name pointed to,
+    /// a primitive can have several names. names never reach the gpu
+    ///
+    /// panics if `index` is out of bounds
+    pub fn name_primitive(&mut self, index: usize, name: impl Into<String>) {
+        let len = self.primitives.len();
+        assert!(index < len, "can't name primitive {index}, there are only {len}");
+        self.names.insert(name.into(), index);
+    }
+
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.names.get(name).copied()
+    }
+
+    /// moves the primitive at `index` to `new_index`, shifting the ones in between by one,
+    /// e.g. to change what it's folded onto. names, the highlight, the selection (and the
+    /// expressions) move along, only the affected range is uploaded
+    ///
+    /// panics if either index is out of bounds
+    pub fn move_to(&mut self, index: usize, new_index: usize, queue: &wgpu::Queue) {
+        let len = self.primitives.len();
+        assert!(
+            index < len && new_index < len,
+            "can't move primitive {index} to {new_index}, there are only {len}"
+        );
+        if index == new_index {
+            return;
+        }
+        let primitive = self.primitives.remove(index);
+        self.primitives.insert(new_index, primitive);
+        let (start, end) = (index.min(new_index), index.max(new_index));
+        let moved = |old: usize| match old {
+            old if old == index => new_index,
+            old if old < start || old > end => old,
+            // the ones in between shift toward where the primitive left
+            old if index < new_index => old - 1,
+            old => old + 1,
+        };
+        for slot in self.names.values_mut() {
+            *slot = moved(*slot);
+        }
+        self.highlighted = self.highlighted.map(moved);
+        self.selection = self.selection.iter().map(|&old| moved(old)).collect();
+        #[cfg(feature = "expressions")]
+        self.expressions.remap_primitives(moved);
+        self.upload_indices((start..=end).collect(), queue);
+    }
+
+    /// stably reorders the primitives by `key_fn`, e.g. `|primitive| primitive.is_transparent()`
+    /// for the opaque ones first. names, the highlight, the selection (and the expressions)
+    /// move along, then everything is uploaded
+    ///
+    /// this gives up the order contract (see `PrimitiveManager`): primitives folded onto the
+    /// ones before them (`SDFPrimitive::COLOR_BLEND`, `operation`) combine with different
+    /// neighbours afterwards, so don't sort scenes relying on their order. floats can't be
+    /// keys directly, a non-negative distance's `to_bits` sorts like the distance
+    pub fn sort_primitives_by<K, F>(&mut self, mut key_fn: F, queue: &wgpu::Queue)
+    where
+        K: Ord,
+        F: FnMut(&SDFPrimitive) -> K,
+    {
+        let mut order: Vec<usize> = (0..self.primitives.len()).collect();
+        // stable, and `key_fn` runs once per primitive
+        order.sort_by_cached_key(|&index| key_fn(&self.primitives[index]));
+        if order.iter().enumerate().all(|(new, &old)| new == old) {
+            return;
+        }
+        let mut new_indices = vec![0; order.len()];
+        for (new, &old) in order.iter().enumerate() {
+            new_indices[old] = new;
+        }
+        self.primitives = order.iter().map(|&old| self.primitives[old]).collect();
+        let moved = |old: usize| new_indices[old];
+        for slot in self.names.values_mut() {
+            *slot = moved(*slot);
+        }
+        self.highlighted = self.highlighted.map(moved);
+        self.selection = self.selection.iter().map(|&old| moved(old)).collect();
+        #[cfg(feature = "expressions")]
+        self.expressions.remap_primitives(|old| new_indices.get(old).copied().unwrap_or(old));
+        self.upload(queue);
+    }
+
+    /// lets the primitive at `index` glow, e.g. the one under the cursor (see `pick_at`),
+    /// clearing the previous one. only the two changed slots are uploaded
+    ///
+    /// panics if `index` is out of bounds
+    pub fn set_highlighted(&mut self, index: Option<usize>, queue: &wgpu::Queue) {
+        if index == self.highlighted {
+            return;
+        }
+        if let Some(old) = self.highlighted.take() {
+            self.primitives[old].flags &= !SDFPrimitive::HIGHLIGHTED;
+            self.upload_one(old, queue);
+        }
+        if let Some(new) = index {
+            self.primitives[new].flags |= SDFPrimitive::HIGHLIGHTED;
+            self.upload_one(new, queue);
+        }
+        self.highlighted = index;
+    }
+
+    pub fn highlighted(&self) -> Option<usize> {
+        self.highlighted
+    }
+
+    /// adds the primitive at `index` to the selection
+    ///
+    /// panics if `index` is out of bounds
+    pub fn select(&mut self, index: usize, queue: &wgpu::Queue) {
+        if self.selection.insert(index) {
+            self.primitives[index].flags |= SDFPrimitive::SELECTED;
+            self.upload_one(index, queue);
+        }
+    }
+
+    pub fn deselect(&mut self, index: usize, queue: &wgpu::Queue) {
+        if self.selection.remove(&index) {
+            self.primitives[index].flags &= !SDFPrimitive::SELECTED;
+            self.upload_one(index, queue);
+        }
+    }
+
+    pub fn clear_selection(&mut self, queue: &wgpu::Queue) {
+        for index in std::mem::take(&mut self.selection) {
+            self.primitives[index].flags &= !SDFPrimitive::SELECTED;
+            self.upload_one(index, queue);
+        }
+    }
+
+    pub fn selection(&self) -> &HashSet<usize> {
+        &self.selection
+    }
+
+    /// the center of the selected primitives' positions, the natural pivot for `rotate_selection`
+    pub fn selection_center(&self) -> Option<cgmath::Point3<f32>> {
+        if self.selection.is_empty() {
+            return None;
+        }
+        let sum = self.selection.iter().fold(cgmath::Vector3::new(0.0, 0.0, 0.0), |sum, &index| {
+            sum + cgmath::Vector3::from(self.primitives[index].position)
+        });
+        Some(cgmath::EuclideanSpace::from_vec(sum / self.selection.len() as f32))
+    }
+
+    /// gives the primitives increasing phases in buffer order, `step` seconds apart, so an
+    /// animation ripples through them (see `SDFPrimitive::set_phase`)
+    pub fn stagger_phases(&mut self, step: f32, queue: &wgpu::Queue) {
+        for (i, primitive) in self.primitives.iter_mut().enumerate() {
+            primitive.phase = i as f32 * step;
+        }
+        self.upload(queue);
+    }
+
+    pub fn translate_selection(&mut self, delta: cgmath::Vector3<f32>, queue: &wgpu::Queue) {
+        for &index in &self.selection {
+            let position = &mut self.primitives[index].position;
+            *position = (cgmath::Vector3::from(*position) + delta).into();
+        }
+        self.upload_selection(queue);
+    }
+
+    /// moves the selection by `amount` along a world axis, for precise placement from the keyboard
+    pub fn nudge_selection(&mut self, axis: Axis, amount: f32, queue: &wgpu::Queue) {
+        self.translate_selection(axis.unit() * amount, queue);
+    }
+
+    /// rotates the selected primitives around `pivot` as one rigid group, each one's position
+    /// is rotated about the pivot (`pivot + rotation * (position - pivot)`) and its own
+    /// orientation turned by the same rotation (`rotation * orientation`)
+    pub fn rotate_selection(
+        &mut self,
+        rotation: cgmath::Quaternion<f32>,
+        pivot: cgmath::Point3<f32>,
+        queue: &wgpu::Queue,
+    ) {
+        use cgmath::Rotation;
+        for &index in &self.selection {
+            let primitive = &mut self.primitives[index];
+            let offset = cgmath::Point3::from(primitive.position) - pivot;
+            primitive.position = (pivot + rotation.rotate_vector(offset)).into();
+            primitive.rotation = (rotation * cgmath::Quaternion::from(primitive.rotation)).into();
+        }
+        self.upload_selection(queue);
+    }
+
+    /// moves the primitive at `index` by `delta` and the ones whose `position` is within
+    /// `radius` of its (unmoved) position by `delta` scaled down by `falloff`, like blender's
+    /// proportional editing. only the moved primitives are written. panics if `index` is out
+    /// of bounds
+    pub fn proportional_edit(
+        &mut self,
+        index: usize,
+        delta: cgmath::Vector3<f32>,
+        radius: f32,
+        falloff: Falloff,
+        queue: &wgpu::Queue,
+    ) {
+        use cgmath::MetricSpace;
+        let center = cgmath::Point3::from(self.primitives[index].position);
+        let mut moved = vec![index];
+        for (other, primitive) in self.primitives.iter_mut().enumerate() {
+            let distance = center.distance(cgmath::Point3::from(primitive.position));
+            let weight = if other == index {
+                1.0
+            } else if radius > 0.0 && distance < radius {
+                falloff.weight(distance / radius)
+            } else {
+                continue;
+            };
+            if weight > 0.0 {
+                primitive.position = (cgmath::Vector3::from(primitive.position) + delta * weight).into();
+                if other != index {
+                    moved.push(other);
+                }
+            }
+        }
+        self.upload_indices(moved, queue);
+    }
+
+    fn upload_selection(&mut self, queue: &wgpu::Queue) {
+        let selection: Vec<usize> = self.selection.iter().copied().collect();
+        self.upload_indices(selection, queue);
+    }
+
+    /// the box (min and max corner) around the `instance_bounds` of every primitive that
+    /// isn't parked (see `SDFPrimitive::is_parked`), `None` without any
+    pub fn bounds(&self) -> Option<(cgmath::Point3<f32>, cgmath::Point3<f32>)> {
+        live_bounds(&self.primitives)
+    }
+
+    /// the sphere (center and radius) around `bounds`
+    pub fn bounding_sphere(&self) -> Option<(cgmath::Point3<f32>, f32)> {
+        live_bounding_sphere(&self.primitives)
+    }
+
+    /// read only access, changes have to go through `iter_mut_tracked`, `update_primitives`
+    /// or `set_primitive` to reach the gpu
+    pub fn iter(&self) -> impl Iterator<Item = &SDFPrimitive> + '_ {
+        self.primitives.iter()
+    }
+
+    /// mutable access that uploads whatever was handed out when the guard is dropped,
+    /// so edits can't be forgotten
+    pub fn iter_mut_tracked<'a>(&'a mut self, queue: &'a wgpu::Queue) -> TrackedPrimitives<'a> {
+        TrackedPrimitives {
+            touched: vec![false; self.primitives.len()],
+            next: 0,
+            manager: self,
+            queue,
+        }
+    }
+
+    /// runs `SDFPrimitive::validate` on every change made through `update_primitives` and
+    /// `set_primitive`, on by default in debug builds. the per frame animation isn't checked
+    pub fn set_validation(&mut self, enabled: bool) {
+        self.validate = enabled;
+    }
+
+    /// caps the instance grid of a single primitive (see `SDFPrimitive::instance_count`),
+    /// `set_instances` and `set_primitive` reject larger ones whether or not validation is on,
+    /// the other ways of changing the primitives log them
+    pub fn set_max_instances(&mut self, max_instances: u64) {
+        self.max_instances = max_instances;
+    }
+
+    pub fn max_instances(&self) -> u64 {
+        self.max_instances
+    }
+
+    fn check_instances(&self, primitive: &SDFPrimitive) -> Result<(), PrimitiveError> {
+        let count = primitive.instance_count();
+        if count > self.max_instances {
+            return Err(PrimitiveError::TooManyInstances { count, max: self.max_instances });
+        }
+        Ok(())
+    }
+
+    /// sets the primitive's instance grid (see `SDFPrimitive::set_instances`) and uploads it,
+    /// a grid above `max_instances` is rejected and the old one kept
+    ///
+    /// panics if `index` is out of bounds
+    pub fn set_instances(
+        &mut self,
+        index: usize,
+        counts: [u32; 3],
+        distance: f32,
+        queue: &wgpu::Queue,
+    ) -> Result<(), PrimitiveError> {
+        let mut primitive = self.primitives[index];
+        primitive.set_instances(counts, distance);
+        self.check_instances(&primitive)?;
+        self.primitives[index] = primitive;
+        self.upload_indices(vec![index], queue);
+        Ok(())
+    }
+
+    /// moves the channels marked dynamic into a buffer of their own, one tightly packed `vec4`
+    /// per primitive (16 bytes instead of the 240 of a whole `SDFPrimitive`), so primitives
+    /// that only move or change color are uploaded without the rest of them: `update` then
+    /// only writes the positions while nothing turns, and so does `update_channels`. the
+    /// primitives buffer keeps the other (static) fields and is only written when they change.
+    /// none are dynamic by default
+    ///
+    /// the channels buffer is binding 8 of the primitives' group (`channels` in common.wgsl):
+    /// the slots' positions (xyz, w unused) followed by the slots' colors, both in buffer order.
+    /// the shader reads the primitives through `load_primitive`, which takes the dynamic
+    /// channels from there (the flags are `dynamic_channels` of the order buffer, binding 4)
+    ///
+    /// the spatial grid and transparency sort by position, so while either is on every change
+    /// writes everything as before
+    pub fn set_dynamic_channels(&mut self, dynamic: DynamicChannels, queue: &wgpu::Queue) {
+        self.dynamic = dynamic;
+        crate::trace_upload!("primitive order", 8, 4);
+        queue.write_buffer(&self.order_buffer, 8, bytemuck::bytes_of(&dynamic.bits()));
+        self.upload(queue);
+    }
+
+    pub fn dynamic_channels(&self) -> DynamicChannels {
+        self.dynamic
+    }
+
+    /// changes the primitives' `position` and `rgba` (`index`, position, rgba), only the
+    /// channels buffer is written if those changed are all dynamic, see `set_dynamic_channels`
+    pub fn update_channels<F>(&mut self, mut channel_updater: F, queue: &wgpu::Queue)
+    where
+        F: FnMut(usize, &mut [f32; 3], &mut [f32; 4]),
+    {
+        let mut changed = DynamicChannels::default();
+        for (index, primitive) in self.primitives.iter_mut().enumerate() {
+            let (position, rgba) = (primitive.position, primitive.rgba);
+            channel_updater(index, &mut primitive.position, &mut primitive.rgba);
+            changed.position |= primitive.position != position;
+            changed.color |= primitive.rgba != rgba;
+        }
+        self.warn_invalid();
+        if self.writes_channels_only(changed) {
+            self.write_channels(0, &self.primitives, changed, queue);
+        } else {
+            self.upload(queue);
+        }
+    }
+
+    /// whether changing `changed` only needs the channels buffer written
+    fn writes_channels_only(&self, changed: DynamicChannels) -> bool {
+        self.dynamic.contains(changed) && self.grid.resolution().is_none() && !self.transparency
+    }
+
+    /// writes the `channels` (the dynamic ones among them) of `primitives`, which sit in the
+    /// slots from `first_slot` on
+    fn write_channels(
+        &self,
+        first_slot: usize,
+        primitives: &[SDFPrimitive],
+        channels: DynamicChannels,
+        queue: &wgpu::Queue,
+    ) {
+        let channels = self.dynamic.intersection(channels);
+        let slot_count = self.buffer.size() as usize / std::mem::size_of::<SDFPrimitive>();
+        let write = |first_channel_slot: usize, values: Vec<[f32; 4]>| {
+            let offset = first_channel_slot * 16;
+            let bytes: &[u8] = bytemuck::cast_slice(&values);
+            crate::trace_upload!("primitive channels", offset, bytes.len());
+            queue.write_buffer(&self.channels_buffer, offset as wgpu::BufferAddress, bytes);
+        };
+        if channels.position {
+            let positions = primitives.iter().map(|primitive| {
+                let [x, y, z] = primitive.position;
+                [x, y, z, 0.0]
+            });
+            write(first_slot, positions.collect());
+        }
+        if channels.color {
+            write(slot_count + first_slot, primitives.iter().map(|primitive| primitive.rgba).collect());
+        }
     }
+
+    /// invalid primitives are still uploaded, but logged while validation is on
     pub fn update_primitives<F>(&mut self, primitive_updater: F, queue: &wgpu::Queue)
     where
         F: Fn(&mut Vec<SDFPrimitive>),
     {
         primitive_updater(&mut self.primitives);
-        queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&self.primitives));
+        self.warn_invalid();
+        self.upload(queue);
+    }
+
+    /// replaces every primitive, e.g. with a loaded `Scene`, resizing first if the count
+    /// differs, the returned bind group has to replace the old one then (see `resize`).
+    /// the given primitives' editor flags are ignored, the highlight and the selection stay on
+    /// their indices as far as those still exist. invalid primitives are logged like in
+    /// `update_primitives`
+    pub fn replace_all(
+        &mut self,
+        primitives: &[SDFPrimitive],
+        device: &Device,
+        queue: &wgpu::Queue,
+    ) -> Result<Option<BindGroup>, CapacityError> {
+        let bind_group = if primitives.len() != self.primitives.len() {
+            Some(self.resize(primitives.len(), device, queue)?)
+        } else {
+            None
+        };
+        self.primitives.copy_from_slice(primitives);
+        for (index, primitive) in self.primitives.iter_mut().enumerate() {
+            primitive.flags = 0;
+            if self.highlighted == Some(index) {
+                primitive.flags |= SDFPrimitive::HIGHLIGHTED;
+            }
+            if self.selection.contains(&index) {
+                primitive.flags |= SDFPrimitive::SELECTED;
+            }
+        }
+        self.warn_invalid();
+        self.update_lods(None);
+        self.upload(queue);
+        Ok(bind_group)
+    }
+
+    fn warn_invalid(&self) {
+        for (index, primitive) in self.primitives.iter().enumerate() {
+            if let Err(err) = self.check_instances(primitive) {
+                tracing::warn!("primitive {index}: {err}");
+            }
+            if !self.validate {
+                continue;
+            }
+            if let Err(err) = primitive.validate() {
+                tracing::warn!("primitive {index} is invalid: {err}");
+            }
+        }
+    }
+
+    /// replaces and uploads a single primitive, while validation is on
+    /// an invalid one is rejected and the old one kept
+    ///
+    /// panics if `index` is out of bounds
+    pub fn set_primitive(
+        &mut self,
+        index: usize,
+        primitive: SDFPrimitive,
+        queue: &wgpu::Queue,
+    ) -> Result<(), PrimitiveError> {
+        self.check_instances(&primitive)?;
+        if self.validate {
+            primitive.validate()?;
+        }
+        self.primitives[index] = primitive;
+        self.upload(queue);
+        Ok(())
+    }
+
+    /// enables the spatial acceleration grid with the given cells per axis, `None` disables it
+    pub fn set_grid_resolution(&mut self, resolution: Option<[u32; 3]>, queue: &wgpu::Queue) {
+        self.grid.set_resolution(resolution, queue);
+        self.upload(queue);
+    }
+
+    /// composites primitives with `rgba[3] < 1` as glass, off by default
+    ///
+    /// the blend contract: opaque primitives are marched as usual, then every transparent
+    /// primitive the ray hits in front of the opaque hit is blended over the result with
+    /// `mix(color, rgba.rgb, rgba.a)`, back to front. for that the buffer is uploaded partitioned,
+    /// opaque primitives first, then the transparent ones sorted from far to near by their
+    /// center's distance to the camera (see `set_view_position`), so the buffer order doesn't
+    /// match `primitives` while this is on. the sort is per primitive, not per pixel, so
+    /// intersecting glass can blend in the wrong order.
+    ///
+    /// while off every primitive is opaque and nothing is sorted
+    pub fn set_transparency(&mut self, enabled: bool, queue: &wgpu::Queue) {
+        self.transparency = enabled;
+        self.upload(queue);
+    }
+
+    /// lowers the detail of distant primitives, `None` (the default) renders everything at full detail
+    ///
+    /// each lod halves the instance counts per axis and drops the displacement noise,
+    /// see `distance_to_primitive` in main_shader.wgsl. picking always uses full detail
+    pub fn set_lod(&mut self, settings: Option<LodSettings>, queue: &wgpu::Queue) {
+        self.lod = settings;
+        if self.lod.is_none() {
+            self.primitives.iter_mut().for_each(|primitive| primitive.lod = 0);
+        }
+        self.update_lods(None);
+        self.upload(queue);
     }
+
+    pub fn lod(&self) -> Option<&LodSettings> {
+        self.lod.as_ref()
+    }
+
+    /// caps how many primitives the shader folds into a distance, the ones past the budget
+    /// (in buffer order, or within the grid cell) are skipped, which keeps pathological scenes
+    /// interactive. that's an approximation, skipped primitives are neither drawn nor blend
+    /// into the others where the budget runs out. `None` (the default) folds them all
+    pub fn set_complexity_budget(&mut self, budget: Option<u32>, queue: &wgpu::Queue) {
+        self.complexity_budget = budget;
+        crate::trace_upload!("primitive order", 4, 4);
+        queue.write_buffer(&self.order_buffer, 4, bytemuck::bytes_of(&budget.unwrap_or(u32::MAX)));
+    }
+
+    pub fn complexity_budget(&self) -> Option<u32> {
+        self.complexity_budget
+    }
+
+    /// recomputes the lods if the camera moved or they got too old, `None` forces it.
+    /// returns whether any lod changed
+    fn update_lods(&mut self, dt: Option<Duration>) -> bool {
+        let Some(settings) = &self.lod else {
+            return false;
+        };
+        if let Some(dt) = dt {
+            self.lod_age += dt;
+            let moved = cgmath::MetricSpace::distance(self.view_position, self.lod_view_position);
+            if moved < settings.min_camera_move && self.lod_age < settings.max_age {
+                return false;
+            }
+        }
+        self.lod_view_position = self.view_position;
+        self.lod_age = Duration::ZERO;
+        let mut changed = false;
+        for primitive in self.primitives.iter_mut() {
+            let lod = settings.lod_at(view_distance(primitive, self.view_position));
+            changed |= primitive.lod != lod;
+            primitive.lod = lod;
+        }
+        changed
+    }
+
+    /// the camera position the transparent primitives are sorted by, takes effect on the next upload
+    pub fn set_view_position(&mut self, view_position: cgmath::Point3<f32>) {
+        self.view_position = view_position;
+    }
+
+    /// writes the primitives in buffer order, their partition and the grid over the opaque ones
+    fn upload(&mut self, queue: &wgpu::Queue) {
+        self.primitives.iter_mut().for_each(SDFPrimitive::refresh_extent);
+        let ordered;
+        let (primitives, transparent_start) = if self.transparency {
+            let (order, transparent_start) = self.transparency_order();
+            self.slots.resize(order.len(), 0);
+            for (slot, &index) in order.iter().enumerate() {
+                self.slots[index] = slot;
+            }
+            ordered = order.iter().map(|&index| self.primitives[index]).collect::<Vec<_>>();
+            (&ordered[..], transparent_start)
+        } else {
+            self.slots = (0..self.primitives.len()).collect();
+            (&self.primitives[..], self.primitives.len())
+        };
+        let bytes: &[u8] = bytemuck::cast_slice(primitives);
+        crate::trace_upload!("primitives", 0, bytes.len());
+        queue.write_buffer(&self.buffer, 0, bytes);
+        self.write_channels(0, primitives, self.dynamic, queue);
+        crate::trace_upload!("primitive order", 0, 4);
+        queue.write_buffer(&self.order_buffer, 0, bytemuck::bytes_of(&(transparent_start as u32)));
+        self.grid.rebuild(&primitives[..transparent_start], queue);
+        self.pending = PendingChange::default();
+        self.stale.clear();
+        self.stale.resize(self.primitives.len(), Staleness::Uploaded);
+        self.uploaded_view_position = self.view_position;
+    }
+
+    /// only the given slots, coalesced into contiguous writes, unless changing them also
+    /// changes the grid or the transparency order
+    fn upload_indices(&mut self, mut indices: Vec<usize>, queue: &wgpu::Queue) {
+        if self.grid.resolution().is_some() || self.transparency {
+            self.upload(queue);
+            return;
+        }
+        indices.sort_unstable();
+        indices.dedup();
+        // without transparency every primitive sits in the slot of its index
+        let mut runs = indices.into_iter().peekable();
+        while let Some(start) = runs.next() {
+            let mut end = start + 1;
+            while runs.next_if_eq(&end).is_some() {
+                end += 1;
+            }
+            let size = std::mem::size_of::<SDFPrimitive>();
+            self.primitives[start..end].iter_mut().for_each(SDFPrimitive::refresh_extent);
+            let bytes: &[u8] = bytemuck::cast_slice(&self.primitives[start..end]);
+            crate::trace_upload!("primitives", start * size, bytes.len());
+            queue.write_buffer(&self.buffer, (start * size) as wgpu::BufferAddress, bytes);
+            self.write_channels(start, &self.primitives[start..end], self.dynamic, queue);
+            self.stale[start..end].fill(Staleness::Uploaded);
+        }
+    }
+
+    /// writes just the primitive at `index` into its slot, for small edits between full uploads
+    fn upload_one(&mut self, index: usize, queue: &wgpu::Queue) {
+        self.primitives[index].refresh_extent();
+        let size = std::mem::size_of::<SDFPrimitive>();
+        let offset = (self.slots[index] * size) as wgpu::BufferAddress;
+        crate::trace_upload!("primitive", offset, size);
+        queue.write_buffer(&self.buffer, offset, bytemuck::bytes_of(&self.primitives[index]));
+        let primitive = std::slice::from_ref(&self.primitives[index]);
+        self.write_channels(self.slots[index], primitive, self.dynamic, queue);
+        self.stale[index] = Staleness::Uploaded;
+    }
+
+    /// the indices of the opaque primitives in their original order,
+    /// then the transparent ones from far to near
+    fn transparency_order(&self) -> (Vec<usize>, usize) {
+        let (mut ordered, mut transparent): (Vec<_>, Vec<_>) = (0..self.primitives.len())
+            .partition(|&index| !self.primitives[index].is_transparent());
+        let transparent_start = ordered.len();
+        let distance = |&index: &usize| view_distance(&self.primitives[index], self.view_position);
+        transparent.sort_by(|a, b| distance(b).total_cmp(&distance(a)));
+        ordered.extend(transparent);
+        (ordered, transparent_start)
+    }
+    /// moves and turns the primitives, the buffer is only written once they changed by more
+    /// than `UPLOAD_EPSILON` (small changes add up over the frames until they do), so a
+    /// paused (`dt` of zero) or still scene doesn't upload anything
     pub fn update(&mut self, dt: Duration, queue: &wgpu::Queue) {
         // self.total_time += dt;
         // let total_time = self.total_time;
-        let updater = |primitives: &mut Vec<SDFPrimitive>| {
-            for primitive in primitives.iter_mut() {
-                primitive.position[2] -= primitive.speed * dt.as_secs_f32();
-                // let (v0, v1) = (Simd::from(primitive.rotation), Simd::from(primitive.rotation_delta));
-                // primitive.rotation = (v0 * v1).into();
-                primitive.rotation = (cgmath::Quaternion::from(primitive.rotation)
-                    * cgmath::Quaternion::from(primitive.rotation_delta)).into();
+        let Some((change, static_change)) = advance(&mut self.primitives, &mut self.stale, dt) else {
+            return;
+        };
+        // the expressions can set anything, so any value they write counts as a change
+        #[cfg(feature = "expressions")]
+        let (change, static_change) = if self.expressions.evaluate(dt, &mut self.primitives) {
+            for source in self.expressions.sources() {
+                if let Some(stale) = self.stale.get_mut(source.primitive) {
+                    *stale = Staleness::Changed;
+                }
             }
+            (change.max(UPLOAD_EPSILON), static_change.max(UPLOAD_EPSILON))
+        } else {
+            (change, static_change)
+        };
+        self.pending.add(change, static_change);
+        let lods_changed = self.update_lods(Some(dt));
+        // the transparent primitives are sorted by their distance to the camera
+        let view_moved = self.transparency
+            && cgmath::MetricSpace::distance(self.view_position, self.uploaded_view_position)
+                > UPLOAD_EPSILON;
+        let moved = DynamicChannels {
+            position: true,
+            color: false,
         };
-        self.update_primitives(updater, queue)
+        match self.pending.upload(lods_changed, view_moved, self.writes_channels_only(moved)) {
+            Upload::Nothing => {}
+            Upload::Channels => {
+                // the turns below the epsilon stay pending for the next full upload
+                self.write_channels(0, &self.primitives, moved, queue);
+                self.pending.total = 0.0;
+                for stale in self.stale.iter_mut().filter(|stale| **stale == Staleness::Moved) {
+                    *stale = Staleness::Uploaded;
+                }
+            }
+            Upload::Everything => self.upload(queue),
+        }
     }
 
     pub fn get_spawnable_primitive(&mut self) -> Option<&mut SDFPrimitive> {
-        self.primitives.iter_mut().find(|primitive| {
-            primitive.position[2] < -1000.0 || primitive.position[2] > 3000.0
-        })
+        self.primitives.iter_mut().find(|primitive| primitive.is_parked())
+    }
+}
+
+/// the guard of `PrimitiveManager::iter_mut_tracked`, every primitive it hands out counts as
+/// changed and is validated (if enabled) and uploaded on drop
+///
+/// it lends one primitive at a time, so it can't be a std `Iterator`:
+/// `while let Some(primitive) = tracked.next_primitive() { .. }`
+pub struct TrackedPrimitives<'a> {
+    manager: &'a mut PrimitiveManager,
+    queue: &'a wgpu::Queue,
+    touched: Vec<bool>,
+    next: usize,
+}
+
+impl TrackedPrimitives<'_> {
+    pub fn next_primitive(&mut self) -> Option<&mut SDFPrimitive> {
+        let index = self.next;
+        self.next += 1;
+        self.get_mut(index)
+    }
+
+    /// `None` if `index` is out of bounds
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut SDFPrimitive> {
+        let primitive = self.manager.primitives.get_mut(index)?;
+        self.touched[index] = true;
+        Some(primitive)
+    }
+
+    pub fn for_each(&mut self, mut f: impl FnMut(usize, &mut SDFPrimitive)) {
+        self.touched.iter_mut().for_each(|touched| *touched = true);
+        for (index, primitive) in self.manager.primitives.iter_mut().enumerate() {
+            f(index, primitive);
+        }
     }
 }
 
+impl Drop for TrackedPrimitives<'_> {
+    fn drop(&mut self) {
+        let touched: Vec<usize> = (0..self.touched.len()).filter(|&index| self.touched[index]).collect();
+        for &index in &touched {
+            let primitive = &self.manager.primitives[index];
+            if let Err(err) = self.manager.check_instances(primitive) {
+                tracing::warn!("primitive {index}: {err}");
+            }
+            if !self.manager.validate {
+                continue;
+            }
+            if let Err(err) = primitive.validate() {
+                tracing::warn!("primitive {index} is invalid: {err}");
+            }
+        }
+        if !touched.is_empty() {
+            self.manager.upload_indices(touched, self.queue);
+        }
+    }
+}
+
+/// see `PrimitiveManager::bounds`
+fn live_bounds(primitives: &[SDFPrimitive]) -> Option<(cgmath::Point3<f32>, cgmath::Point3<f32>)> {
+    primitives
+        .iter()
+        .filter(|primitive| !primitive.is_parked())
+        .map(SDFPrimitive::instance_bounds)
+        .reduce(|(min, max), (lo, hi)| {
+            (
+                cgmath::Point3::new(min.x.min(lo.x), min.y.min(lo.y), min.z.min(lo.z)),
+                cgmath::Point3::new(max.x.max(hi.x), max.y.max(hi.y), max.z.max(hi.z)),
+            )
+        })
+}
+
+/// see `PrimitiveManager::bounding_sphere`
+fn live_bounding_sphere(primitives: &[SDFPrimitive]) -> Option<(cgmath::Point3<f32>, f32)> {
+    live_bounds(primitives).map(|(min, max)| {
+        let half_diagonal = (max - min) * 0.5;
+        (min + half_diagonal, cgmath::InnerSpace::magnitude(half_diagonal))
+    })
+}
+
+/// distance from `view_position` to the closest of the primitive's infinitely repeated copies
+fn view_distance(primitive: &SDFPrimitive, view_position: cgmath::Point3<f32>) -> f32 {
+    let mut delta = cgmath::Point3::from(primitive.position) - view_position;
+    delta.x -= INFINITE_REPETITION_PERIOD * (delta.x / INFINITE_REPETITION_PERIOD).round();
+    delta.y -= INFINITE_REPETITION_PERIOD * (delta.y / INFINITE_REPETITION_PERIOD).round();
+    cgmath::InnerSpace::magnitude(delta)
+}
+
+fn mk_primitive_bind_group_layout(device: &Device, visibility: ShaderStages) -> BindGroupLayout {
+    // binding 0 are the primitives, 1..=3 the spatial grid (header, cell offsets, indices),
+    // 4 the primitive order (where the transparent primitives start), 5 the palette,
+    // 6 and 7 the texture atlas and its sampler and 8 the dynamic channels
+    let storage_entry = |binding| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only: true },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        count: None,
+    };
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &[
+            storage_entry(0),
+            storage_entry(1),
+            storage_entry(2),
+            storage_entry(3),
+            storage_entry(4),
+            wgpu::BindGroupLayoutEntry {
+                binding: 5,
+                visibility,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 6,
+                visibility,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2Array,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 7,
+                visibility,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+            storage_entry(8),
+        ],
+        label: Some("primitives_bind_group_layout"),
+    })
+}
+
 fn mk_primitive_bind_group(
     device: &Device,
-    primitive_count: u8,
-) -> (BindGroup, BindGroupLayout, Buffer) {
+    layout: &BindGroupLayout,
+    primitives: &[SDFPrimitive],
+    grid: &SpatialGrid,
+    palette: &PaletteManager,
+    textures: &TextureAtlas,
+    order_buffer: &Buffer,
+) -> (BindGroup, Buffer, Buffer) {
     let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
         label: Some("Primitives Buffer"),
-        contents: bytemuck::cast_slice(&vec![SDFPrimitive::new(); primitive_count as usize]),
+        contents: bytemuck::cast_slice(primitives),
         usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
     });
-
-    let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-        entries: &[wgpu::BindGroupLayoutEntry {
-            binding: 0,
-            visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
-            ty: wgpu::BindingType::Buffer {
-                ty: wgpu::BufferBindingType::Storage { read_only: true },
-                has_dynamic_offset: false,
-                min_binding_size: None,
-            },
-            count: None,
-        }],
-        label: Some("primitives_bind_group_layout"),
+    // a position and a color per slot, see `PrimitiveManager::set_dynamic_channels`
+    let channels_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Primitive Channels Buffer"),
+        size: (2 * primitives.len().max(1) * 16) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
     });
 
+    let [header, cell_offsets, indices] = grid.bind_group_entries();
+    let [texture, sampler] = textures.bind_group_entries();
     let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        layout: &layout,
-        entries: &[wgpu::BindGroupEntry {
-            binding: 0,
-            resource: buffer.as_entire_binding(),
-        }],
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            },
+            header,
+            cell_offsets,
+            indices,
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: order_buffer.as_entire_binding(),
+            },
+            palette.bind_group_entry(),
+            texture,
+            sampler,
+            wgpu::BindGroupEntry {
+                binding: 8,
+                resource: channels_buffer.as_entire_binding(),
+            },
+        ],
         label: Some("primitives_bind_group"),
     });
-    (bind_group, layout, buffer)
+    (bind_group, buffer, channels_buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::assert_abs_diff_eq;
+
+    #[test]
+    fn parked_slots_dont_change_the_bounding_sphere() {
+        let live = SDFPrimitive {
+            position: [3.0, -2.0, 50.0],
+            ..SDFPrimitive::new()
+        };
+        let parked = SDFPrimitive::new();
+        assert!(parked.is_parked() && !live.is_parked());
+        assert_eq!(live_bounding_sphere(&[live]), live_bounding_sphere(&[live, parked]));
+        assert_eq!(live_bounding_sphere(&[parked]), None);
+    }
+
+    /// moving along z and turning a little around y every frame
+    fn spinning() -> SDFPrimitive {
+        let turn = <cgmath::Quaternion<f32> as cgmath::Rotation3>::from_angle_y(cgmath::Rad(0.1));
+        SDFPrimitive {
+            position: [0.0, 0.0, 50.0],
+            speed: 2.0,
+            rotation_delta: turn.into(),
+            ..SDFPrimitive::new()
+        }
+    }
+
+    #[test]
+    fn zero_dt_changes_nothing() {
+        let mut primitives = [spinning()];
+        let mut stale = [Staleness::Uploaded];
+        assert_eq!(advance(&mut primitives, &mut stale, Duration::ZERO), None);
+        assert_eq!(primitives[0].position, spinning().position);
+        assert_eq!(primitives[0].rotation, spinning().rotation);
+        assert_eq!(stale, [Staleness::Uploaded]);
+        // so `update` returns before anything is written
+        assert_eq!(PendingChange::default().upload(false, false, true), Upload::Nothing);
+    }
+
+    #[test]
+    fn advancing_marks_the_primitives_stale() {
+        let mover = SDFPrimitive {
+            rotation_delta: [0.0, 0.0, 0.0, 1.0],
+            ..spinning()
+        };
+        let mut primitives = [spinning(), mover];
+        let mut stale = [Staleness::Uploaded; 2];
+        let (change, static_change) =
+            advance(&mut primitives, &mut stale, Duration::from_millis(500)).unwrap();
+        assert_abs_diff_eq!(change, 1.0, epsilon = 1e-4);
+        assert_abs_diff_eq!(static_change, 0.1, epsilon = 1e-4);
+        assert_eq!(primitives[1].position[2], 49.0);
+        assert_eq!(stale, [Staleness::Changed, Staleness::Moved]);
+    }
+
+    #[test]
+    fn nothing_pending_uploads_nothing() {
+        // a zero `dt` returns before adding anything, a still scene adds zeros
+        let mut pending = PendingChange::default();
+        assert_eq!(pending.upload(false, false, false), Upload::Nothing);
+        pending.add(0.0, 0.0);
+        assert_eq!(pending.upload(false, false, false), Upload::Nothing);
+    }
+
+    #[test]
+    fn changes_below_epsilon_dont_upload() {
+        let mut pending = PendingChange::default();
+        pending.add(0.5 * UPLOAD_EPSILON, 0.5 * UPLOAD_EPSILON);
+        assert_eq!(pending.upload(false, false, false), Upload::Nothing);
+        assert_eq!(pending.upload(false, false, true), Upload::Nothing);
+    }
+
+    #[test]
+    fn small_changes_add_up() {
+        let mut pending = PendingChange::default();
+        let mut frames = 0;
+        while pending.upload(false, false, false) == Upload::Nothing {
+            pending.add(0.3 * UPLOAD_EPSILON, 0.3 * UPLOAD_EPSILON);
+            frames += 1;
+            assert!(frames <= 4, "still nothing to upload after {frames} frames");
+        }
+        assert_eq!(pending.upload(false, false, false), Upload::Everything);
+    }
+
+    #[test]
+    fn moves_alone_only_write_the_channels() {
+        let mut pending = PendingChange::default();
+        pending.add(1.0, 0.0);
+        assert_eq!(pending.upload(false, false, true), Upload::Channels);
+        assert_eq!(pending.upload(false, false, false), Upload::Everything);
+        // new lods change more than the positions
+        assert_eq!(pending.upload(true, false, true), Upload::Everything);
+        pending.add(0.0, 1.0);
+        assert_eq!(pending.upload(false, false, true), Upload::Everything);
+    }
+
+    #[test]
+    fn lods_and_view_upload_without_changes() {
+        let pending = PendingChange::default();
+        assert_eq!(pending.upload(true, false, false), Upload::Everything);
+        assert_eq!(pending.upload(false, true, false), Upload::Everything);
+    }
 }
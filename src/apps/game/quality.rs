@@ -0,0 +1,59 @@
+use std::time::Duration;
+
+/// how many steps the marcher takes per ray unless adapted, see `CameraUniform::max_steps`
+pub const DEFAULT_MAX_STEPS: u32 = 32;
+
+/// how much of a new frame time goes into the rolling average
+const AVERAGE_WEIGHT: f32 = 0.1;
+/// the fraction of the relative difference to the target the step count moves per frame
+const NUDGE: f32 = 0.05;
+
+/// adjusts the marcher's step count every frame to keep the frame time at a target,
+/// see `State::set_target_frame_time`
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdaptiveSteps {
+    pub target: Duration,
+    /// the bounds the step count stays within
+    pub min_steps: u32,
+    pub max_steps: u32,
+    /// rolling average of the frame time in seconds, `None` until the first frame
+    average: Option<f32>,
+    /// kept fractional so the small nudges add up
+    steps: f32,
+}
+
+impl AdaptiveSteps {
+    pub fn new(target: Duration) -> Self {
+        Self {
+            target,
+            min_steps: 8,
+            max_steps: 128,
+            average: None,
+            steps: DEFAULT_MAX_STEPS as f32,
+        }
+    }
+
+    /// the step count for the next frame after one that took `dt`
+    pub fn update(&mut self, dt: Duration) -> u32 {
+        let dt = dt.as_secs_f32();
+        if dt > 0.0 {
+            let average = match self.average {
+                Some(average) => average + (dt - average) * AVERAGE_WEIGHT,
+                None => dt,
+            };
+            self.average = Some(average);
+            // positive while there's time left, so more steps fit
+            let headroom = (self.target.as_secs_f32() / average - 1.0).clamp(-1.0, 1.0);
+            self.steps *= 1.0 + NUDGE * headroom;
+        }
+        self.steps = self
+            .steps
+            .clamp(self.min_steps as f32, self.max_steps.max(self.min_steps) as f32);
+        self.steps.round() as u32
+    }
+
+    /// the rolling average frame time the steps are adapted to
+    pub fn average_frame_time(&self) -> Option<Duration> {
+        self.average.map(Duration::from_secs_f32)
+    }
+}
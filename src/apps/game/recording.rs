@@ -0,0 +1,159 @@
+use std::fmt;
+use std::num::NonZeroU32;
+use std::time::Duration;
+
+use super::wgpu;
+use super::{CurrentScene, GameRendering, State};
+
+/// what `record_sequence` replaces with the 1-based, zero-padded frame number
+pub const FRAME_PLACEHOLDER: &str = "{}";
+
+impl State {
+    /// renders `duration` at `fps` into numbered png files, `path_pattern` has to contain
+    /// `FRAME_PLACEHOLDER` (e.g. `"out/frame_{}.png"` gives `out/frame_0001.png`, ...)
+    ///
+    /// the level advances by exactly `1 / fps` per frame, independent of how long rendering
+    /// takes, so starting from the same state (same seed, same camera) gives the same images.
+    /// `scene_update` runs before each frame is stepped with the frame's index, to script
+    /// the camera or the primitives. stops early on game over, returns how many frames were written
+    ///
+    /// the images hold the same values the screen shows, at the current size
+    pub fn record_sequence<F: FnMut(&mut Self, u32)>(
+        &mut self,
+        path_pattern: &str,
+        fps: u32,
+        duration: Duration,
+        mut scene_update: F,
+    ) -> Result<u32, RecordError> {
+        if !path_pattern.contains(FRAME_PLACEHOLDER) {
+            return Err(RecordError::Pattern);
+        }
+        let dt = Duration::from_secs(1) / fps.max(1);
+        let frame_count = (duration.as_secs_f64() * fps as f64).round() as u32;
+        let mut written = 0;
+        for frame in 0..frame_count {
+            scene_update(self, frame);
+            let CurrentScene::Level(single_level_manager) = &mut self.scene else {
+                break;
+            };
+            single_level_manager.update(dt, &self.queue);
+            if single_level_manager.game_over {
+                self.scene = CurrentScene::GameOver;
+                break;
+            }
+            let (pixels, (width, height)) = self.render_to_pixels()?;
+            let path = path_pattern.replace(FRAME_PLACEHOLDER, &format!("{:04}", frame + 1));
+            image::save_buffer(path, &pixels, width, height, image::ColorType::Rgba8)?;
+            written += 1;
+        }
+        // the next interactive frame shouldn't see the recording as one huge step
+        self.last_time = None;
+        Ok(written)
+    }
+
+    /// renders the current state offscreen and reads the result back as 8 bit rgba
+    fn render_to_pixels(&self) -> Result<(Vec<u8>, (u32, u32)), RecordError> {
+        let frame = self.frame_settings();
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Recording Encoder"),
+            });
+        let mut renderer = self.renderer.write();
+        let resources: &mut GameRendering = renderer.paint_callback_resources.get_mut().unwrap();
+        resources.add_target_usage(&self.device, wgpu::TextureUsages::COPY_SRC);
+        resources.prepare(&self.device, &self.queue, &mut encoder, &frame);
+        let (width, height) = resources.targets.size();
+        // COLOR_FORMAT is 4 half floats per pixel
+        let unpadded_bytes_per_row = width * 8;
+        let alignment = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let bytes_per_row = (unpadded_bytes_per_row + alignment - 1) / alignment * alignment;
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Recording Buffer"),
+            size: (bytes_per_row * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let (texture, _) = resources.output(&frame);
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: NonZeroU32::new(bytes_per_row),
+                    rows_per_image: None,
+                },
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+        drop(renderer);
+        self.queue.submit(Some(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        receiver
+            .recv()
+            .map_err(|_| RecordError::Readback)?
+            .map_err(|_| RecordError::Readback)?;
+        let data = slice.get_mapped_range();
+        let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+        for row in data.chunks(bytes_per_row as usize) {
+            for channel in row[..unpadded_bytes_per_row as usize].chunks_exact(2) {
+                let value = f16_to_f32(u16::from_le_bytes([channel[0], channel[1]]));
+                // the blit hands the values to the screen as they are
+                pixels.push((value.clamp(0.0, 1.0) * 255.0).round() as u8);
+            }
+        }
+        Ok((pixels, (width, height)))
+    }
+}
+
+/// ieee 754 half precision, as stored in `COLOR_FORMAT`
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f32;
+    sign * match exponent {
+        0 => mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0.0 => f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+/// why `State::record_sequence` stopped
+#[derive(Debug)]
+pub enum RecordError {
+    /// the path pattern lacks `FRAME_PLACEHOLDER`
+    Pattern,
+    /// the rendered frame couldn't be read back from the gpu
+    Readback,
+    Image(image::ImageError),
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Pattern => write!(f, "the path pattern has no `{FRAME_PLACEHOLDER}` for the frame number"),
+            Self::Readback => write!(f, "can't read the rendered frame back"),
+            Self::Image(err) => write!(f, "can't write the frame: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for RecordError {}
+
+impl From<image::ImageError> for RecordError {
+    fn from(err: image::ImageError) -> Self {
+        Self::Image(err)
+    }
+}
@@ -0,0 +1,179 @@
+use super::render_targets::{post_process_pipeline, RenderTargets};
+use super::wgpu::{self, util::DeviceExt, BindGroup, BindGroupLayout, Buffer, Device, Queue};
+use super::{BindGroups, CAMERA_GROUP, ENVIRONMENT_GROUP, PRIMITIVES_GROUP, VISIBLE_GROUP};
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct ReflectionSettings {
+    /// how many samples are taken along the reflected ray
+    pub max_steps: u32,
+    /// how far behind the gbuffer surface a sample may be to still count as a hit
+    pub thickness: f32,
+    /// blend factor of the reflection, scaled by the hit's metalness
+    pub intensity: f32,
+    /// pixels with a lower metalness are left untouched
+    pub metalness_threshold: f32,
+}
+
+impl Default for ReflectionSettings {
+    fn default() -> Self {
+        Self {
+            max_steps: 64,
+            thickness: 20.0,
+            intensity: 0.5,
+            metalness_threshold: 0.5,
+        }
+    }
+}
+
+/// screen-space reflections post pass, reads the marcher's color and gbuffer targets
+/// and writes `RenderTargets::post`, falling back to the environment map where the
+/// reflected ray leaves the screen
+pub(crate) struct ReflectionPass {
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: BindGroupLayout,
+    settings_buffer: Buffer,
+    bind_group: BindGroup,
+}
+
+impl ReflectionPass {
+    pub fn new(
+        device: &Device,
+        primitives_bind_group_layout: &BindGroupLayout,
+        camera_bind_group_layout: &BindGroupLayout,
+        environment_bind_group_layout: &BindGroupLayout,
+        targets: &RenderTargets,
+    ) -> Self {
+        let texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                texture_entry(0),
+                texture_entry(1),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("reflection_bind_group_layout"),
+        });
+        let settings_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Reflection Settings Buffer"),
+            contents: bytemuck::bytes_of(&ReflectionSettings::default()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Reflection Shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                concat!(
+                    include_str!("level/common.wgsl"),
+                    include_str!("level/environment.wgsl"),
+                    include_str!("level/fullscreen.wgsl"),
+                    include_str!("level/reflection_shader.wgsl")
+                )
+                .into(),
+            ),
+        });
+        // same group indices as the marcher, so the shared wgsl snippets can be reused
+        let pipeline = post_process_pipeline(
+            device,
+            "Reflection Pipeline",
+            &shader,
+            &[
+                primitives_bind_group_layout,
+                camera_bind_group_layout,
+                &bind_group_layout,
+                environment_bind_group_layout,
+            ],
+        );
+        let bind_group = mk_bind_group(device, &bind_group_layout, &settings_buffer, targets);
+        Self {
+            pipeline,
+            bind_group_layout,
+            settings_buffer,
+            bind_group,
+        }
+    }
+
+    /// the settings buffer, in bytes
+    pub fn memory_usage(&self) -> u64 {
+        self.settings_buffer.size()
+    }
+
+    /// has to be called whenever the targets were recreated
+    pub fn rebind(&mut self, device: &Device, targets: &RenderTargets) {
+        self.bind_group = mk_bind_group(device, &self.bind_group_layout, &self.settings_buffer, targets);
+    }
+
+    pub fn run(
+        &self,
+        queue: &Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        bind_groups: &BindGroups,
+        targets: &RenderTargets,
+        settings: &ReflectionSettings,
+    ) {
+        crate::trace_upload!("reflection settings", 0, std::mem::size_of::<ReflectionSettings>());
+        queue.write_buffer(&self.settings_buffer, 0, bytemuck::bytes_of(settings));
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Reflection Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &targets.post,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(PRIMITIVES_GROUP, &bind_groups.primitives_bind_group, &[]);
+        pass.set_bind_group(CAMERA_GROUP, &bind_groups.camera_bind_group, &[]);
+        pass.set_bind_group(VISIBLE_GROUP, &self.bind_group, &[]);
+        pass.set_bind_group(ENVIRONMENT_GROUP, &bind_groups.environment_bind_group, &[]);
+        pass.draw(0..3, 0..1);
+    }
+}
+
+fn mk_bind_group(
+    device: &Device,
+    layout: &BindGroupLayout,
+    settings_buffer: &Buffer,
+    targets: &RenderTargets,
+) -> BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&targets.color),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(&targets.gbuffer),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: settings_buffer.as_entire_binding(),
+            },
+        ],
+        label: Some("reflection_bind_group"),
+    })
+}
@@ -0,0 +1,361 @@
+use super::memory::texture_bytes;
+use super::viewports::ViewRect;
+use super::wgpu::{
+    self, util::DeviceExt, BindGroup, BindGroupLayout, Buffer, Device, Queue, Texture,
+    TextureFormat, TextureUsages, TextureView,
+};
+
+/// hdr color the marcher and post-processing passes render into
+pub const COLOR_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+/// distance to the hit and its metalness, see `FragmentOutput` in main_shader.wgsl
+pub const GBUFFER_FORMAT: TextureFormat = TextureFormat::Rg32Float;
+/// the marched surfaces' depth (written by main_shader.wgsl's `frag_depth`), the gizmo
+/// and frustum passes test against it
+pub const DEPTH_FORMAT: TextureFormat = TextureFormat::Depth32Float;
+
+/// what the render targets are always created with: every pass draws into them
+/// (`RENDER_ATTACHMENT`) and the reflections, motion blur, bloom and the blit sample them
+/// (`TEXTURE_BINDING`). features needing more add it with `GameRendering::add_target_usage`:
+///
+/// | feature | usage |
+/// |---|---|
+/// | reading the image back (`State::record_sequence`) | `COPY_SRC` |
+/// | copying the image into a texture of your own | `COPY_SRC` |
+/// | writing a target from a compute pass | `STORAGE_BINDING` |
+///
+/// the surface's usage is separate, see `InitOptions::surface_usage`
+pub const REQUIRED_TARGET_USAGES: TextureUsages =
+    TextureUsages::RENDER_ATTACHMENT.union(TextureUsages::TEXTURE_BINDING);
+
+/// offscreen textures the level is rendered into before being blitted into egui's render pass,
+/// they follow the size of the painted rect
+pub(crate) struct RenderTargets {
+    size: (u32, u32),
+    /// `REQUIRED_TARGET_USAGES` and whatever was asked for on top
+    usage: TextureUsages,
+    pub color: TextureView,
+    pub gbuffer: TextureView,
+    /// written by post-processing passes reading `color`
+    pub post: TextureView,
+    /// written by the motion blur, which reads `color` or `post`
+    pub blurred: TextureView,
+    /// `DEPTH_FORMAT`, only ever an attachment so it ignores `usage`
+    pub depth: TextureView,
+    /// behind `color`, `post` and `blurred`, to copy the result out (see `output`)
+    textures: [Texture; 3],
+}
+
+impl RenderTargets {
+    /// `usage` is added to `REQUIRED_TARGET_USAGES`
+    pub fn new(device: &Device, size: (u32, u32), usage: TextureUsages) -> Self {
+        let size = (size.0.max(1), size.1.max(1));
+        let usage = REQUIRED_TARGET_USAGES | usage;
+        let mk_texture = |label, format, usage| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size: wgpu::Extent3d {
+                    width: size.0,
+                    height: size.1,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage,
+                view_formats: &[],
+            })
+        };
+        let view = |texture: &Texture| texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let textures = [
+            mk_texture("Color Target", COLOR_FORMAT, usage),
+            mk_texture("Post Target", COLOR_FORMAT, usage),
+            mk_texture("Blurred Target", COLOR_FORMAT, usage),
+        ];
+        Self {
+            size,
+            usage,
+            color: view(&textures[0]),
+            gbuffer: view(&mk_texture("GBuffer Target", GBUFFER_FORMAT, usage)),
+            post: view(&textures[1]),
+            blurred: view(&textures[2]),
+            depth: view(&mk_texture("Depth Target", DEPTH_FORMAT, TextureUsages::RENDER_ATTACHMENT)),
+            textures,
+        }
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    pub fn usage(&self) -> TextureUsages {
+        self.usage
+    }
+
+    /// the three color targets, the gbuffer and the depth, in bytes
+    pub fn memory_usage(&self) -> u64 {
+        3 * texture_bytes(self.size, COLOR_FORMAT)
+            + texture_bytes(self.size, GBUFFER_FORMAT)
+            + texture_bytes(self.size, DEPTH_FORMAT)
+    }
+
+    /// the depth attachment of a pass drawing over the level: testing against the marched
+    /// surfaces while `depth_test` is set, against a cleared (far) depth otherwise
+    pub fn overlay_depth(&self, depth_test: bool) -> wgpu::RenderPassDepthStencilAttachment<'_> {
+        wgpu::RenderPassDepthStencilAttachment {
+            view: &self.depth,
+            depth_ops: Some(wgpu::Operations {
+                load: if depth_test { wgpu::LoadOp::Load } else { wgpu::LoadOp::Clear(1.0) },
+                store: true,
+            }),
+            stencil_ops: None,
+        }
+    }
+
+    /// the texture the last pass wrote, `color` without post passes, `post` after reflections
+    /// and `blurred` after the motion blur
+    pub fn output(&self, after_post: bool, after_blur: bool) -> (&Texture, &TextureView) {
+        if after_blur {
+            (&self.textures[2], &self.blurred)
+        } else if after_post {
+            (&self.textures[1], &self.post)
+        } else {
+            (&self.textures[0], &self.color)
+        }
+    }
+
+    /// recreates the targets if the size changed, returns whether it did
+    pub fn resize(&mut self, device: &Device, size: (u32, u32)) -> bool {
+        if (size.0.max(1), size.1.max(1)) == self.size {
+            return false;
+        }
+        *self = Self::new(device, size, self.usage);
+        true
+    }
+
+    /// recreates the targets if they lack some of `usage`, returns whether it did
+    pub fn add_usage(&mut self, device: &Device, usage: TextureUsages) -> bool {
+        if self.usage.contains(usage) {
+            return false;
+        }
+        *self = Self::new(device, self.size, self.usage | usage);
+        true
+    }
+}
+
+/// where the gizmo and frustum passes draw: the image the blit reads, the depth to test
+/// against (see `RenderTargets::overlay_depth`) and the main camera's rect
+pub(crate) struct OverlayTarget<'a> {
+    pub view: &'a TextureView,
+    pub depth: wgpu::RenderPassDepthStencilAttachment<'a>,
+    pub rect: ViewRect,
+}
+
+impl<'a> OverlayTarget<'a> {
+    /// a pass drawing over the image within `rect`
+    pub fn begin_pass<'e>(&self, encoder: &'e mut wgpu::CommandEncoder, label: &str) -> wgpu::RenderPass<'e>
+    where
+        'a: 'e,
+    {
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some(label),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: self.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: Some(self.depth.clone()),
+        });
+        let rect = self.rect;
+        let (x, y) = (rect.origin.0 as f32, rect.origin.1 as f32);
+        pass.set_viewport(x, y, rect.size.0 as f32, rect.size.1 as f32, 0.0, 1.0);
+        pass
+    }
+}
+
+/// what the gizmo and frustum pipelines test their lines with, they don't write depth
+/// themselves so they can't hide each other
+pub(crate) fn overlay_depth_stencil() -> wgpu::DepthStencilState {
+    wgpu::DepthStencilState {
+        format: DEPTH_FORMAT,
+        depth_write_enabled: false,
+        depth_compare: wgpu::CompareFunction::LessEqual,
+        stencil: wgpu::StencilState::default(),
+        bias: wgpu::DepthBiasState::default(),
+    }
+}
+
+/// a fullscreen pass writing a `COLOR_FORMAT` target, post-processing passes share this
+pub(crate) fn post_process_pipeline(
+    device: &Device,
+    label: &str,
+    shader: &wgpu::ShaderModule,
+    bind_group_layouts: &[&BindGroupLayout],
+) -> wgpu::RenderPipeline {
+    let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some(label),
+        bind_group_layouts,
+        push_constant_ranges: &[],
+    });
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(&layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: "fs_main",
+            targets: &[Some(COLOR_FORMAT.into())],
+        }),
+        primitive: wgpu::PrimitiveState::default(),
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    })
+}
+
+/// draws an offscreen color target into egui's render pass
+pub(crate) struct Blit {
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: BindGroupLayout,
+    sampler: wgpu::Sampler,
+    /// `BlitSettings` in blit_shader.wgsl, shared by all the blit's bind groups
+    settings_buffer: Buffer,
+    dither: bool,
+}
+
+impl Blit {
+    pub fn new(device: &Device, target_format: TextureFormat) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("blit_bind_group_layout"),
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Blit Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let settings_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Blit Settings Buffer"),
+            contents: bytemuck::cast_slice(&[0u32; 4]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Blit Shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                concat!(
+                    include_str!("level/fullscreen.wgsl"),
+                    include_str!("level/blit_shader.wgsl")
+                )
+                .into(),
+            ),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Blit Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Blit Pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(target_format.into())],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+        Self {
+            pipeline,
+            bind_group_layout,
+            sampler,
+            settings_buffer,
+            dither: false,
+        }
+    }
+
+    /// the settings buffer, in bytes
+    pub fn memory_usage(&self) -> u64 {
+        self.settings_buffer.size()
+    }
+
+    /// adds noise below one 8 bit step to the output, hiding the banding of smooth gradients
+    /// on 8 bit targets, off by default. only uploads when it changed
+    pub fn set_dither(&mut self, queue: &Queue, dither: bool) {
+        if self.dither == dither {
+            return;
+        }
+        self.dither = dither;
+        crate::trace_upload!("blit settings", 0, 4);
+        queue.write_buffer(&self.settings_buffer, 0, bytemuck::bytes_of(&(dither as u32)));
+    }
+
+    pub fn bind_group(&self, device: &Device, source: &TextureView) -> BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(source),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: self.settings_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("blit_bind_group"),
+        })
+    }
+
+    pub fn render<'rp>(&'rp self, render_pass: &mut wgpu::RenderPass<'rp>, source: &'rp BindGroup) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, source, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
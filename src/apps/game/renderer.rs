@@ -0,0 +1,238 @@
+use std::fmt;
+use std::time::Duration;
+
+use super::camera::RenderCamera;
+use super::capabilities::supported_present_mode;
+use super::device::{init_wgpu_with_present_modes, InitError, InitOptions};
+use super::primitives::{PrimitiveManager, SDFPrimitive};
+use super::wgpu::{self, Device, Queue, Surface, SurfaceConfiguration};
+use super::{level, CapacityError, FrameSettings, GameRendering, ViewLayout, MAX_DEBUG_FRUSTUMS};
+
+/// the level and everything drawing it into a surface, for apps with a window of their own
+/// (the eframe app uses `State`, which draws through egui's render pass instead)
+///
+/// ```ignore
+/// let instance = wgpu::Instance::default();
+/// let surface = unsafe { instance.create_surface(&window) }?;
+/// let mut renderer = Renderer::new(&instance, surface, (width, height), InitOptions::default()).await?;
+/// // on every frame
+/// renderer.update(dt);
+/// renderer.render()?;
+/// ```
+pub struct Renderer {
+    device: Device,
+    queue: Queue,
+    surface: Surface,
+    config: SurfaceConfiguration,
+    /// what the surface supports, see `set_present_mode`
+    present_modes: Vec<wgpu::PresentMode>,
+    level: level::SingleLevelManager,
+    rendering: GameRendering,
+}
+
+impl Renderer {
+    /// sets up the device (see `init_wgpu`, which also explains why the caller creates the
+    /// surface) and starts a level filling `size`
+    pub async fn new(
+        instance: &wgpu::Instance,
+        surface: Surface,
+        size: (u32, u32),
+        options: InitOptions,
+    ) -> Result<Self, RendererError> {
+        let (device, queue, surface, config, present_modes) =
+            init_wgpu_with_present_modes(instance, surface, size, options).await?;
+        let size = (config.width, config.height);
+        let (mut level, level_rendering) =
+            level::SingleLevelManager::new(0.7, fastrand::u64(..), &device, &queue, size)?;
+        level.start(&queue);
+        let rendering = GameRendering::new(&device, &level, level_rendering, size, config.format);
+        Ok(Self {
+            device,
+            queue,
+            surface,
+            config,
+            present_modes,
+            level,
+            rendering,
+        })
+    }
+
+    /// reconfigures the surface, the render targets follow on the next `render`
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        self.config.width = width;
+        self.config.height = height;
+        self.surface.configure(&self.device, &self.config);
+        self.level.resize((width, height), ViewLayout::default());
+    }
+
+    /// reconfigures the surface to present with `mode`, e.g. `Fifo` for vsync and `Immediate`
+    /// or `Mailbox` to measure uncapped frame times. falls back to `Fifo` if the surface
+    /// doesn't support `mode` (see `supported_present_mode`), returns the mode applied
+    pub fn set_present_mode(&mut self, mode: wgpu::PresentMode) -> wgpu::PresentMode {
+        let mode = supported_present_mode(mode, &self.present_modes);
+        if mode != self.config.present_mode {
+            self.config.present_mode = mode;
+            self.surface.configure(&self.device, &self.config);
+        }
+        mode
+    }
+
+    pub fn present_mode(&self) -> wgpu::PresentMode {
+        self.config.present_mode
+    }
+
+    /// advances the level by `dt`
+    pub fn update(&mut self, dt: Duration) {
+        self.level.update(dt, &self.queue);
+    }
+
+    /// draws the level into the surface's next texture and presents it
+    ///
+    /// a lost or outdated surface (e.g. after a resize or the display changing) is reconfigured
+    /// and the frame skipped, so only `Timeout` and `OutOfMemory` are returned, the latter
+    /// meaning the app should quit
+    pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
+        let output = match self.surface.get_current_texture() {
+            Ok(output) => output,
+            Err(err @ (wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated)) => {
+                tracing::debug!("{err}, reconfiguring the surface");
+                self.surface.configure(&self.device, &self.config);
+                return Ok(());
+            }
+            Err(err) => return Err(err),
+        };
+        let view = output
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        let frame = self.frame_settings();
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Renderer Encoder"),
+            });
+        self.rendering
+            .prepare(&self.device, &self.queue, &mut encoder, &frame);
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Renderer Pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            self.rendering.render(&mut render_pass, &frame);
+        }
+        self.queue.submit(Some(encoder.finish()));
+        output.present();
+        Ok(())
+    }
+
+    /// creates the render targets with `usage` too, see `REQUIRED_TARGET_USAGES`. the
+    /// surface's usage is picked by `InitOptions::surface_usage`
+    pub fn add_target_usage(&mut self, usage: wgpu::TextureUsages) {
+        self.rendering.add_target_usage(&self.device, usage);
+    }
+
+    pub fn device(&self) -> &Device {
+        &self.device
+    }
+
+    pub fn queue(&self) -> &Queue {
+        &self.queue
+    }
+
+    pub fn camera(&mut self) -> &mut RenderCamera {
+        &mut self.level.camera
+    }
+
+    /// changing the primitive count has to go through `resize_primitives` or
+    /// `replace_primitives`, the bind group `PrimitiveManager::resize` returns is lost otherwise
+    pub fn primitive_manager(&mut self) -> &mut PrimitiveManager {
+        &mut self.level.primitive_manager
+    }
+
+    /// `PrimitiveManager::resize`, drawing from the new buffer from the next `render` on
+    pub fn resize_primitives(&mut self, count: usize) -> Result<(), CapacityError> {
+        let bind_group = self.level.primitive_manager.resize(count, &self.device, &self.queue)?;
+        self.rendering.set_primitives_bind_group(&self.device, bind_group, count);
+        Ok(())
+    }
+
+    /// `PrimitiveManager::replace_all`, resizing like `resize_primitives` if the count differs
+    pub fn replace_primitives(&mut self, primitives: &[SDFPrimitive]) -> Result<(), CapacityError> {
+        let replaced = self
+            .level
+            .primitive_manager
+            .replace_all(primitives, &self.device, &self.queue)?;
+        if let Some(bind_group) = replaced {
+            self.rendering.set_primitives_bind_group(&self.device, bind_group, primitives.len());
+        }
+        Ok(())
+    }
+
+    /// the level's clock, see `SingleLevelManager::elapsed`
+    pub fn elapsed(&self) -> Duration {
+        self.level.elapsed()
+    }
+
+    /// whether the level has ended, `update` keeps going regardless
+    pub fn is_game_over(&self) -> bool {
+        self.level.game_over
+    }
+
+    /// the plain image: no culling, post effects or debug overlays
+    fn frame_settings(&self) -> FrameSettings {
+        FrameSettings {
+            size: (self.config.width, self.config.height),
+            culling_enabled: false,
+            primitive_count: self.level.primitive_manager.len(),
+            reflections: None,
+            motion_blur: None,
+            bloom: None,
+            dither: false,
+            gizmo: None,
+            frustums: [None; MAX_DEBUG_FRUSTUMS],
+            overlay_depth_test: true,
+            views: 1 + self.level.views.len(),
+            view_layout: ViewLayout::default(),
+        }
+    }
+}
+
+/// why `Renderer::new` failed
+#[derive(Debug)]
+pub enum RendererError {
+    Init(InitError),
+    Capacity(CapacityError),
+}
+
+impl fmt::Display for RendererError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Init(err) => write!(f, "{err}"),
+            Self::Capacity(err) => write!(f, "can't start the level: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for RendererError {}
+
+impl From<InitError> for RendererError {
+    fn from(err: InitError) -> Self {
+        Self::Init(err)
+    }
+}
+
+impl From<CapacityError> for RendererError {
+    fn from(err: CapacityError) -> Self {
+        Self::Capacity(err)
+    }
+}
@@ -0,0 +1,134 @@
+use std::time::Duration;
+
+use cgmath::{Point3, Vector3};
+
+use super::camera::{MoveDirection, RenderCamera, ScrollDelta};
+
+/// one call into the camera's input methods, see `InputRecorder`
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub enum InputEvent {
+    /// see `CameraController::process_keyboard`
+    Keyboard { direction: MoveDirection, pressed: bool },
+    /// see `RenderCamera::process_mouse`
+    Mouse { dx: f64, dy: f64 },
+    /// `cursor_hit` is what was under the cursor while recording, so playing it back
+    /// doesn't depend on picking (see `SingleLevelManager::scroll_at`)
+    Scroll { delta: ScrollDelta, cursor_hit: Option<[f32; 3]> },
+    /// see `RenderCamera::process_pan`
+    Pan { dx: f64, dy: f64 },
+    /// dragging the camera, see `SingleLevelManager::move_by`
+    Move { delta: [f32; 3] },
+}
+
+impl InputEvent {
+    pub(crate) fn apply(&self, camera: &mut RenderCamera) {
+        match *self {
+            Self::Keyboard { direction, pressed } => camera.process_keyboard(direction, pressed),
+            Self::Mouse { dx, dy } => camera.process_mouse(dx, dy),
+            Self::Scroll { delta, cursor_hit } => {
+                camera.process_scroll(&delta, cursor_hit.map(Point3::from))
+            }
+            Self::Pan { dx, dy } => camera.process_pan(dx, dy),
+            Self::Move { delta } => camera
+                .controller
+                .move_by(Vector3::from(delta), &mut camera.camera),
+        }
+    }
+}
+
+/// an `InputEvent` and when it happened, in the level's (unscaled) time since recording started
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct TimedInput {
+    pub time: Duration,
+    pub event: InputEvent,
+}
+
+/// a recorded session's input, ordered by time
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct InputRecording {
+    pub events: Vec<TimedInput>,
+}
+
+#[cfg(feature = "serde")]
+impl InputRecording {
+    pub fn load<P: AsRef<std::path::Path>>(path: P) -> Result<Self, super::SettingsError> {
+        let text = std::fs::read_to_string(path)?;
+        Ok(toml::from_str(&text)?)
+    }
+
+    pub fn save<P: AsRef<std::path::Path>>(&self, path: P) -> Result<(), super::SettingsError> {
+        std::fs::write(path, toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// timestamps the camera input as it is processed, see `State::start_input_recording`
+///
+/// the time is the sum of the frame times the level was updated with, not the wall clock,
+/// so with a fixed timestep (see `State::set_fixed_timestep`) and the same seed the playback
+/// lands on the same frames
+#[derive(Debug, Clone, Default)]
+pub struct InputRecorder {
+    recording: InputRecording,
+    elapsed: Duration,
+}
+
+impl InputRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, event: InputEvent) {
+        self.recording.events.push(TimedInput {
+            time: self.elapsed,
+            event,
+        });
+    }
+
+    /// once per update of the level, by the frame time it's updated with
+    pub fn advance(&mut self, dt: Duration) {
+        self.elapsed += dt;
+    }
+
+    pub fn finish(self) -> InputRecording {
+        self.recording
+    }
+}
+
+/// feeds an `InputRecording` back into the camera at the recorded times, see `State::play_input`
+#[derive(Debug, Clone)]
+pub struct InputPlayer {
+    recording: InputRecording,
+    /// the first event not played yet
+    next: usize,
+    elapsed: Duration,
+}
+
+impl InputPlayer {
+    pub fn new(recording: InputRecording) -> Self {
+        Self {
+            recording,
+            next: 0,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    /// the events recorded before the update that takes `dt`, then advances by it
+    pub fn advance(&mut self, dt: Duration) -> &[TimedInput] {
+        let start = self.next;
+        let events = &self.recording.events;
+        while self.next < events.len() && events[self.next].time <= self.elapsed {
+            self.next += 1;
+        }
+        self.elapsed += dt;
+        &self.recording.events[start..self.next]
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.recording.events.len()
+    }
+}
@@ -0,0 +1,99 @@
+use std::fmt;
+use std::path::Path;
+
+use super::capabilities::CapacityError;
+#[cfg(feature = "expressions")]
+use super::expressions::{ExpressionError, PrimitiveExpression};
+use super::primitives::{PrimitiveManager, SDFPrimitive};
+
+/// a level's primitives as a toml file, one `[[primitives]]` table per primitive with the
+/// fields of `SDFPrimitive`. missing fields keep the values of `SDFPrimitive::new`, the editor
+/// flags and the lod aren't stored
+///
+/// with the `expressions` feature, `[[expressions]]` tables animate them, e.g.
+/// `primitive = 3` and `expression = "position.y = sin(t + phase)"`, see `PrimitiveExpression`
+#[derive(Debug, Clone, Default, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct Scene {
+    pub primitives: Vec<SDFPrimitive>,
+    #[cfg(feature = "expressions")]
+    pub expressions: Vec<PrimitiveExpression>,
+}
+
+impl Scene {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, SceneError> {
+        let text = std::fs::read_to_string(path)?;
+        Ok(toml::from_str(&text)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SceneError> {
+        std::fs::write(path, toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// the primitives as they are now
+    pub fn capture(primitive_manager: &PrimitiveManager) -> Self {
+        Self {
+            primitives: primitive_manager.iter().copied().collect(),
+            #[cfg(feature = "expressions")]
+            expressions: primitive_manager.expressions().sources().cloned().collect(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(std::io::Error),
+    Parse(toml::de::Error),
+    Write(toml::ser::Error),
+    /// more primitives than the device can hold
+    Capacity(CapacityError),
+    #[cfg(feature = "expressions")]
+    Expression(ExpressionError),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "can't access the scene: {err}"),
+            Self::Parse(err) => write!(f, "invalid scene: {err}"),
+            Self::Write(err) => write!(f, "can't serialize the scene: {err}"),
+            Self::Capacity(err) => write!(f, "can't load the scene: {err}"),
+            #[cfg(feature = "expressions")]
+            Self::Expression(err) => write!(f, "can't load the scene: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+impl From<std::io::Error> for SceneError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<toml::de::Error> for SceneError {
+    fn from(err: toml::de::Error) -> Self {
+        Self::Parse(err)
+    }
+}
+
+impl From<toml::ser::Error> for SceneError {
+    fn from(err: toml::ser::Error) -> Self {
+        Self::Write(err)
+    }
+}
+
+impl From<CapacityError> for SceneError {
+    fn from(err: CapacityError) -> Self {
+        Self::Capacity(err)
+    }
+}
+
+#[cfg(feature = "expressions")]
+impl From<ExpressionError> for SceneError {
+    fn from(err: ExpressionError) -> Self {
+        Self::Expression(err)
+    }
+}
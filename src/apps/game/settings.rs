@@ -0,0 +1,196 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use super::{
+    BloomSettings, ContourSettings, DebugMode, EdgeAA, LodSettings, MotionBlurSettings,
+    ReflectionSettings, ViewLayout,
+};
+#[cfg(all(feature = "serde", not(target_arch = "wasm32")))]
+use super::State;
+
+/// all of `State`'s renderer settings in one place, to be saved to and loaded from a toml file
+///
+/// missing fields fall back to `State`'s defaults, so files written by older versions keep
+/// loading. the present mode belongs to eframe and is only picked at startup, see
+/// `supported_present_mode`. what isn't rendering (input, the camera, the scene) isn't saved
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct RendererSettings {
+    pub culling: bool,
+    pub reflections: Option<ReflectionSettings>,
+    pub motion_blur: Option<MotionBlurSettings>,
+    pub bloom: Option<BloomSettings>,
+    pub dither: bool,
+    /// equirectangular background image, `None` is the plain clear color
+    pub environment: Option<PathBuf>,
+    pub lod: Option<LodSettings>,
+    pub fixed_timestep: Option<Duration>,
+    pub fixed_timestep_camera: bool,
+    /// see `State::set_render_scale`
+    pub render_scale: f32,
+    /// the adaptive step count's target, see `State::set_target_frame_time`
+    pub target_frame_time: Option<Duration>,
+    pub complexity_budget: Option<u32>,
+    pub edge_aa: EdgeAA,
+    pub contour: ContourSettings,
+    pub debug_mode: DebugMode,
+    pub gizmo: bool,
+    pub frustums: bool,
+    pub overlay_depth_test: bool,
+    pub view_layout: ViewLayout,
+}
+
+impl Default for RendererSettings {
+    fn default() -> Self {
+        Self {
+            culling: false,
+            reflections: None,
+            motion_blur: None,
+            bloom: None,
+            dither: false,
+            environment: None,
+            lod: None,
+            fixed_timestep: None,
+            fixed_timestep_camera: false,
+            render_scale: 1.0,
+            target_frame_time: None,
+            complexity_budget: None,
+            edge_aa: EdgeAA::default(),
+            contour: ContourSettings::default(),
+            debug_mode: DebugMode::default(),
+            gizmo: true,
+            frustums: false,
+            overlay_depth_test: true,
+            view_layout: ViewLayout::default(),
+        }
+    }
+}
+
+// only `State::load_settings` and `save_settings` use these
+#[cfg(all(feature = "serde", not(target_arch = "wasm32")))]
+impl RendererSettings {
+    /// where `State` loads the settings from on startup and saves them to
+    pub const PATH: &'static str = "renderer_settings.toml";
+
+    pub(crate) fn capture(state: &State) -> Self {
+        let defaults = Self::default();
+        let (lod, fixed_timestep, fixed_timestep_camera, complexity_budget, edge_aa, contour) =
+            match state.level() {
+                Some(level) => (
+                    level.primitive_manager.lod().cloned(),
+                    level.fixed_timestep(),
+                    level.fixed_timestep_camera,
+                    level.primitive_manager.complexity_budget(),
+                    level.camera.uniform.edge_aa,
+                    level.camera.uniform.contour,
+                ),
+                None => (None, None, false, None, defaults.edge_aa, defaults.contour),
+            };
+        Self {
+            culling: state.culling_enabled,
+            reflections: state.reflections,
+            motion_blur: state.motion_blur,
+            bloom: state.bloom,
+            dither: state.dither,
+            environment: state.environment.clone(),
+            lod,
+            fixed_timestep,
+            fixed_timestep_camera,
+            render_scale: state.render_scale,
+            target_frame_time: state.adaptive_steps.as_ref().map(|steps| steps.target),
+            complexity_budget,
+            edge_aa,
+            contour,
+            debug_mode: state.debug_mode,
+            gizmo: state.gizmo,
+            frustums: state.frustums,
+            overlay_depth_test: state.overlay_depth_test,
+            view_layout: state.view_layout,
+        }
+    }
+
+    /// everything but the environment is applied even if its image can't be loaded
+    pub(crate) fn apply(&self, state: &mut State) -> Result<(), image::ImageError> {
+        state.enable_culling(self.culling);
+        state.set_reflections(self.reflections);
+        state.set_motion_blur(self.motion_blur);
+        state.set_bloom(self.bloom);
+        state.set_dither(self.dither);
+        state.set_lod(self.lod.clone());
+        state.set_fixed_timestep(self.fixed_timestep, self.fixed_timestep_camera);
+        state.set_render_scale(self.render_scale);
+        state.set_target_frame_time(self.target_frame_time);
+        state.set_complexity_budget(self.complexity_budget);
+        state.set_edge_aa(self.edge_aa);
+        state.set_contour(self.contour);
+        state.set_debug_mode(self.debug_mode);
+        state.show_gizmo(self.gizmo);
+        state.show_frustums(self.frustums);
+        state.set_overlay_depth_test(self.overlay_depth_test);
+        state.set_view_layout(self.view_layout);
+        match &self.environment {
+            Some(path) => state.set_environment(path),
+            None => {
+                state.clear_environment();
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl RendererSettings {
+    pub fn load<P: AsRef<std::path::Path>>(path: P) -> Result<Self, SettingsError> {
+        let text = std::fs::read_to_string(path)?;
+        Ok(toml::from_str(&text)?)
+    }
+
+    pub fn save<P: AsRef<std::path::Path>>(&self, path: P) -> Result<(), SettingsError> {
+        std::fs::write(path, toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "serde")]
+#[derive(Debug)]
+pub enum SettingsError {
+    Io(std::io::Error),
+    Parse(toml::de::Error),
+    Write(toml::ser::Error),
+}
+
+#[cfg(feature = "serde")]
+impl std::fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "can't access the renderer settings: {err}"),
+            Self::Parse(err) => write!(f, "invalid renderer settings: {err}"),
+            Self::Write(err) => write!(f, "can't serialize the renderer settings: {err}"),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl std::error::Error for SettingsError {}
+
+#[cfg(feature = "serde")]
+impl From<std::io::Error> for SettingsError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<toml::de::Error> for SettingsError {
+    fn from(err: toml::de::Error) -> Self {
+        Self::Parse(err)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<toml::ser::Error> for SettingsError {
+    fn from(err: toml::ser::Error) -> Self {
+        Self::Write(err)
+    }
+}
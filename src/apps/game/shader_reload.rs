@@ -0,0 +1,40 @@
+use std::future::Future;
+use std::path::PathBuf;
+use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+/// where the wgsl files are, as in the source tree this was built from
+pub const SHADER_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/apps/game/level");
+
+/// what the marcher's shader is concatenated from, in order (see `SingleLevelManager::new`)
+pub const LEVEL_SHADER_FILES: [&str; 3] = ["common.wgsl", "environment.wgsl", "main_shader.wgsl"];
+
+pub(crate) fn level_shader_paths() -> Vec<PathBuf> {
+    LEVEL_SHADER_FILES.iter().map(|file| PathBuf::from(SHADER_DIR).join(file)).collect()
+}
+
+/// the marcher's shader as it is on disk now
+pub(crate) fn read_level_shader() -> std::io::Result<String> {
+    let mut source = String::new();
+    for path in level_shader_paths() {
+        source.push_str(&std::fs::read_to_string(path)?);
+    }
+    Ok(source)
+}
+
+/// polls `future` once, native wgpu resolves `Device::pop_error_scope` right away
+pub(crate) fn now_or_never<F: Future>(future: F) -> Option<F::Output> {
+    fn noop_raw_waker() -> RawWaker {
+        fn clone(_: *const ()) -> RawWaker {
+            noop_raw_waker()
+        }
+        fn noop(_: *const ()) {}
+        static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+        RawWaker::new(std::ptr::null(), &VTABLE)
+    }
+    // SAFETY: the waker's functions don't touch the (null) data pointer
+    let waker = unsafe { Waker::from_raw(noop_raw_waker()) };
+    match Box::pin(future).as_mut().poll(&mut Context::from_waker(&waker)) {
+        Poll::Ready(output) => Some(output),
+        Poll::Pending => None,
+    }
+}
@@ -0,0 +1,191 @@
+use std::fmt;
+
+use super::memory::texture_bytes;
+use super::wgpu::{self, Device, Queue, Sampler, Texture, TextureView};
+
+/// how many textures the atlas holds, one layer each
+pub const MAX_TEXTURES: u32 = 16;
+/// the width and height of every layer, images are resized to fit
+pub const TEXTURE_SIZE: u32 = 256;
+/// `SDFPrimitive::texture_index` of a primitive using its plain `rgba`
+pub const NO_TEXTURE: u32 = u32::MAX;
+
+const TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+/// images the primitives can reference by index, projected onto them along their local axes
+/// (triplanar, so no uvs are needed), see `SDFPrimitive::set_texture`
+///
+/// bound next to the primitives (bindings 6 and 7 of the primitives' group, `textures` in
+/// main_shader.wgsl) as all four bind groups are taken. a layer that was never set is
+/// transparent, which leaves the primitives using it at their `rgba`
+pub struct TextureAtlas {
+    texture: Texture,
+    view: TextureView,
+    sampler: Sampler,
+    /// which layers were set
+    layers: [bool; MAX_TEXTURES as usize],
+}
+
+impl TextureAtlas {
+    pub fn new(device: &Device) -> Self {
+        // zeroed, so every layer is transparent
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Primitive Texture Atlas"),
+            size: wgpu::Extent3d {
+                width: TEXTURE_SIZE,
+                height: TEXTURE_SIZE,
+                depth_or_array_layers: MAX_TEXTURES,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: TEXTURE_FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Primitive Texture Sampler"),
+            // the projection tiles the texture
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        Self {
+            texture,
+            view,
+            sampler,
+            layers: [false; MAX_TEXTURES as usize],
+        }
+    }
+
+    /// the layers, in bytes, all `MAX_TEXTURES` of them exist from the start
+    pub fn memory_usage(&self) -> u64 {
+        texture_bytes((TEXTURE_SIZE, TEXTURE_SIZE), TEXTURE_FORMAT) * MAX_TEXTURES as u64
+    }
+
+    /// whether `index` holds an image
+    pub fn is_set(&self, index: u32) -> bool {
+        self.layers.get(index as usize).copied().unwrap_or(false)
+    }
+
+    /// writes `image` into layer `index`, resized to `TEXTURE_SIZE` squared if it isn't.
+    /// its alpha blends between the primitive's `rgba` and the textured color
+    pub fn set_texture(
+        &mut self,
+        index: u32,
+        image: &image::RgbaImage,
+        queue: &Queue,
+    ) -> Result<(), TextureError> {
+        if index >= MAX_TEXTURES {
+            return Err(TextureError::OutOfRange { index });
+        }
+        let resized;
+        let image = if image.dimensions() == (TEXTURE_SIZE, TEXTURE_SIZE) {
+            image
+        } else {
+            resized = image::imageops::resize(
+                image,
+                TEXTURE_SIZE,
+                TEXTURE_SIZE,
+                image::imageops::FilterType::Triangle,
+            );
+            &resized
+        };
+        self.write_layer(index, image.as_raw(), queue);
+        self.layers[index as usize] = true;
+        Ok(())
+    }
+
+    /// loads an image file (`.png`, `.jpg`, ...) into layer `index`, see `set_texture`
+    pub fn load<P: AsRef<std::path::Path>>(
+        &mut self,
+        index: u32,
+        path: P,
+        queue: &Queue,
+    ) -> Result<(), TextureError> {
+        let image = image::open(path)?.into_rgba8();
+        self.set_texture(index, &image, queue)
+    }
+
+    /// makes layer `index` transparent again, out of range indices are ignored
+    pub fn clear_texture(&mut self, index: u32, queue: &Queue) {
+        if index < MAX_TEXTURES {
+            let transparent = vec![0; (TEXTURE_SIZE * TEXTURE_SIZE * 4) as usize];
+            self.write_layer(index, &transparent, queue);
+            self.layers[index as usize] = false;
+        }
+    }
+
+    fn write_layer(&self, index: u32, rgba: &[u8], queue: &Queue) {
+        crate::trace_upload!("textures", index as usize * rgba.len(), rgba.len());
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d {
+                    x: 0,
+                    y: 0,
+                    z: index,
+                },
+                aspect: wgpu::TextureAspect::All,
+            },
+            rgba,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(TEXTURE_SIZE * 4),
+                rows_per_image: std::num::NonZeroU32::new(TEXTURE_SIZE),
+            },
+            wgpu::Extent3d {
+                width: TEXTURE_SIZE,
+                height: TEXTURE_SIZE,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
+    pub(crate) fn bind_group_entries(&self) -> [wgpu::BindGroupEntry<'_>; 2] {
+        [
+            wgpu::BindGroupEntry {
+                binding: 6,
+                resource: wgpu::BindingResource::TextureView(&self.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 7,
+                resource: wgpu::BindingResource::Sampler(&self.sampler),
+            },
+        ]
+    }
+}
+
+#[derive(Debug)]
+pub enum TextureError {
+    /// past `MAX_TEXTURES`
+    OutOfRange { index: u32 },
+    Image(image::ImageError),
+}
+
+impl fmt::Display for TextureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OutOfRange { index } => write!(
+                f,
+                "texture {index} was requested but the atlas only holds {MAX_TEXTURES}"
+            ),
+            Self::Image(err) => write!(f, "can't load the texture: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for TextureError {}
+
+impl From<image::ImageError> for TextureError {
+    fn from(err: image::ImageError) -> Self {
+        Self::Image(err)
+    }
+}
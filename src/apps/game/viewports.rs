@@ -0,0 +1,58 @@
+/// a camera's part of the render targets in physical pixels, see `Projection::set_viewport`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ViewRect {
+    pub origin: (u32, u32),
+    pub size: (u32, u32),
+}
+
+impl ViewRect {
+    pub fn full(size: (u32, u32)) -> Self {
+        Self {
+            origin: (0, 0),
+            size,
+        }
+    }
+}
+
+/// how several cameras share the render targets, see `State::add_view`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+// toml can't write `Grid`'s fields as an externally tagged variant
+#[cfg_attr(feature = "serde", serde(tag = "kind"))]
+pub enum ViewLayout {
+    /// next to each other, left to right
+    #[default]
+    SideBySide,
+    /// on top of each other, top to bottom
+    Stacked,
+    /// row by row, the last row may be incomplete
+    Grid { columns: u32 },
+}
+
+impl ViewLayout {
+    /// the rect of each of the `count` views in a target of `size`, rounding leftovers go to
+    /// the last column and row so the rects always cover the whole target
+    pub fn rects(&self, count: usize, size: (u32, u32)) -> Vec<ViewRect> {
+        let count = count.max(1) as u32;
+        let columns = match *self {
+            ViewLayout::SideBySide => count,
+            ViewLayout::Stacked => 1,
+            ViewLayout::Grid { columns } => columns.clamp(1, count),
+        };
+        let rows = (count + columns - 1) / columns;
+        let split = |extent: u32, parts: u32, i: u32| {
+            let start = extent * i / parts;
+            (start, extent * (i + 1) / parts - start)
+        };
+        (0..count)
+            .map(|i| {
+                let (x, width) = split(size.0, columns, i % columns);
+                let (y, height) = split(size.1, rows, i / columns);
+                ViewRect {
+                    origin: (x, y),
+                    size: (width.max(1), height.max(1)),
+                }
+            })
+            .collect()
+    }
+}
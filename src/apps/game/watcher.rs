@@ -0,0 +1,80 @@
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use notify::Watcher;
+
+/// how long the files have to stay unchanged before they're reloaded, editors often
+/// write a file in several steps
+const RELOAD_DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// notices changes to a few files, see `State::watch_scene` and `State::watch_shaders`
+pub(crate) struct FileWatcher {
+    paths: Vec<PathBuf>,
+    /// stops watching when dropped
+    _watcher: notify::RecommendedWatcher,
+    changes: mpsc::Receiver<()>,
+    /// when a file last changed, until they're reloaded
+    changed: Option<Instant>,
+}
+
+impl FileWatcher {
+    pub fn new(paths: &[&Path]) -> notify::Result<Self> {
+        let file_names: Vec<OsString> =
+            paths.iter().filter_map(|path| path.file_name()).map(OsStr::to_owned).collect();
+        let is_watched = move |path: &PathBuf| {
+            matches!(path.file_name(), Some(name) if file_names.iter().any(|file_name| file_name == name))
+        };
+        let (sender, changes) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            match event {
+                Ok(event)
+                    if (event.kind.is_create() || event.kind.is_modify())
+                        && event.paths.iter().any(&is_watched) =>
+                {
+                    let _ = sender.send(());
+                }
+                Ok(_) => {}
+                Err(err) => tracing::warn!("can't watch for changes: {err}"),
+            }
+        })?;
+        // editors often replace the file instead of writing to it, which ends a watch
+        // on the file itself, so its directory is watched
+        let mut directories: Vec<&Path> = paths
+            .iter()
+            .map(|path| match path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => Path::new("."),
+            })
+            .collect();
+        directories.dedup();
+        for directory in directories {
+            watcher.watch(directory, notify::RecursiveMode::NonRecursive)?;
+        }
+        Ok(Self {
+            paths: paths.iter().map(|path| path.to_path_buf()).collect(),
+            _watcher: watcher,
+            changes,
+            changed: None,
+        })
+    }
+
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    /// whether a file changed and has settled since, call once per frame
+    pub fn poll(&mut self) -> bool {
+        if self.changes.try_iter().count() > 0 {
+            self.changed = Some(Instant::now());
+        }
+        match self.changed {
+            Some(changed) if changed.elapsed() >= RELOAD_DEBOUNCE => {
+                self.changed = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
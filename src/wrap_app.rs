@@ -90,6 +90,10 @@ impl eframe::App for WrapApp {
     #[cfg(feature = "persistence")]
     fn save(&mut self, storage: &mut dyn eframe::Storage) {
         eframe::set_value(storage, eframe::APP_KEY, &self.state);
+        #[cfg(all(feature = "wgpu", not(target_arch = "wasm32")))]
+        if let Some(game) = &self.game {
+            game.save_settings();
+        }
     }
 
     fn clear_color(&self, visuals: &egui::Visuals) -> [f32; 4] {